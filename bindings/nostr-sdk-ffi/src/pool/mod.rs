@@ -265,7 +265,7 @@ impl RelayPool {
                     .into_iter()
                     .map(|f| f.as_ref().deref().clone())
                     .collect(),
-                **opts,
+                opts.deref().clone(),
             )
             .await?
             .into())
@@ -292,7 +292,7 @@ impl RelayPool {
                     .into_iter()
                     .map(|f| f.as_ref().deref().clone())
                     .collect(),
-                **opts,
+                opts.deref().clone(),
             )
             .await?
             .into())
@@ -313,7 +313,11 @@ impl RelayPool {
             .into_iter()
             .map(|f| f.as_ref().deref().clone())
             .collect();
-        Ok(self.inner.subscribe_to(urls, filters, **opts).await?.into())
+        Ok(self
+            .inner
+            .subscribe_to(urls, filters, opts.deref().clone())
+            .await?
+            .into())
     }
 
     /// Subscribe to filters with custom subscription ID to specific relays
@@ -334,7 +338,7 @@ impl RelayPool {
             .collect();
         Ok(self
             .inner
-            .subscribe_with_id_to(urls, SubscriptionId::new(id), filters, **opts)
+            .subscribe_with_id_to(urls, SubscriptionId::new(id), filters, opts.deref().clone())
             .await?
             .into())
     }
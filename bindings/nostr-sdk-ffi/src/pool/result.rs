@@ -33,6 +33,55 @@ impl From<pool::Output<()>> for Output {
     }
 }
 
+impl From<pool::Output<pool::Reconciliation>> for Output {
+    fn from(output: pool::Output<pool::Reconciliation>) -> Self {
+        Self {
+            success: output.success.into_iter().map(|u| u.to_string()).collect(),
+            failed: output
+                .failed
+                .into_iter()
+                .map(|(u, e)| (u.to_string(), e))
+                .collect(),
+        }
+    }
+}
+
+/// Reconciliation output
+#[derive(Record)]
+pub struct Reconciliation {
+    /// Events sent to the relays
+    pub sent: Vec<Arc<EventId>>,
+    /// Events received from the relays
+    pub received: Vec<Arc<EventId>>,
+    /// Events that the relays have but are missing locally
+    pub local_missing: Vec<Arc<EventId>>,
+    /// Events that are stored locally but are missing on the relays
+    pub remote_missing: Vec<Arc<EventId>>,
+}
+
+impl From<pool::Reconciliation> for Reconciliation {
+    fn from(value: pool::Reconciliation) -> Self {
+        Self {
+            sent: value.sent.into_iter().map(|e| Arc::new(e.into())).collect(),
+            received: value
+                .received
+                .into_iter()
+                .map(|e| Arc::new(e.into()))
+                .collect(),
+            local_missing: value
+                .local_missing
+                .into_iter()
+                .map(|e| Arc::new(e.into()))
+                .collect(),
+            remote_missing: value
+                .remote_missing
+                .into_iter()
+                .map(|e| Arc::new(e.into()))
+                .collect(),
+        }
+    }
+}
+
 /// Send event output
 #[derive(Record)]
 pub struct SendEventOutput {
@@ -26,6 +26,7 @@ pub use self::stats::RelayConnectionStats;
 pub use self::status::RelayStatus;
 use crate::error::Result;
 use crate::negentropy::NegentropyItem;
+use crate::pool::result::Reconciliation;
 use crate::NostrDatabase;
 
 #[derive(Object)]
@@ -228,7 +229,7 @@ impl Relay {
                     .into_iter()
                     .map(|f| f.as_ref().deref().clone())
                     .collect(),
-                **opts,
+                opts.deref().clone(),
             )
             .await?
             .to_string())
@@ -255,7 +256,7 @@ impl Relay {
                     .into_iter()
                     .map(|f| f.as_ref().deref().clone())
                     .collect(),
-                **opts,
+                opts.deref().clone(),
             )
             .await?)
     }
@@ -310,8 +311,16 @@ impl Relay {
     /// Negentropy reconciliation
     ///
     /// Use events stored in database
-    pub async fn reconcile(&self, filter: &Filter, opts: &NegentropyOptions) -> Result<()> {
-        Ok(self.inner.reconcile(filter.deref().clone(), **opts).await?)
+    pub async fn reconcile(
+        &self,
+        filter: &Filter,
+        opts: &NegentropyOptions,
+    ) -> Result<Reconciliation> {
+        Ok(self
+            .inner
+            .reconcile(filter.deref().clone(), **opts)
+            .await?
+            .into())
     }
 
     /// Negentropy reconciliation with custom items
@@ -320,7 +329,7 @@ impl Relay {
         filter: &Filter,
         items: Vec<NegentropyItem>,
         opts: &NegentropyOptions,
-    ) -> Result<()> {
+    ) -> Result<Reconciliation> {
         let items = items
             .into_iter()
             .map(|item| (**item.id, **item.timestamp))
@@ -328,7 +337,8 @@ impl Relay {
         Ok(self
             .inner
             .reconcile_with_items(filter.deref().clone(), items, **opts)
-            .await?)
+            .await?
+            .into())
     }
 
     /// Check if relay support negentropy protocol
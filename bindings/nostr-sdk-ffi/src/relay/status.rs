@@ -16,6 +16,8 @@ pub enum RelayStatus {
     Connected,
     /// Relay disconnected, will retry to connect again
     Disconnected,
+    /// Relay disconnected for inactivity, will reconnect on the next message
+    Idle,
     /// Relay completely disconnected
     Terminated,
 }
@@ -28,6 +30,7 @@ impl From<nostr_sdk::RelayStatus> for RelayStatus {
             nostr_sdk::RelayStatus::Connecting => Self::Connecting,
             nostr_sdk::RelayStatus::Connected => Self::Connected,
             nostr_sdk::RelayStatus::Disconnected => Self::Disconnected,
+            nostr_sdk::RelayStatus::Idle => Self::Idle,
             nostr_sdk::RelayStatus::Terminated => Self::Terminated,
         }
     }
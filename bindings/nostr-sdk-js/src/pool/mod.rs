@@ -299,7 +299,7 @@ impl JsRelayPool {
         self.inner
             .subscribe(
                 filters.into_iter().map(|f| f.deref().clone()).collect(),
-                **opts,
+                opts.deref().clone(),
             )
             .await
             .map_err(into_err)
@@ -324,7 +324,7 @@ impl JsRelayPool {
             .subscribe_with_id(
                 SubscriptionId::new(id),
                 filters.into_iter().map(|f| f.deref().clone()).collect(),
-                **opts,
+                opts.deref().clone(),
             )
             .await
             .map_err(into_err)
@@ -345,7 +345,7 @@ impl JsRelayPool {
     ) -> Result<JsSubscribeOutput> {
         let filters = filters.into_iter().map(|f| f.deref().clone()).collect();
         self.inner
-            .subscribe_to(urls, filters, **opts)
+            .subscribe_to(urls, filters, opts.deref().clone())
             .await
             .map_err(into_err)
             .map(|o| o.into())
@@ -366,7 +366,7 @@ impl JsRelayPool {
     ) -> Result<JsOutput> {
         let filters = filters.into_iter().map(|f| f.deref().clone()).collect();
         self.inner
-            .subscribe_with_id_to(urls, SubscriptionId::new(id), filters, **opts)
+            .subscribe_with_id_to(urls, SubscriptionId::new(id), filters, opts.deref().clone())
             .await
             .map_err(into_err)
             .map(|o| o.into())
@@ -45,6 +45,69 @@ impl From<Output<()>> for JsOutput {
     }
 }
 
+impl From<Output<Reconciliation>> for JsOutput {
+    fn from(value: Output<Reconciliation>) -> Self {
+        Self {
+            success: value.success.into_iter().map(|u| u.to_string()).collect(),
+            failed: value
+                .failed
+                .into_iter()
+                .map(|(u, e)| JsFailedOutputItem {
+                    url: u.to_string(),
+                    error: e,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Reconciliation output
+#[wasm_bindgen(js_name = Reconciliation)]
+pub struct JsReconciliation {
+    inner: Reconciliation,
+}
+
+impl From<Reconciliation> for JsReconciliation {
+    fn from(inner: Reconciliation) -> Self {
+        Self { inner }
+    }
+}
+
+#[wasm_bindgen(js_class = Reconciliation)]
+impl JsReconciliation {
+    /// Events sent to the relays
+    #[wasm_bindgen(getter)]
+    pub fn sent(&self) -> Vec<JsEventId> {
+        self.inner.sent.iter().map(|e| (*e).into()).collect()
+    }
+
+    /// Events received from the relays
+    #[wasm_bindgen(getter)]
+    pub fn received(&self) -> Vec<JsEventId> {
+        self.inner.received.iter().map(|e| (*e).into()).collect()
+    }
+
+    /// Events that the relays have but are missing locally
+    #[wasm_bindgen(getter, js_name = localMissing)]
+    pub fn local_missing(&self) -> Vec<JsEventId> {
+        self.inner
+            .local_missing
+            .iter()
+            .map(|e| (*e).into())
+            .collect()
+    }
+
+    /// Events that are stored locally but are missing on the relays
+    #[wasm_bindgen(getter, js_name = remoteMissing)]
+    pub fn remote_missing(&self) -> Vec<JsEventId> {
+        self.inner
+            .remote_missing
+            .iter()
+            .map(|e| (*e).into())
+            .collect()
+    }
+}
+
 /// Send event output
 #[wasm_bindgen(js_name = SendEventOutput)]
 pub struct JsSendEventOutput {
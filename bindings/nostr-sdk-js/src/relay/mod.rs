@@ -23,6 +23,7 @@ use self::options::{
     JsFilterOptions, JsNegentropyOptions, JsRelayOptions, JsRelaySendOptions, JsSubscribeOptions,
 };
 use crate::duration::JsDuration;
+use crate::pool::result::JsReconciliation;
 
 #[wasm_bindgen]
 extern "C" {
@@ -54,6 +55,8 @@ pub enum JsRelayStatus {
     Connected,
     /// Relay disconnected, will retry to connect again
     Disconnected,
+    /// Relay disconnected for inactivity, will reconnect on the next message
+    Idle,
     /// Relay completely disconnected
     Terminated,
 }
@@ -66,6 +69,7 @@ impl From<RelayStatus> for JsRelayStatus {
             RelayStatus::Connecting => Self::Connecting,
             RelayStatus::Connected => Self::Connected,
             RelayStatus::Disconnected => Self::Disconnected,
+            RelayStatus::Idle => Self::Idle,
             RelayStatus::Terminated => Self::Terminated,
         }
     }
@@ -193,7 +197,7 @@ impl JsRelay {
         let filters: Vec<Filter> = filters.into_iter().map(|f| f.into()).collect();
         Ok(self
             .inner
-            .subscribe(filters, **opts) // TODO: allow to pass opts as reference
+            .subscribe(filters, opts.deref().clone()) // TODO: allow to pass opts as reference
             .await
             .map_err(into_err)?
             .to_string())
@@ -213,7 +217,7 @@ impl JsRelay {
     ) -> Result<()> {
         let filters: Vec<Filter> = filters.into_iter().map(|f| f.into()).collect();
         self.inner
-            .subscribe_with_id(SubscriptionId::new(id), filters, **opts) // TODO: allow to pass opts as reference
+            .subscribe_with_id(SubscriptionId::new(id), filters, opts.deref().clone()) // TODO: allow to pass opts as reference
             .await
             .map_err(into_err)
     }
@@ -278,10 +282,15 @@ impl JsRelay {
     /// Use events stored in database
     ///
     /// <https://github.com/hoytech/negentropy>
-    pub async fn reconcile(&self, filter: &JsFilter, opts: &JsNegentropyOptions) -> Result<()> {
+    pub async fn reconcile(
+        &self,
+        filter: &JsFilter,
+        opts: &JsNegentropyOptions,
+    ) -> Result<JsReconciliation> {
         self.inner
             .reconcile(filter.deref().clone(), **opts)
             .await
             .map_err(into_err)
+            .map(|r| r.into())
     }
 }
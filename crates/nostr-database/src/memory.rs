@@ -290,3 +290,37 @@ where
         None => LruCache::unbounded(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_event_seen_on_relays() {
+        let database = MemoryDatabase::new();
+        let event_id =
+            EventId::from_hex("70b10f70c1318967eddf12527799411b1a9780ad9c43858f5e5fcd45486a13a5")
+                .unwrap();
+        let damus = Url::parse("wss://relay.damus.io").unwrap();
+        let nos = Url::parse("wss://nos.lol").unwrap();
+
+        assert_eq!(database.event_seen_on_relays(event_id).await.unwrap(), None);
+
+        // Same event from two relays (and a duplicate)
+        database
+            .event_id_seen(event_id, damus.clone())
+            .await
+            .unwrap();
+        database.event_id_seen(event_id, nos.clone()).await.unwrap();
+        database.event_id_seen(event_id, nos.clone()).await.unwrap();
+
+        let sources = database
+            .event_seen_on_relays(event_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(sources.len(), 2);
+        assert!(sources.contains(&damus));
+        assert!(sources.contains(&nos));
+    }
+}
@@ -10,18 +10,29 @@
 #![allow(unknown_lints)] // TODO: remove when MSRV >= 1.72.0, required for `clippy::arc_with_non_send_sync`
 #![allow(clippy::arc_with_non_send_sync)]
 
+#[cfg(all(any(test, feature = "test-utils"), not(target_arch = "wasm32")))]
+pub mod mock;
 pub mod pool;
 pub mod prelude;
 pub mod relay;
 mod util;
 
-pub use self::pool::options::RelayPoolOptions;
-pub use self::pool::{Output, RelayPool, RelayPoolNotification};
+pub use self::pool::options::{
+    AddRelayBehavior, EventOrder, EventOverflowPolicy, InvalidSigPolicy, RelayMatcher, RelayPolicy,
+    RelayPoolOptions, RelayScoreWeights,
+};
+pub use self::pool::{
+    Output, PublishOutput, ReconcileItem, Reconciliation, RelayPool, RelayPoolNotification,
+    RelayStatusReceiver, SubscriptionHandle, SubscriptionStream,
+};
 pub use self::relay::flags::{AtomicRelayServiceFlags, RelayServiceFlags};
 pub use self::relay::limits::RelayLimits;
 pub use self::relay::options::{
-    FilterOptions, NegentropyDirection, NegentropyOptions, RelayOptions, RelaySendOptions,
-    SubscribeAutoCloseOptions, SubscribeOptions,
+    FilterOptions, NegentropyDirection, NegentropyOptions, PingStrategy, RelayOptions,
+    RelaySendOptions, SubscribeAutoCloseOptions, SubscribeOptions,
+};
+pub use self::relay::stats::{RelayConnectionStats, RelayStats, RetryState};
+pub use self::relay::{
+    ConnectionEvent, EventValidator, MessageDirection, MessageTracer, Relay, RelayBlacklist,
+    RelayNotification, RelayStatus, SyncHandle,
 };
-pub use self::relay::stats::RelayConnectionStats;
-pub use self::relay::{Relay, RelayBlacklist, RelayNotification, RelayStatus};
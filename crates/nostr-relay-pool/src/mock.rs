@@ -0,0 +1,572 @@
+// Copyright (c) 2022-2023 Yuki Kishimoto
+// Copyright (c) 2023-2024 Rust Nostr Developers
+// Distributed under the MIT software license
+
+//! Mock relay
+//!
+//! In-process relay listening on `127.0.0.1`, that stores the events and records the received messages.
+//! It replies to `EVENT`, `REQ`, `CLOSE`, `COUNT` and negentropy messages,
+//! so [`RelayPool`](crate::RelayPool) and `Client` can be tested against a real WebSocket connection.
+//!
+//! Other messages (i.e. `NOTICE`, `CLOSED` or `AUTH`) can be scripted with [`MockRelay::send_msg`].
+//!
+//! Available with the `test-utils` feature: **not** meant to be used in production!
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//!
+//! use nostr_relay_pool::mock::MockRelay;
+//! use nostr_relay_pool::prelude::*;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let mock = MockRelay::run().await;
+//!
+//! let pool = RelayPool::default();
+//! pool.add_relay(mock.url(), RelayOptions::default())
+//!     .await
+//!     .unwrap();
+//! pool.connect(Some(Duration::from_secs(5))).await;
+//!
+//! let keys = Keys::generate();
+//! let event = EventBuilder::text_note("hello", []).to_event(&keys).unwrap();
+//! pool.send_event(event, RelaySendOptions::default())
+//!     .await
+//!     .unwrap();
+//!
+//! assert_eq!(mock.events().await.len(), 1);
+//! # }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_utility::futures_util::{SinkExt, StreamExt};
+use async_utility::time;
+use nostr::message::relay::NegentropyErrorCode;
+use nostr::negentropy::{self, Bytes, Negentropy};
+use nostr::{ClientMessage, Event, EventId, Filter, JsonUtil, RelayMessage, SubscriptionId, Url};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, Mutex};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::{self, Message};
+
+/// Mock relay options
+#[derive(Debug, Clone, Default)]
+pub struct MockRelayOptions {
+    no_eose: bool,
+    req_delay: Option<Duration>,
+    handshake_delay: Option<Duration>,
+}
+
+impl MockRelayOptions {
+    /// New default options
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Never send `EOSE`
+    pub fn no_eose(mut self) -> Self {
+        self.no_eose = true;
+        self
+    }
+
+    /// Wait before replying to `REQ`
+    pub fn req_delay(mut self, delay: Duration) -> Self {
+        self.req_delay = Some(delay);
+        self
+    }
+
+    /// Wait before completing the WebSocket handshake
+    pub fn handshake_delay(mut self, delay: Duration) -> Self {
+        self.handshake_delay = Some(delay);
+        self
+    }
+}
+
+/// Track the current and the max number of concurrent operations
+#[derive(Debug, Default)]
+struct Concurrency {
+    current: AtomicUsize,
+    max: AtomicUsize,
+}
+
+impl Concurrency {
+    fn increment(&self) {
+        let current: usize = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max.fetch_max(current, Ordering::SeqCst);
+    }
+
+    fn decrement(&self) {
+        self.current.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    fn max(&self) -> usize {
+        self.max.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    opts: MockRelayOptions,
+    events: Mutex<Vec<Event>>,
+    received: Mutex<Vec<ClientMessage>>,
+    connections: AtomicUsize,
+    handshakes: Concurrency,
+    reqs: Concurrency,
+    disconnect: broadcast::Sender<()>,
+    /// Scripted messages, sent to all the connected clients
+    scripted: broadcast::Sender<RelayMessage>,
+}
+
+/// Mock relay
+///
+/// Stop listening when dropped.
+#[derive(Debug)]
+pub struct MockRelay {
+    url: Url,
+    shared: Arc<Shared>,
+    listener: JoinHandle<()>,
+}
+
+impl Drop for MockRelay {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl MockRelay {
+    /// Run mock relay with default options
+    #[inline]
+    pub async fn run() -> Self {
+        Self::run_with_opts(MockRelayOptions::default()).await
+    }
+
+    /// Run mock relay
+    pub async fn run_with_opts(opts: MockRelayOptions) -> Self {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Impossible to bind mock relay");
+        let url: Url = Url::parse(&format!(
+            "ws://{}",
+            listener.local_addr().expect("Mock relay address")
+        ))
+        .expect("Valid mock relay URL");
+
+        let (disconnect, ..) = broadcast::channel::<()>(1);
+        let (scripted, ..) = broadcast::channel::<RelayMessage>(1024);
+        let shared: Arc<Shared> = Arc::new(Shared {
+            opts,
+            events: Mutex::new(Vec::new()),
+            received: Mutex::new(Vec::new()),
+            connections: AtomicUsize::new(0),
+            handshakes: Concurrency::default(),
+            reqs: Concurrency::default(),
+            disconnect,
+            scripted,
+        });
+
+        let s: Arc<Shared> = shared.clone();
+        let listener: JoinHandle<()> = tokio::spawn(async move {
+            while let Ok((stream, ..)) = listener.accept().await {
+                let shared: Arc<Shared> = s.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(shared, stream).await {
+                        tracing::debug!("Mock relay connection error: {e}");
+                    }
+                });
+            }
+        });
+
+        Self {
+            url,
+            shared,
+            listener,
+        }
+    }
+
+    /// Relay URL
+    #[inline]
+    pub fn url(&self) -> Url {
+        self.url.clone()
+    }
+
+    /// URL of another relay served by this mock
+    ///
+    /// Useful to simulate many relays sharing the same counters.
+    pub fn url_with_path(&self, path: &str) -> Url {
+        let mut url: Url = self.url();
+        url.set_path(path);
+        url
+    }
+
+    /// Store events, served to the matching `REQ`, `COUNT` and `NEG-OPEN`
+    pub async fn add_events<I>(&self, events: I)
+    where
+        I: IntoIterator<Item = Event>,
+    {
+        let mut stored = self.shared.events.lock().await;
+        stored.extend(events);
+    }
+
+    /// Stored events (included the published ones)
+    pub async fn events(&self) -> Vec<Event> {
+        self.shared.events.lock().await.clone()
+    }
+
+    /// Messages received, in order
+    pub async fn received(&self) -> Vec<ClientMessage> {
+        self.shared.received.lock().await.clone()
+    }
+
+    /// Total number of accepted WebSocket connections
+    #[inline]
+    pub fn connections(&self) -> usize {
+        self.shared.connections.load(Ordering::SeqCst)
+    }
+
+    /// Max number of concurrent WebSocket handshakes
+    #[inline]
+    pub fn max_concurrent_handshakes(&self) -> usize {
+        self.shared.handshakes.max()
+    }
+
+    /// Max number of `REQ` processed concurrently (until `EOSE`)
+    #[inline]
+    pub fn max_concurrent_reqs(&self) -> usize {
+        self.shared.reqs.max()
+    }
+
+    /// Send a message to all the connected clients (i.e. `NOTICE`, `CLOSED` or `AUTH`)
+    #[inline]
+    pub fn send_msg(&self, msg: RelayMessage) {
+        let _ = self.shared.scripted.send(msg);
+    }
+
+    /// Close the active connections, keeping accepting the new ones
+    #[inline]
+    pub fn disconnect_all(&self) {
+        let _ = self.shared.disconnect.send(());
+    }
+
+    /// Stop accepting connections and close the active ones
+    pub fn shutdown(&self) {
+        self.listener.abort();
+        self.disconnect_all();
+    }
+}
+
+async fn handle_connection(
+    shared: Arc<Shared>,
+    stream: TcpStream,
+) -> Result<(), tungstenite::Error> {
+    // Ignore the plain HTTP requests (i.e. NIP-11 document)
+    if !is_websocket_upgrade(&stream).await? {
+        return Ok(());
+    }
+
+    shared.connections.fetch_add(1, Ordering::SeqCst);
+
+    // Subscribe before the handshake, so no scripted message is lost once connected
+    let mut scripted = shared.scripted.subscribe();
+
+    // Handshake
+    shared.handshakes.increment();
+    if let Some(delay) = shared.opts.handshake_delay {
+        time::sleep(delay).await;
+    }
+    let ws = tokio_tungstenite::accept_async(stream).await;
+    shared.handshakes.decrement();
+
+    let (mut tx, mut rx) = ws?.split();
+    let mut disconnect = shared.disconnect.subscribe();
+    let mut sessions: HashMap<SubscriptionId, Negentropy> = HashMap::new();
+
+    loop {
+        tokio::select! {
+            msg = rx.next() => match msg {
+                Some(Ok(Message::Text(text))) => {
+                    for reply in shared.handle(&text, &mut sessions).await.into_iter() {
+                        tx.send(Message::Text(reply.as_json())).await?;
+                    }
+                }
+                Some(Ok(Message::Close(..))) | None => break,
+                Some(Ok(..)) => (),
+                Some(Err(e)) => return Err(e),
+            },
+            msg = scripted.recv() => {
+                if let Ok(msg) = msg {
+                    tx.send(Message::Text(msg.as_json())).await?;
+                }
+            }
+            _ = disconnect.recv() => {
+                let _ = tx.send(Message::Close(None)).await;
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Check if the client is requesting the WebSocket upgrade, without consuming the request
+async fn is_websocket_upgrade(stream: &TcpStream) -> Result<bool, tungstenite::Error> {
+    let mut buf = [0u8; 4096];
+    loop {
+        let len: usize = stream.peek(&mut buf).await?;
+        let request: String = String::from_utf8_lossy(&buf[..len]).to_lowercase();
+
+        // Wait for the end of the headers
+        if len == 0 || len == buf.len() || request.contains("\r\n\r\n") {
+            return Ok(request.contains("upgrade: websocket"));
+        }
+
+        time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+impl Shared {
+    async fn handle(
+        &self,
+        text: &str,
+        sessions: &mut HashMap<SubscriptionId, Negentropy>,
+    ) -> Vec<RelayMessage> {
+        let msg: ClientMessage = match ClientMessage::from_json(text) {
+            Ok(msg) => msg,
+            Err(e) => return vec![RelayMessage::notice(format!("invalid message: {e}"))],
+        };
+
+        let mut received = self.received.lock().await;
+        received.push(msg.clone());
+        drop(received);
+
+        match msg {
+            ClientMessage::Event(event) => {
+                let id: EventId = event.id();
+                let mut events = self.events.lock().await;
+                if !events.iter().any(|e| e.id() == id) {
+                    events.push(*event);
+                }
+                vec![RelayMessage::ok(id, true, "")]
+            }
+            ClientMessage::Req {
+                subscription_id,
+                filters,
+            } => {
+                self.reqs.increment();
+
+                if let Some(delay) = self.opts.req_delay {
+                    time::sleep(delay).await;
+                }
+
+                let mut replies: Vec<RelayMessage> = self
+                    .query(&filters)
+                    .await
+                    .into_iter()
+                    .map(|event| RelayMessage::event(subscription_id.clone(), event))
+                    .collect();
+
+                if !self.opts.no_eose {
+                    replies.push(RelayMessage::eose(subscription_id));
+                }
+
+                self.reqs.decrement();
+
+                replies
+            }
+            ClientMessage::Count {
+                subscription_id,
+                filters,
+            } => vec![RelayMessage::count(
+                subscription_id,
+                self.query(&filters).await.len(),
+            )],
+            ClientMessage::NegOpen {
+                subscription_id,
+                filter,
+                initial_message,
+                ..
+            } => match self.neg_open(*filter, initial_message).await {
+                Ok((negentropy, message)) => {
+                    sessions.insert(subscription_id.clone(), negentropy);
+                    vec![RelayMessage::NegMsg {
+                        subscription_id,
+                        message,
+                    }]
+                }
+                Err(e) => vec![RelayMessage::NegErr {
+                    subscription_id,
+                    code: NegentropyErrorCode::Other(e.to_string()),
+                }],
+            },
+            ClientMessage::NegMsg {
+                subscription_id,
+                message,
+            } => match sessions.get_mut(&subscription_id) {
+                Some(negentropy) => {
+                    match Bytes::from_hex(message).and_then(|query| negentropy.reconcile(&query)) {
+                        Ok(reply) => vec![RelayMessage::NegMsg {
+                            subscription_id,
+                            message: reply.to_hex(),
+                        }],
+                        Err(e) => vec![RelayMessage::NegErr {
+                            subscription_id,
+                            code: NegentropyErrorCode::Other(e.to_string()),
+                        }],
+                    }
+                }
+                None => vec![RelayMessage::NegErr {
+                    subscription_id,
+                    code: NegentropyErrorCode::Closed,
+                }],
+            },
+            ClientMessage::NegClose { subscription_id } => {
+                sessions.remove(&subscription_id);
+                Vec::new()
+            }
+            ClientMessage::Close(..) | ClientMessage::Auth(..) => Vec::new(),
+        }
+    }
+
+    /// Get the stored events matching the filters (newest first)
+    async fn query(&self, filters: &[Filter]) -> Vec<Event> {
+        let mut events: Vec<Event> = self.events.lock().await.clone();
+        events.sort_by(|a, b| b.cmp(a));
+
+        let mut ids: HashSet<EventId> = HashSet::new();
+        let mut matched: Vec<Event> = Vec::new();
+        for filter in filters.iter() {
+            let iter = events.iter().filter(|event| filter.match_event(event));
+            let iter: Box<dyn Iterator<Item = &Event>> = match filter.limit {
+                Some(limit) => Box::new(iter.take(limit)),
+                None => Box::new(iter),
+            };
+            for event in iter {
+                if ids.insert(event.id()) {
+                    matched.push(event.clone());
+                }
+            }
+        }
+        matched
+    }
+
+    async fn neg_open(
+        &self,
+        filter: Filter,
+        initial_message: String,
+    ) -> Result<(Negentropy, String), negentropy::Error> {
+        let mut negentropy = Negentropy::new(32, None)?;
+        for event in self.query(&[filter]).await.into_iter() {
+            negentropy.add_item(
+                event.created_at().as_u64(),
+                Bytes::from_slice(event.id().as_bytes()),
+            )?;
+        }
+        negentropy.seal()?;
+
+        let reply: Bytes = negentropy.reconcile(&Bytes::from_hex(initial_message)?)?;
+        Ok((negentropy, reply.to_hex()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr::{EventBuilder, Keys, Kind};
+
+    use super::*;
+    use crate::{
+        FilterOptions, RelayOptions, RelayPool, RelayPoolNotification, RelaySendOptions,
+        SubscribeOptions,
+    };
+
+    async fn connected_pool(mock: &MockRelay) -> RelayPool {
+        let pool = RelayPool::default();
+        pool.add_relay(mock.url(), RelayOptions::default())
+            .await
+            .unwrap();
+        pool.connect(Some(Duration::from_secs(5))).await;
+        pool
+    }
+
+    #[tokio::test]
+    async fn test_publish_and_fetch() {
+        let mock = MockRelay::run().await;
+        let pool = connected_pool(&mock).await;
+
+        let keys = Keys::generate();
+        let event = EventBuilder::text_note("hello", [])
+            .to_event(&keys)
+            .unwrap();
+        let output = pool
+            .send_event(event.clone(), RelaySendOptions::default())
+            .await
+            .unwrap();
+        assert!(output.success.contains(&mock.url()));
+        assert_eq!(mock.events().await, vec![event.clone()]);
+
+        let output = pool
+            .fetch_events(
+                vec![Filter::new().kind(Kind::TextNote)],
+                Duration::from_secs(5),
+                FilterOptions::ExitOnEOSE,
+            )
+            .await
+            .unwrap();
+        assert_eq!(output.val, vec![event]);
+        assert!(mock
+            .received()
+            .await
+            .iter()
+            .any(|msg| matches!(msg, ClientMessage::Req { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_scripted_messages() {
+        let mock = MockRelay::run().await;
+        let pool = connected_pool(&mock).await;
+        let mut notifications = pool.notifications();
+
+        let id: SubscriptionId = pool
+            .subscribe(
+                vec![Filter::new().kind(Kind::TextNote)],
+                SubscribeOptions::default(),
+            )
+            .await
+            .unwrap()
+            .val;
+
+        mock.send_msg(RelayMessage::notice("slow down"));
+        mock.send_msg(RelayMessage::closed(id.clone(), "blocked"));
+
+        let mut scripted: Vec<RelayMessage> = Vec::new();
+        time::timeout(Some(Duration::from_secs(5)), async {
+            while scripted.len() < 2 {
+                if let Ok(RelayPoolNotification::Message { message, .. }) =
+                    notifications.recv().await
+                {
+                    if matches!(
+                        message,
+                        RelayMessage::Notice { .. } | RelayMessage::Closed { .. }
+                    ) {
+                        scripted.push(message);
+                    }
+                }
+            }
+        })
+        .await
+        .expect("Scripted messages not received");
+
+        assert_eq!(
+            scripted,
+            vec![
+                RelayMessage::notice("slow down"),
+                RelayMessage::closed(id, "blocked")
+            ]
+        );
+    }
+}
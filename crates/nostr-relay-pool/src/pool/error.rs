@@ -5,6 +5,7 @@
 use async_utility::thread;
 use nostr::message::MessageHandleError;
 use nostr::types::url;
+use nostr::{SubscriptionId, Url};
 use nostr_database::DatabaseError;
 use thiserror::Error;
 
@@ -52,6 +53,18 @@ pub enum Error {
     /// Relay not found
     #[error("relay not found")]
     RelayNotFound,
+    /// Global limit of the subscription is zero
+    #[error("global limit must be greater than zero")]
+    ZeroGlobalLimit,
+    /// Subscription ID already used in the pool
+    #[error("subscription ID already used: {0}")]
+    DuplicateSubscriptionId(SubscriptionId),
+    /// Relay already added to the pool
+    #[error("relay already added: {0}")]
+    RelayAlreadyExists(Url),
+    /// Relay not allowed by [`RelayPolicy`](super::RelayPolicy)
+    #[error("relay not allowed by policy: {0}")]
+    RelayNotAllowed(Url),
     /// Notification Handler error
     #[error("notification handler error: {0}")]
     Handler(String),
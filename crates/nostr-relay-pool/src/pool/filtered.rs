@@ -0,0 +1,128 @@
+// Copyright (c) 2022-2023 Yuki Kishimoto
+// Copyright (c) 2023-2024 Rust Nostr Developers
+// Distributed under the MIT software license
+
+//! Filtered notifications
+//!
+//! The predicate of every listener is checked before cloning the notification,
+//! so each listener receives (and clones) only the notifications it cares about.
+
+use std::fmt;
+use std::sync::Arc;
+
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::sync::RwLock;
+
+use super::RelayPoolNotification;
+
+type Predicate = Arc<dyn Fn(&RelayPoolNotification) -> bool + Send + Sync>;
+
+struct FilteredListener {
+    predicate: Predicate,
+    sender: Sender<RelayPoolNotification>,
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct FilteredNotifications {
+    listeners: Arc<RwLock<Vec<FilteredListener>>>,
+}
+
+impl fmt::Debug for FilteredNotifications {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FilteredNotifications")
+            .finish_non_exhaustive()
+    }
+}
+
+impl FilteredNotifications {
+    /// Add a listener of the notifications matching the `predicate`
+    pub async fn subscribe<F>(
+        &self,
+        predicate: F,
+        capacity: usize,
+    ) -> Receiver<RelayPoolNotification>
+    where
+        F: Fn(&RelayPoolNotification) -> bool + Send + Sync + 'static,
+    {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        let mut listeners = self.listeners.write().await;
+        listeners.push(FilteredListener {
+            predicate: Arc::new(predicate),
+            sender,
+        });
+        receiver
+    }
+
+    /// Forward the notification to the matching listeners
+    ///
+    /// If a listener is full, the notification is dropped for it (like a lagging broadcast receiver).
+    /// Closed listeners are removed.
+    pub async fn dispatch(&self, notification: &RelayPoolNotification) {
+        let mut closed: bool = false;
+
+        {
+            let listeners = self.listeners.read().await;
+            for listener in listeners.iter() {
+                if listener.sender.is_closed() {
+                    closed = true;
+                    continue;
+                }
+
+                if (listener.predicate)(notification) {
+                    if let Err(TrySendError::Closed(..)) =
+                        listener.sender.try_send(notification.clone())
+                    {
+                        closed = true;
+                    }
+                }
+            }
+        }
+
+        if closed {
+            let mut listeners = self.listeners.write().await;
+            listeners.retain(|listener| !listener.sender.is_closed());
+        }
+    }
+
+    /// Number of listeners
+    #[cfg(test)]
+    pub async fn listeners(&self) -> usize {
+        self.listeners.read().await.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dispatch() {
+        let filtered = FilteredNotifications::default();
+        let mut shutdown = filtered
+            .subscribe(
+                |notification| matches!(notification, RelayPoolNotification::Shutdown),
+                1,
+            )
+            .await;
+        let never = filtered.subscribe(|_| false, 1).await;
+        assert_eq!(filtered.listeners().await, 2);
+
+        filtered.dispatch(&RelayPoolNotification::Shutdown).await;
+        assert!(matches!(
+            shutdown.try_recv(),
+            Ok(RelayPoolNotification::Shutdown)
+        ));
+
+        // Full: dropped
+        filtered.dispatch(&RelayPoolNotification::Shutdown).await;
+        filtered.dispatch(&RelayPoolNotification::Shutdown).await;
+        assert!(shutdown.try_recv().is_ok());
+        assert!(shutdown.try_recv().is_err());
+
+        // Closed listeners are removed
+        drop(never);
+        filtered.dispatch(&RelayPoolNotification::Shutdown).await;
+        assert_eq!(filtered.listeners().await, 1);
+    }
+}
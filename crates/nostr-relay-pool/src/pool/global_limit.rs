@@ -0,0 +1,132 @@
+// Copyright (c) 2022-2023 Yuki Kishimoto
+// Copyright (c) 2023-2024 Rust Nostr Developers
+// Distributed under the MIT software license
+
+//! Global limit of subscriptions
+//!
+//! Max number of unique events delivered for a subscription, across all relays.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use nostr::SubscriptionId;
+use tokio::sync::{Notify, RwLock};
+
+/// For how long a reached limit is kept after the `CLOSE`, to drop the events still in flight
+pub(crate) const GLOBAL_LIMIT_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+struct GlobalLimit {
+    max: usize,
+    delivered: usize,
+    /// Notified when the limit is reached or removed
+    done: Arc<Notify>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct GlobalLimits {
+    limits: Arc<RwLock<HashMap<SubscriptionId, GlobalLimit>>>,
+}
+
+impl GlobalLimits {
+    /// Set the global limit of a subscription
+    ///
+    /// Return the [`Notify`] used to signal that the limit is reached (or removed).
+    pub async fn set(&self, id: SubscriptionId, max: usize) -> Arc<Notify> {
+        let done: Arc<Notify> = Arc::new(Notify::new());
+        let mut limits = self.limits.write().await;
+        let previous: Option<GlobalLimit> = limits.insert(
+            id,
+            GlobalLimit {
+                max,
+                delivered: 0,
+                done: done.clone(),
+            },
+        );
+
+        // Release the waiter of the previous subscription with the same ID
+        if let Some(previous) = previous {
+            previous.done.notify_one();
+        }
+
+        done
+    }
+
+    /// Count a new unique event of the subscription
+    ///
+    /// Return `false` if the event must be dropped, since the limit was already reached.
+    pub async fn deliver(&self, id: &SubscriptionId) -> bool {
+        let mut limits = self.limits.write().await;
+        match limits.get_mut(id) {
+            Some(limit) => {
+                if limit.delivered >= limit.max {
+                    return false;
+                }
+
+                limit.delivered += 1;
+
+                if limit.delivered >= limit.max {
+                    limit.done.notify_one();
+                }
+
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// Check if the limit of the subscription has been reached
+    pub async fn is_reached(&self, id: &SubscriptionId) -> bool {
+        let limits = self.limits.read().await;
+        limits
+            .get(id)
+            .map_or(false, |limit| limit.delivered >= limit.max)
+    }
+
+    /// Remove the limit of the subscription
+    ///
+    /// Reached limits are kept, to drop the events still in flight from relays that have not processed the `CLOSE` yet
+    /// (see [`GLOBAL_LIMIT_GRACE_PERIOD`]).
+    pub async fn remove(&self, id: &SubscriptionId) {
+        let mut limits = self.limits.write().await;
+        if let Some(limit) = limits.get(id) {
+            if limit.delivered < limit.max {
+                limit.done.notify_one();
+                limits.remove(id);
+            }
+        }
+    }
+
+    /// Forget the limit of the subscription, also if reached
+    pub async fn forget(&self, id: &SubscriptionId) {
+        let mut limits = self.limits.write().await;
+        if let Some(limit) = limits.remove(id) {
+            limit.done.notify_one();
+        }
+    }
+
+    /// Forget the limit of the subscription, only if it's still the one signaling with `done`
+    ///
+    /// Used to not forget the limit of a new subscription with the same ID.
+    pub async fn forget_if(&self, id: &SubscriptionId, done: &Arc<Notify>) {
+        let mut limits = self.limits.write().await;
+        if let Some(limit) = limits.get(id) {
+            if Arc::ptr_eq(&limit.done, done) {
+                limits.remove(id);
+            }
+        }
+    }
+
+    /// Remove the limits of all subscriptions (except the reached ones)
+    pub async fn clear(&self) {
+        let mut limits = self.limits.write().await;
+        limits.retain(|.., limit| {
+            let reached: bool = limit.delivered >= limit.max;
+            if !reached {
+                limit.done.notify_one();
+            }
+            reached
+        });
+    }
+}
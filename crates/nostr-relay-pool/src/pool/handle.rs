@@ -0,0 +1,284 @@
+// Copyright (c) 2022-2023 Yuki Kishimoto
+// Copyright (c) 2023-2024 Rust Nostr Developers
+// Distributed under the MIT software license
+
+//! Subscription handle
+
+use std::fmt;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use async_utility::futures_util::stream::{self, BoxStream};
+use async_utility::futures_util::{Stream, StreamExt};
+use async_utility::thread;
+use nostr::{Event, SubscriptionId};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, watch};
+
+use super::internal::InternalRelayPool;
+use super::RelayPoolNotification;
+use crate::RelaySendOptions;
+
+/// Subscription handle
+///
+/// Returned by [`RelayPool::subscribe_cancellable`](super::RelayPool::subscribe_cancellable).
+#[derive(Debug, Clone)]
+pub struct SubscriptionHandle {
+    id: SubscriptionId,
+    pool: InternalRelayPool,
+    cancelled: Arc<watch::Sender<bool>>,
+}
+
+impl SubscriptionHandle {
+    pub(super) fn new(id: SubscriptionId, pool: InternalRelayPool) -> Self {
+        let (cancelled, ..) = watch::channel(false);
+        Self {
+            id,
+            pool,
+            cancelled: Arc::new(cancelled),
+        }
+    }
+
+    /// Subscription ID
+    #[inline]
+    pub fn id(&self) -> &SubscriptionId {
+        &self.id
+    }
+
+    /// Check if subscription has been cancelled
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.borrow()
+    }
+
+    /// Cancel subscription, sending `CLOSE` to all relays
+    ///
+    /// Calling it more than once (also from the clones of the handle) is a no-op.
+    /// Return `false` if the subscription was already cancelled.
+    pub async fn cancel(&self, opts: RelaySendOptions) -> bool {
+        if self.cancelled.send_replace(true) {
+            return false;
+        }
+
+        self.pool.unsubscribe(self.id.clone(), opts).await;
+        true
+    }
+}
+
+/// Stream of the events received for a subscription
+///
+/// Returned by [`RelayPool::subscribe_stream`](super::RelayPool::subscribe_stream).
+/// The stream ends as soon as the subscription is cancelled, discarding the pending events.
+/// Dropping the stream cancels the subscription.
+pub struct SubscriptionStream {
+    handle: SubscriptionHandle,
+    events: BoxStream<'static, Event>,
+}
+
+impl fmt::Debug for SubscriptionStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SubscriptionStream")
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
+impl Drop for SubscriptionStream {
+    fn drop(&mut self) {
+        if !self.handle.is_cancelled() {
+            let handle: SubscriptionHandle = self.handle.clone();
+            let _ = thread::spawn(async move {
+                handle.cancel(RelaySendOptions::default()).await;
+            });
+        }
+    }
+}
+
+impl Stream for SubscriptionStream {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.events.poll_next_unpin(cx)
+    }
+}
+
+impl SubscriptionStream {
+    /// The `notifications` receiver must be created before subscribing, to not miss any event
+    pub(super) fn new(
+        handle: SubscriptionHandle,
+        notifications: broadcast::Receiver<RelayPoolNotification>,
+    ) -> Self {
+        let cancelled: watch::Receiver<bool> = handle.cancelled.subscribe();
+        let id: SubscriptionId = handle.id.clone();
+        let events = stream::unfold(
+            (notifications, cancelled),
+            move |(mut notifications, mut cancelled)| {
+                let id: SubscriptionId = id.clone();
+                async move {
+                    let event: Event = next_event(&mut notifications, &mut cancelled, &id).await?;
+                    Some((event, (notifications, cancelled)))
+                }
+            },
+        );
+
+        Self {
+            handle,
+            events: events.boxed(),
+        }
+    }
+
+    /// Subscription handle
+    #[inline]
+    pub fn handle(&self) -> &SubscriptionHandle {
+        &self.handle
+    }
+}
+
+/// Wait for the next event of the subscription
+///
+/// Return `None` if the subscription is cancelled or the pool is shutdown.
+async fn next_event(
+    notifications: &mut broadcast::Receiver<RelayPoolNotification>,
+    cancelled: &mut watch::Receiver<bool>,
+    id: &SubscriptionId,
+) -> Option<Event> {
+    loop {
+        if *cancelled.borrow() {
+            return None;
+        }
+
+        tokio::select! {
+            biased;
+            res = cancelled.changed() => {
+                if res.is_err() {
+                    return None;
+                }
+            }
+            res = notifications.recv() => match res {
+                Ok(RelayPoolNotification::Event {
+                    subscription_id,
+                    event,
+                    ..
+                }) => {
+                    if &subscription_id == id {
+                        return Some(*event);
+                    }
+                }
+                Ok(RelayPoolNotification::Shutdown) | Err(RecvError::Closed) => return None,
+                Ok(..) => (),
+                Err(RecvError::Lagged(count)) => {
+                    tracing::warn!("Subscription stream '{id}' is falling behind: {count} notifications dropped");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use async_utility::time;
+    use nostr::{ClientMessage, EventBuilder, Filter, Keys};
+    use nostr_database::MemoryDatabase;
+
+    use super::*;
+    use crate::mock::MockRelay;
+    use crate::{RelayOptions, RelayPool, RelayPoolOptions, SubscribeOptions};
+
+    async fn pool_with_relays(mocks: &[MockRelay]) -> RelayPool {
+        let pool = RelayPool::default();
+        for mock in mocks.iter() {
+            pool.add_relay(mock.url(), RelayOptions::default())
+                .await
+                .unwrap();
+        }
+        pool.connect(Some(Duration::from_secs(5))).await;
+        pool
+    }
+
+    async fn wait_for_close(mock: &MockRelay, id: &SubscriptionId) {
+        let close = ClientMessage::close(id.clone());
+        time::timeout(Some(Duration::from_secs(5)), async {
+            while !mock.received().await.contains(&close) {
+                thread::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("CLOSE not sent");
+    }
+
+    #[tokio::test]
+    async fn test_cancel_idempotent() {
+        let pool = InternalRelayPool::with_database(
+            RelayPoolOptions::default(),
+            MemoryDatabase::default(),
+        );
+        let handle = SubscriptionHandle::new(SubscriptionId::generate(), pool);
+        let clone = handle.clone();
+
+        assert!(!handle.is_cancelled());
+        assert!(handle.cancel(RelaySendOptions::default()).await);
+        assert!(clone.is_cancelled());
+        assert!(!clone.cancel(RelaySendOptions::default()).await);
+        assert!(!handle.cancel(RelaySendOptions::default()).await);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_stream() {
+        let keys = Keys::generate();
+        let event = EventBuilder::text_note("stream", [])
+            .to_event(&keys)
+            .unwrap();
+
+        let mocks = [MockRelay::run().await, MockRelay::run().await];
+        for mock in mocks.iter() {
+            mock.add_events([event.clone()]).await;
+        }
+        let pool = pool_with_relays(&mocks).await;
+
+        let mut stream = pool
+            .subscribe_stream(vec![Filter::new()], SubscribeOptions::default())
+            .await
+            .unwrap()
+            .val;
+        let id: SubscriptionId = stream.handle().id().clone();
+
+        // Event received
+        let received = time::timeout(Some(Duration::from_secs(5)), stream.next())
+            .await
+            .expect("Event not received");
+        assert_eq!(received, Some(event));
+
+        // Cancelled stream ends immediately
+        assert!(stream.handle().cancel(RelaySendOptions::default()).await);
+        let next = time::timeout(Some(Duration::from_secs(1)), stream.next())
+            .await
+            .expect("Stream not ended");
+        assert_eq!(next, None);
+
+        for mock in mocks.iter() {
+            wait_for_close(mock, &id).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_drop_stream() {
+        let mocks = [MockRelay::run().await, MockRelay::run().await];
+        let pool = pool_with_relays(&mocks).await;
+
+        let stream = pool
+            .subscribe_stream(vec![Filter::new()], SubscribeOptions::default())
+            .await
+            .unwrap()
+            .val;
+        let id: SubscriptionId = stream.handle().id().clone();
+        drop(stream);
+
+        for mock in mocks.iter() {
+            wait_for_close(mock, &id).await;
+        }
+        assert!(pool.subscription(&id).await.is_none());
+    }
+}
@@ -4,33 +4,61 @@
 
 //! Relay Pool
 
-use std::collections::btree_set::IntoIter;
-use std::collections::{BTreeSet, HashMap, HashSet};
-use std::iter::Rev;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::Duration;
 
 use async_utility::thread::JoinHandle;
 use async_utility::{thread, time};
 use atomic_destructor::AtomicDestroyer;
-use nostr::{ClientMessage, Event, EventId, Filter, SubscriptionId, Timestamp, TryIntoUrl, Url};
+use nostr::{
+    ClientMessage, Event, EventId, Filter, Kind, PublicKey, RelayMessage, SubscriptionId,
+    Timestamp, TryIntoUrl, Url,
+};
 use nostr_database::{DynNostrDatabase, IntoNostrDatabase, Order};
-use tokio::sync::{broadcast, Mutex, RwLock};
+use tokio::sync::{broadcast, mpsc, watch, Mutex, Notify, OwnedSemaphorePermit, RwLock, Semaphore};
 
-use super::options::RelayPoolOptions;
-use super::{Error, Output, RelayPoolNotification};
+use super::filtered::FilteredNotifications;
+use super::global_limit::{GlobalLimits, GLOBAL_LIMIT_GRACE_PERIOD};
+use super::options::{AddRelayBehavior, RelayPoolOptions};
+use super::status::RelayStatusReceiver;
+use super::{Error, Output, ReconcileItem, Reconciliation, RelayPoolNotification};
 use crate::relay::options::{FilterOptions, NegentropyOptions, RelayOptions, RelaySendOptions};
-use crate::relay::{Relay, RelayBlacklist};
+use crate::relay::{
+    Error as RelayError, EventValidator, MessageTracer, Relay, RelayBlacklist,
+    RelayConnectionStats, RelayNotification, RelayStats, RelayStatus, SyncHandle,
+};
 use crate::{util, SubscribeOptions};
 
+/// Subscription ID of the events injected with [`InternalRelayPool::inject_events`]
+const INJECTED_SUBSCRIPTION_ID: &str = "injected";
+
+/// Relays that delivered the newest version of replaceable events
+type StickyRelays = Arc<RwLock<HashMap<(PublicKey, Kind), (Url, Timestamp)>>>;
+
+/// Called for every [`ReconcileItem`] discovered with a relay
+type ReconcileCallback = dyn Fn(&Url, ReconcileItem) + Send + Sync;
+
 #[derive(Debug, Clone)]
 pub struct InternalRelayPool {
     database: Arc<DynNostrDatabase>,
     relays: Arc<RwLock<HashMap<Url, Relay>>>,
     notification_sender: broadcast::Sender<RelayPoolNotification>,
+    status_sender: broadcast::Sender<(Url, RelayStatus)>,
     subscriptions: Arc<RwLock<HashMap<SubscriptionId, Vec<Filter>>>>,
+    /// Number of consumers of coalesced subscriptions
+    subscription_refs: Arc<RwLock<HashMap<SubscriptionId, usize>>>,
+    /// Custom subscription IDs being subscribed (not stored by the relays yet)
+    pending_ids: Arc<Mutex<HashSet<SubscriptionId>>>,
     blacklist: RelayBlacklist,
-    // opts: RelayPoolOptions,
+    /// Muted public keys and words (separate from the blacklist)
+    mutes: RelayBlacklist,
+    sticky_relays: StickyRelays,
+    validator: Arc<RwLock<Option<EventValidator>>>,
+    tracer: Arc<RwLock<Option<MessageTracer>>>,
+    global_limits: GlobalLimits,
+    filtered_notifications: FilteredNotifications,
+    opts: RelayPoolOptions,
 }
 
 impl AtomicDestroyer for InternalRelayPool {
@@ -54,14 +82,24 @@ impl InternalRelayPool {
         D: IntoNostrDatabase,
     {
         let (notification_sender, _) = broadcast::channel(opts.notification_channel_size);
+        let (status_sender, _) = broadcast::channel(opts.notification_channel_size);
 
         Self {
             database: database.into_nostr_database(),
             relays: Arc::new(RwLock::new(HashMap::new())),
             notification_sender,
+            status_sender,
             subscriptions: Arc::new(RwLock::new(HashMap::new())),
+            subscription_refs: Arc::new(RwLock::new(HashMap::new())),
+            pending_ids: Arc::new(Mutex::new(HashSet::new())),
             blacklist: RelayBlacklist::empty(),
-            //opts,
+            mutes: RelayBlacklist::empty(),
+            sticky_relays: Arc::new(RwLock::new(HashMap::new())),
+            validator: Arc::new(RwLock::new(None)),
+            tracer: Arc::new(RwLock::new(None)),
+            global_limits: GlobalLimits::default(),
+            filtered_notifications: FilteredNotifications::default(),
+            opts,
         }
     }
 
@@ -71,6 +109,9 @@ impl InternalRelayPool {
 
         // Send shutdown notification
         time::timeout(Some(Duration::from_secs(1)), async move {
+            self.filtered_notifications
+                .dispatch(&RelayPoolNotification::Shutdown)
+                .await;
             let _ = self
                 .notification_sender
                 .send(RelayPoolNotification::Shutdown);
@@ -86,6 +127,29 @@ impl InternalRelayPool {
         self.notification_sender.subscribe()
     }
 
+    pub async fn subscribe_filtered<F>(&self, predicate: F) -> mpsc::Receiver<RelayPoolNotification>
+    where
+        F: Fn(&RelayPoolNotification) -> bool + Send + Sync + 'static,
+    {
+        self.filtered_notifications
+            .subscribe(predicate, self.opts.notification_channel_size)
+            .await
+    }
+
+    pub async fn status_stream(&self) -> RelayStatusReceiver {
+        // Subscribe before getting the current statuses, to not lose any change
+        let receiver = self.status_sender.subscribe();
+
+        let relays: HashMap<Url, Relay> = self.relays().await;
+        let mut initial: Vec<(Url, RelayStatus)> = Vec::with_capacity(relays.len());
+        for (url, relay) in relays.into_iter() {
+            let status: RelayStatus = relay.status().await;
+            initial.push((url, status));
+        }
+
+        RelayStatusReceiver::new(initial, receiver)
+    }
+
     pub fn database(&self) -> Arc<DynNostrDatabase> {
         self.database.clone()
     }
@@ -94,11 +158,251 @@ impl InternalRelayPool {
         self.blacklist.clone()
     }
 
+    pub fn mutes(&self) -> RelayBlacklist {
+        self.mutes.clone()
+    }
+
     pub async fn relays(&self) -> HashMap<Url, Relay> {
         let relays = self.relays.read().await;
         relays.clone()
     }
 
+    /// Relays used for routine subscriptions and queries (archive relays excluded)
+    async fn routine_relays(&self) -> HashMap<Url, Relay> {
+        let relays = self.relays.read().await;
+        relays
+            .iter()
+            .filter(|(.., relay)| !relay.flags().has_archive())
+            .map(|(url, relay)| (url.clone(), relay.clone()))
+            .collect()
+    }
+
+    pub async fn archive_relays(&self) -> HashMap<Url, Relay> {
+        let relays = self.relays.read().await;
+        relays
+            .iter()
+            .filter(|(.., relay)| relay.flags().has_archive())
+            .map(|(url, relay)| (url.clone(), relay.clone()))
+            .collect()
+    }
+
+    pub async fn relays_in_group(&self, group: &str) -> HashMap<Url, Relay> {
+        let relays = self.relays.read().await;
+        relays
+            .iter()
+            .filter(|(.., relay)| relay.opts().get_group() == Some(group))
+            .map(|(url, relay)| (url.clone(), relay.clone()))
+            .collect()
+    }
+
+    pub async fn kind_histogram(&self) -> HashMap<Kind, u64> {
+        let relays = self.relays.read().await;
+        let mut histogram: HashMap<Kind, u64> = HashMap::new();
+        for relay in relays.values() {
+            for (kind, count) in relay.kind_histogram().await.into_iter() {
+                *histogram.entry(kind).or_default() += count;
+            }
+        }
+        histogram
+    }
+
+    pub async fn reset_kind_histogram(&self) {
+        let relays = self.relays.read().await;
+        for relay in relays.values() {
+            relay.reset_kind_histogram().await;
+        }
+    }
+
+    #[cfg(feature = "nip11")]
+    #[inline]
+    pub async fn relays_supporting(&self, nip: u16) -> HashSet<Url> {
+        let relays: HashMap<Url, Relay> = self.relays().await;
+        self.filter_supporting(relays, nip).await
+    }
+
+    /// Keep only the relays that support a NIP
+    #[cfg(feature = "nip11")]
+    async fn filter_supporting(&self, relays: HashMap<Url, Relay>, nip: u16) -> HashSet<Url> {
+        let mut urls: HashSet<Url> = HashSet::with_capacity(relays.len());
+        for (url, relay) in relays.into_iter() {
+            let document = relay.document().await;
+            if supports_nip(
+                document.supported_nips.as_deref(),
+                nip,
+                self.opts.include_unknown_nips.unwrap_or(true),
+            ) {
+                urls.insert(url);
+            }
+        }
+        urls
+    }
+
+    pub async fn slow_relays(&self, below: f64) -> HashSet<Url> {
+        let relays: HashMap<Url, Relay> = self.relays().await;
+        let mut urls: HashSet<Url> = HashSet::new();
+        for (url, relay) in relays.into_iter() {
+            if !relay.is_connected().await {
+                continue;
+            }
+
+            let rate: f64 = relay
+                .stats()
+                .events_per_second(self.opts.throughput_window)
+                .await;
+            if rate < below {
+                urls.insert(url);
+            }
+        }
+        urls
+    }
+
+    pub async fn inject_events<U>(&self, relay_url: U, events: Vec<Event>) -> Result<(), Error>
+    where
+        U: TryIntoUrl,
+        Error: From<<U as TryIntoUrl>::Err>,
+    {
+        let url: Url = relay_url.try_into_url()?;
+        let subscription_id = SubscriptionId::new(INJECTED_SUBSCRIPTION_ID);
+
+        match self.internal_relay(&url).await {
+            Ok(relay) => relay.inner.inject_events(&subscription_id, events).await,
+            Err(..) => {
+                // Virtual relay: not added to the pool and never connected
+                let relay = Relay::custom(
+                    url,
+                    self.database.clone(),
+                    self.blacklist.clone(),
+                    RelayOptions::default(),
+                );
+                relay
+                    .set_notification_sender(Some(self.notification_sender.clone()))
+                    .await;
+                relay
+                    .inner
+                    .set_filtered_notifications(Some(self.filtered_notifications.clone()))
+                    .await;
+                relay.inner.set_mutes(Some(self.mutes.clone())).await;
+                {
+                    let validator = self.validator.read().await;
+                    relay.inner.set_validator(validator.clone()).await;
+                }
+                relay
+                    .inner
+                    .set_invalid_sig_policy(self.opts.on_invalid_signature)
+                    .await;
+                relay
+                    .inner
+                    .set_required_tags(self.opts.require_tags.clone())
+                    .await;
+                relay
+                    .inner
+                    .set_event_overflow_policy(
+                        self.opts.event_overflow_policy,
+                        self.opts.notification_channel_size,
+                    )
+                    .await;
+
+                relay.inner.inject_events(&subscription_id, events).await;
+
+                // Don't send the status change notification when the relay is dropped
+                relay.set_notification_sender(None).await;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn set_message_tracer(&self, tracer: Option<MessageTracer>) {
+        {
+            let mut t = self.tracer.write().await;
+            *t = tracer.clone();
+        }
+
+        let relays = self.relays.read().await;
+        for relay in relays.values() {
+            relay.inner.set_message_tracer(tracer.clone()).await;
+        }
+    }
+
+    pub async fn scores(&self) -> HashMap<Url, f64> {
+        let relays: HashMap<Url, Relay> = self.relays().await;
+        let mut scores: HashMap<Url, f64> = HashMap::with_capacity(relays.len());
+        for (url, relay) in relays.into_iter() {
+            let score: f64 = self.score(&relay).await;
+            scores.insert(url, score);
+        }
+        scores
+    }
+
+    async fn score(&self, relay: &Relay) -> f64 {
+        let stats: RelayConnectionStats = relay.stats();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let latency: Option<Duration> = stats.latency().await;
+        #[cfg(target_arch = "wasm32")]
+        let latency: Option<Duration> = None;
+
+        let events_per_second: f64 = stats.events_per_second(self.opts.throughput_window).await;
+
+        self.opts.score_weights.score(
+            latency,
+            stats.success().saturating_sub(1),
+            stats.uptime(),
+            events_per_second,
+        )
+    }
+
+    pub async fn prune_low_scoring(&self, threshold: f64) -> Result<HashSet<Url>, Error> {
+        let relays: HashMap<Url, Relay> = self.relays().await;
+
+        let mut candidates: Vec<PruneCandidate> = Vec::with_capacity(relays.len());
+        for (url, relay) in relays.iter() {
+            let flags = relay.flags();
+            candidates.push(PruneCandidate {
+                url: url.clone(),
+                score: self.score(relay).await,
+                read: flags.has_read(),
+                write: flags.has_write(),
+            });
+        }
+
+        let urls: HashSet<Url> = prunable_relays(candidates, threshold);
+        for url in urls.iter() {
+            self.remove_relay(url.clone()).await?;
+        }
+
+        Ok(urls)
+    }
+
+    pub async fn set_validator(&self, validator: EventValidator) {
+        {
+            let mut v = self.validator.write().await;
+            *v = Some(validator.clone());
+        }
+
+        let relays = self.relays.read().await;
+        for relay in relays.values() {
+            relay.inner.set_validator(Some(validator.clone())).await;
+        }
+    }
+
+    pub async fn stats(&self) -> HashMap<Url, RelayConnectionStats> {
+        let relays = self.relays.read().await;
+        relays
+            .iter()
+            .map(|(url, relay)| (url.clone(), relay.stats()))
+            .collect()
+    }
+
+    pub async fn stats_snapshot(&self) -> HashMap<Url, RelayStats> {
+        let relays: HashMap<Url, Relay> = self.relays().await;
+        let mut snapshot: HashMap<Url, RelayStats> = HashMap::with_capacity(relays.len());
+        for (url, relay) in relays.into_iter() {
+            snapshot.insert(url, relay.stats_snapshot().await);
+        }
+        snapshot
+    }
+
     async fn internal_relay(&self, url: &Url) -> Result<Relay, Error> {
         let relays = self.relays.read().await;
         relays.get(url).cloned().ok_or(Error::RelayNotFound)
@@ -136,9 +440,58 @@ impl InternalRelayPool {
     pub(crate) async fn remove_all_subscriptions(&self) {
         let mut subscriptions = self.subscriptions.write().await;
         subscriptions.clear();
+
+        let mut subscription_refs = self.subscription_refs.write().await;
+        subscription_refs.clear();
     }
 
-    pub async fn add_relay<U>(&self, url: U, opts: RelayOptions) -> Result<bool, Error>
+    /// Find an active coalesced subscription with the same filters and increment its consumers,
+    /// or register a new coalesced subscription
+    ///
+    /// Return the subscription ID and `true` if it's reused.
+    async fn acquire_subscription(&self, filters: &[Filter]) -> (SubscriptionId, bool) {
+        // Keep both locks, to not register the same filters twice
+        let mut subscriptions = self.subscriptions.write().await;
+        let mut subscription_refs = self.subscription_refs.write().await;
+        for (id, consumers) in subscription_refs.iter_mut() {
+            if let Some(current) = subscriptions.get(id) {
+                if same_filters(current, filters) {
+                    *consumers += 1;
+                    return (id.clone(), true);
+                }
+            }
+        }
+
+        let id: SubscriptionId = self.opts.generate_subscription_id();
+        subscriptions.insert(id.clone(), filters.to_vec());
+        subscription_refs.insert(id.clone(), 1);
+        (id, false)
+    }
+
+    /// Decrement consumers of a coalesced subscription
+    ///
+    /// Return `true` if the subscription is still used by other consumers.
+    async fn release_subscription(&self, id: &SubscriptionId) -> bool {
+        let mut subscription_refs = self.subscription_refs.write().await;
+        match subscription_refs.get_mut(id) {
+            Some(consumers) if *consumers > 1 => {
+                *consumers -= 1;
+                true
+            }
+            Some(..) => {
+                subscription_refs.remove(id);
+                false
+            }
+            None => false,
+        }
+    }
+
+    pub async fn add_relay<U>(
+        &self,
+        url: U,
+        opts: RelayOptions,
+        behavior: AddRelayBehavior,
+    ) -> Result<bool, Error>
     where
         U: TryIntoUrl,
         Error: From<<U as TryIntoUrl>::Err>,
@@ -146,6 +499,11 @@ impl InternalRelayPool {
         // Convert into url
         let url: Url = url.try_into_url()?;
 
+        // Check relay policy
+        if !self.opts.relay_policy.is_allowed(&url) {
+            return Err(Error::RelayNotAllowed(url));
+        }
+
         // Get relays
         let mut relays = self.relays.write().await;
 
@@ -159,10 +517,62 @@ impl InternalRelayPool {
                 .set_notification_sender(Some(self.notification_sender.clone()))
                 .await;
 
-            // Set relay subscriptions
-            let subscriptions = self.subscriptions().await;
-            for (id, filters) in subscriptions.into_iter() {
-                relay.inner.update_subscription(id, filters, false).await;
+            // Set status sender
+            relay
+                .inner
+                .set_status_sender(Some(self.status_sender.clone()))
+                .await;
+
+            // Set event validator
+            let validator = self.validator.read().await;
+            relay.inner.set_validator(validator.clone()).await;
+
+            // Set invalid signature policy
+            relay
+                .inner
+                .set_invalid_sig_policy(self.opts.on_invalid_signature)
+                .await;
+
+            // Set required tags
+            relay
+                .inner
+                .set_required_tags(self.opts.require_tags.clone())
+                .await;
+
+            // Set event overflow policy
+            relay
+                .inner
+                .set_event_overflow_policy(
+                    self.opts.event_overflow_policy,
+                    self.opts.notification_channel_size,
+                )
+                .await;
+
+            // Set subscriptions global limits
+            relay
+                .inner
+                .set_global_limits(Some(self.global_limits.clone()))
+                .await;
+
+            // Set mute list
+            relay.inner.set_mutes(Some(self.mutes.clone())).await;
+
+            // Set filtered notifications listeners
+            relay
+                .inner
+                .set_filtered_notifications(Some(self.filtered_notifications.clone()))
+                .await;
+
+            // Set message tracer
+            let tracer = self.tracer.read().await;
+            relay.inner.set_message_tracer(tracer.clone()).await;
+
+            // Set relay subscriptions (archive relays are used only for historical queries)
+            if !relay.flags().has_archive() {
+                let subscriptions = self.subscriptions().await;
+                for (id, filters) in subscriptions.into_iter() {
+                    relay.inner.store_subscription(id, filters, false).await;
+                }
             }
 
             // Insert relay into map
@@ -170,7 +580,16 @@ impl InternalRelayPool {
 
             Ok(true)
         } else {
-            Ok(false)
+            match behavior {
+                AddRelayBehavior::Error => Err(Error::RelayAlreadyExists(url)),
+                AddRelayBehavior::Ignore => Ok(false),
+                AddRelayBehavior::UpdateOptions => {
+                    if let Some(relay) = relays.get(&url) {
+                        relay.inner.update_opts(opts).await;
+                    }
+                    Ok(false)
+                }
+            }
         }
     }
 
@@ -186,6 +605,32 @@ impl InternalRelayPool {
         }
         Ok(())
     }
+
+    pub async fn remove_relay_graceful<U>(&self, url: U, timeout: Duration) -> Result<bool, Error>
+    where
+        U: TryIntoUrl,
+        Error: From<<U as TryIntoUrl>::Err>,
+    {
+        let url: Url = url.try_into_url()?;
+
+        // Remove from the pool, so no new subscriptions are sent to the relay
+        let relay: Relay = {
+            let mut relays = self.relays.write().await;
+            relays.remove(&url).ok_or(Error::RelayNotFound)?
+        };
+
+        // Wait for active subscriptions to reach EOSE (events still flow to the pool notifications)
+        let drained: bool = relay.inner.drain(timeout).await;
+
+        if !drained {
+            tracing::warn!("Relay '{url}' not drained within {timeout:?}, force-closing.");
+        }
+
+        relay.disconnect().await?;
+
+        Ok(drained)
+    }
+
     pub async fn remove_all_relays(&self) -> Result<(), Error> {
         let mut relays = self.relays.write().await;
         for relay in relays.values() {
@@ -414,9 +859,15 @@ impl InternalRelayPool {
                         Err(e) => {
                             tracing::error!("Impossible to send event to {url}: {e}");
 
+                            // Keep the raw `OK` message if the relay rejected the event
+                            let message: String = match e {
+                                RelayError::EventNotPublished(message) => message,
+                                e => e.to_string(),
+                            };
+
                             // Failed, insert relay url in 'failed' map result
                             let mut result = result.lock().await;
-                            result.failed.insert(url, Some(e.to_string()));
+                            result.failed.insert(url, Some(message));
                         }
                     }
                 })?;
@@ -442,8 +893,48 @@ impl InternalRelayPool {
         filters: Vec<Filter>,
         opts: SubscribeOptions,
     ) -> Result<Output<SubscriptionId>, Error> {
-        let id: SubscriptionId = SubscriptionId::generate();
-        let output: Output<()> = self.subscribe_with_id(id.clone(), filters, opts).await?;
+        let coalesce: bool =
+            self.opts.coalesce_subscriptions && !opts.is_auto_closing() && opts.id.is_none();
+
+        if coalesce {
+            // Reuse active subscription, if any
+            let (id, reused) = self.acquire_subscription(&filters).await;
+            if reused {
+                let mut output: Output<SubscriptionId> = Output {
+                    val: id,
+                    success: HashSet::new(),
+                    failed: HashMap::new(),
+                };
+                for (url, relay) in self.routine_relays().await.into_iter() {
+                    if relay.subscription(&output.val).await.is_some() {
+                        output.success.insert(url);
+                    }
+                }
+                return Ok(output);
+            }
+
+            return match self.subscribe_with_id(id.clone(), filters, opts).await {
+                Ok(output) => Ok(Output {
+                    val: id,
+                    success: output.success,
+                    failed: output.failed,
+                }),
+                Err(e) => {
+                    // Unregister, if not reused in the meantime
+                    if !self.release_subscription(&id).await {
+                        self.remove_subscription(&id).await;
+                    }
+                    Err(e)
+                }
+            };
+        }
+
+        let id: SubscriptionId = self.subscription_id(&opts).await?;
+        let output: Result<Output<()>, Error> =
+            self.subscribe_with_id(id.clone(), filters, opts).await;
+        self.release_subscription_id(&id).await;
+        let output: Output<()> = output?;
+
         Ok(Output {
             val: id,
             success: output.success,
@@ -451,6 +942,39 @@ impl InternalRelayPool {
         })
     }
 
+    /// Get the custom subscription ID, checking that it's not already used, or generate a new one
+    ///
+    /// The custom ID is reserved until [`InternalRelayPool::release_subscription_id`] is called.
+    async fn subscription_id(&self, opts: &SubscribeOptions) -> Result<SubscriptionId, Error> {
+        match &opts.id {
+            Some(id) => {
+                // Keep the lock until the ID is reserved
+                let mut pending_ids = self.pending_ids.lock().await;
+
+                if pending_ids.contains(id) || self.subscription(id).await.is_some() {
+                    return Err(Error::DuplicateSubscriptionId(id.clone()));
+                }
+
+                for relay in self.relays().await.into_values() {
+                    if relay.subscription(id).await.is_some() {
+                        return Err(Error::DuplicateSubscriptionId(id.clone()));
+                    }
+                }
+
+                pending_ids.insert(id.clone());
+
+                Ok(id.clone())
+            }
+            None => Ok(self.opts.generate_subscription_id()),
+        }
+    }
+
+    /// Release the custom subscription ID reserved by [`InternalRelayPool::subscription_id`]
+    async fn release_subscription_id(&self, id: &SubscriptionId) {
+        let mut pending_ids = self.pending_ids.lock().await;
+        pending_ids.remove(id);
+    }
+
     pub async fn subscribe_with_id(
         &self,
         id: SubscriptionId,
@@ -464,8 +988,8 @@ impl InternalRelayPool {
                 .await;
         }
 
-        // Get relays
-        let relays = self.relays().await;
+        // Get relays (archive relays excluded)
+        let relays = self.routine_relays().await;
 
         // Subscribe
         self.subscribe_with_id_to(relays.into_keys(), id, filters, opts)
@@ -483,10 +1007,12 @@ impl InternalRelayPool {
         U: TryIntoUrl,
         Error: From<<U as TryIntoUrl>::Err>,
     {
-        let id: SubscriptionId = SubscriptionId::generate();
-        let output: Output<()> = self
+        let id: SubscriptionId = self.subscription_id(&opts).await?;
+        let output: Result<Output<()>, Error> = self
             .subscribe_with_id_to(urls, id.clone(), filters, opts)
-            .await?;
+            .await;
+        self.release_subscription_id(&id).await;
+        let output: Output<()> = output?;
         Ok(Output {
             val: id,
             success: output.success,
@@ -525,6 +1051,53 @@ impl InternalRelayPool {
             return Err(Error::NoRelays);
         }
 
+        // Set the global limit (or reset the one of a previous subscription with the same ID)
+        let done: Option<Arc<Notify>> = match opts.global_limit {
+            Some(0) => return Err(Error::ZeroGlobalLimit),
+            Some(max) => Some(self.global_limits.set(id.clone(), max).await),
+            None => {
+                self.global_limits.forget(&id).await;
+                None
+            }
+        };
+
+        let output: Result<Output<()>, Error> = self
+            .subscribe_relays(urls, relays, id.clone(), filters, opts.clone())
+            .await;
+
+        if let Some(done) = done {
+            if output.is_ok() {
+                // Close the subscription when the global limit is reached
+                let pool = self.clone();
+                let send_opts: RelaySendOptions = opts.send_opts;
+                thread::spawn(async move {
+                    done.notified().await;
+
+                    if pool.global_limits.is_reached(&id).await {
+                        tracing::debug!("Global limit reached for subscription '{id}'");
+                        pool.unsubscribe(id.clone(), send_opts).await;
+
+                        // Keep dropping the events in flight for a while, then forget the limit
+                        thread::sleep(GLOBAL_LIMIT_GRACE_PERIOD).await;
+                        pool.global_limits.forget_if(&id, &done).await;
+                    }
+                })?;
+            } else {
+                self.global_limits.forget_if(&id, &done).await;
+            }
+        }
+
+        output
+    }
+
+    async fn subscribe_relays(
+        &self,
+        urls: HashSet<Url>,
+        relays: HashMap<Url, Relay>,
+        id: SubscriptionId,
+        filters: Vec<Filter>,
+        opts: SubscribeOptions,
+    ) -> Result<Output<()>, Error> {
         // If passed only 1 url, not use threads
         if urls.len() == 1 {
             let url: Url = urls.into_iter().next().ok_or(Error::RelayNotFound)?;
@@ -540,17 +1113,45 @@ impl InternalRelayPool {
             let result: Arc<Mutex<Output<()>>> = Arc::new(Mutex::new(Output::default()));
             let mut handles: Vec<JoinHandle<()>> = Vec::with_capacity(urls.len());
 
+            // Bound the number of relays with an in-flight REQ, if requested
+            let semaphore: Option<Arc<Semaphore>> = opts
+                .concurrency_limit()
+                .map(|max| Arc::new(Semaphore::new(max.max(1))));
+
             // Subscribe
             for (url, relay) in relays.into_iter().filter(|(url, ..)| urls.contains(url)) {
                 let id: SubscriptionId = id.clone();
                 let filters: Vec<Filter> = filters.clone();
                 let result: Arc<Mutex<Output<()>>> = result.clone();
+                let semaphore: Option<Arc<Semaphore>> = semaphore.clone();
+                let opts: SubscribeOptions = opts.clone();
                 let handle: JoinHandle<()> = thread::spawn(async move {
-                    match relay.subscribe_with_id(id, filters, opts).await {
+                    // Wait for a free slot
+                    let permit: Option<OwnedSemaphorePermit> = match semaphore {
+                        Some(semaphore) => semaphore.acquire_owned().await.ok(),
+                        None => None,
+                    };
+
+                    // Subscribe to relay notifications before sending the REQ, to avoid to miss the EOSE
+                    let notifications = permit.as_ref().map(|_| relay.notifications());
+
+                    let timeout: Duration = opts.send_timeout();
+                    match relay.subscribe_with_id(id.clone(), filters, opts).await {
                         Ok(_) => {
                             // Success, insert relay url in 'success' set result
                             let mut result = result.lock().await;
                             result.success.insert(url);
+                            drop(result);
+
+                            // Keep the slot busy until EOSE (or timeout)
+                            if let (Some(permit), Some(notifications)) = (permit, notifications) {
+                                let _ = thread::spawn(release_on_eose(
+                                    notifications,
+                                    id,
+                                    timeout,
+                                    permit,
+                                ));
+                            }
                         }
                         Err(e) => {
                             tracing::error!("Impossible to subscribe to '{url}': {e}");
@@ -578,9 +1179,110 @@ impl InternalRelayPool {
         }
     }
 
+    pub async fn wait_for_eose(
+        &self,
+        id: &SubscriptionId,
+        timeout: Duration,
+    ) -> Result<Output<()>, Error> {
+        // Subscribe to notifications before collecting the relays, to avoid to miss some EOSE
+        let mut notifications = self.notifications();
+
+        // Collect relays participating in the subscription
+        let mut pending: HashSet<Url> = HashSet::new();
+        for (url, relay) in self.relays().await.into_iter() {
+            if relay.subscription(id).await.is_some() {
+                pending.insert(url);
+            }
+        }
+
+        if pending.is_empty() {
+            return Err(Error::NotSubscribed);
+        }
+
+        let mut output: Output<()> = Output::default();
+
+        time::timeout(Some(timeout), async {
+            while let Ok(notification) = notifications.recv().await {
+                match notification {
+                    RelayPoolNotification::Eose {
+                        relay_url,
+                        subscription_id,
+                    } => {
+                        if &subscription_id == id && pending.remove(&relay_url) {
+                            output.success.insert(relay_url);
+                        }
+                    }
+                    RelayPoolNotification::Shutdown => break,
+                    _ => (),
+                }
+
+                if pending.is_empty() {
+                    break;
+                }
+            }
+        })
+        .await;
+
+        // Relays that not sent the EOSE
+        for url in pending.into_iter() {
+            output
+                .failed
+                .insert(url, Some(String::from("EOSE not received")));
+        }
+
+        Ok(output)
+    }
+
+    pub async fn update_subscription(
+        &self,
+        id: SubscriptionId,
+        filters: Vec<Filter>,
+        opts: RelaySendOptions,
+    ) -> Result<Output<()>, Error> {
+        // Update pool subscriptions
+        if self.subscription(&id).await.is_some() {
+            self.update_pool_subscription(id.clone(), filters.clone())
+                .await;
+        }
+
+        let mut output: Output<()> = Output::default();
+
+        // Update only the relays participating in the subscription
+        for (url, relay) in self.relays().await.into_iter() {
+            if relay.subscription(&id).await.is_none() {
+                continue;
+            }
+
+            match relay
+                .update_subscription(id.clone(), filters.clone(), opts)
+                .await
+            {
+                Ok(()) => {
+                    output.success.insert(url);
+                }
+                Err(e) => {
+                    tracing::error!("Impossible to update subscription '{id}' of '{url}': {e}");
+                    output.failed.insert(url, Some(e.to_string()));
+                }
+            }
+        }
+
+        if output.success.is_empty() {
+            return Err(Error::NotSubscribed);
+        }
+
+        Ok(output)
+    }
+
     pub async fn unsubscribe(&self, id: SubscriptionId, opts: RelaySendOptions) {
+        // Keep the subscription if there are other consumers
+        if self.release_subscription(&id).await {
+            return;
+        }
+
         let relays = self.relays().await;
         self.remove_subscription(&id).await;
+        self.global_limits.remove(&id).await;
         for relay in relays.values() {
             if let Err(e) = relay.unsubscribe(id.clone(), opts).await {
                 tracing::error!("{e}");
@@ -591,6 +1293,7 @@ impl InternalRelayPool {
     pub async fn unsubscribe_all(&self, opts: RelaySendOptions) {
         let relays = self.relays().await;
         self.remove_all_subscriptions().await;
+        self.global_limits.clear().await;
         for relay in relays.values() {
             if let Err(e) = relay.unsubscribe_all(opts).await {
                 tracing::error!("{e}");
@@ -598,6 +1301,148 @@ impl InternalRelayPool {
         }
     }
 
+    pub async fn preferred_relay_for(&self, public_key: &PublicKey, kind: &Kind) -> Option<Url> {
+        let sticky_relays = self.sticky_relays.read().await;
+        sticky_relays
+            .get(&(*public_key, *kind))
+            .map(|(url, ..)| url.clone())
+    }
+
+    #[inline]
+    pub async fn get_events_of(
+        &self,
+        filters: Vec<Filter>,
+        timeout: Duration,
+        opts: FilterOptions,
+    ) -> Result<Vec<Event>, Error> {
+        Ok(self.fetch_events(filters, timeout, opts).await?.val)
+    }
+
+    pub async fn fetch_events(
+        &self,
+        filters: Vec<Filter>,
+        timeout: Duration,
+        opts: FilterOptions,
+    ) -> Result<Output<Vec<Event>>, Error> {
+        // Try to get the events from the preferred relay
+        if let Some(output) = self
+            .fetch_events_from_preferred_relay(&filters, timeout, opts)
+            .await
+        {
+            return Ok(output);
+        }
+
+        // Search filters (NIP-50) are sent only to the relays that support them (archive relays are skipped)
+        #[cfg(feature = "nip11")]
+        if filters.iter().any(|f| f.search.is_some()) {
+            let relays: HashMap<Url, Relay> = self.routine_relays().await;
+            let urls: HashSet<Url> = self.filter_supporting(relays, 50).await;
+            return self.fetch_events_from(urls, filters, timeout, opts).await;
+        }
+
+        let relays = self.routine_relays().await;
+        self.fetch_events_from(relays.into_keys(), filters, timeout, opts)
+            .await
+    }
+
+    /// Get events from the sticky relay, if enabled and if filters are targeting a single replaceable event.
+    ///
+    /// Return `None` if the query must be widened to all relays.
+    async fn fetch_events_from_preferred_relay(
+        &self,
+        filters: &[Filter],
+        timeout: Duration,
+        opts: FilterOptions,
+    ) -> Option<Output<Vec<Event>>> {
+        if !self.opts.sticky_relays {
+            return None;
+        }
+
+        let (public_key, kind) = sticky_target(filters)?;
+        let url: Url = self.preferred_relay_for(&public_key, &kind).await?;
+        let relay: Relay = self.internal_relay(&url).await.ok()?;
+
+        // Preferred relay is down: widen the query
+        if !relay.is_connected().await {
+            return None;
+        }
+
+        match self
+            .fetch_events_from([url.clone()], filters.to_vec(), timeout, opts)
+            .await
+        {
+            Ok(output) if !output.val.is_empty() => Some(output),
+            Ok(..) => None,
+            Err(e) => {
+                tracing::warn!("Failed to get events from preferred relay {url}: {e}");
+                None
+            }
+        }
+    }
+
+    pub async fn count_events(
+        &self,
+        filters: Vec<Filter>,
+        timeout: Duration,
+    ) -> Result<Output<HashMap<Url, usize>>, Error> {
+        let relays: HashMap<Url, Relay> = self.routine_relays().await;
+
+        if relays.is_empty() {
+            return Err(Error::NoRelays);
+        }
+
+        let result: Arc<Mutex<Output<HashMap<Url, usize>>>> =
+            Arc::new(Mutex::new(Output::default()));
+        let mut handles: Vec<JoinHandle<()>> = Vec::with_capacity(relays.len());
+
+        for (url, relay) in relays.into_iter() {
+            // Skip relays that not advertise NIP-45 support
+            #[cfg(feature = "nip11")]
+            {
+                let document = relay.document().await;
+                if !supports_nip(
+                    document.supported_nips.as_deref(),
+                    45,
+                    self.opts.include_unknown_nips.unwrap_or(false),
+                ) {
+                    let mut result = result.lock().await;
+                    result
+                        .failed
+                        .insert(url, Some(String::from("NIP-45 not supported")));
+                    continue;
+                }
+            }
+
+            let filters: Vec<Filter> = filters.clone();
+            let result: Arc<Mutex<Output<HashMap<Url, usize>>>> = result.clone();
+            let handle: JoinHandle<()> = thread::spawn(async move {
+                match relay.count_events_of(filters, timeout).await {
+                    Ok(count) => {
+                        // Success, insert count and relay url in 'success' set result
+                        let mut result = result.lock().await;
+                        result.val.insert(url.clone(), count);
+                        result.success.insert(url);
+                    }
+                    Err(e) => {
+                        tracing::error!("Impossible to count events from {url}: {e}");
+
+                        // Failed, insert relay url in 'failed' map result
+                        let mut result = result.lock().await;
+                        result.failed.insert(url, Some(e.to_string()));
+                    }
+                }
+            })?;
+            handles.push(handle);
+        }
+
+        for handle in handles.into_iter() {
+            handle.join().await?;
+        }
+
+        Ok(util::take_mutex_ownership(result).await)
+    }
+
+    #[inline]
     pub async fn get_events_from<I, U>(
         &self,
         urls: I,
@@ -605,6 +1450,24 @@ impl InternalRelayPool {
         timeout: Duration,
         opts: FilterOptions,
     ) -> Result<Vec<Event>, Error>
+    where
+        I: IntoIterator<Item = U>,
+        U: TryIntoUrl,
+        Error: From<<U as TryIntoUrl>::Err>,
+    {
+        Ok(self
+            .fetch_events_from(urls, filters, timeout, opts)
+            .await?
+            .val)
+    }
+
+    pub async fn fetch_events_from<I, U>(
+        &self,
+        urls: I,
+        filters: Vec<Filter>,
+        timeout: Duration,
+        opts: FilterOptions,
+    ) -> Result<Output<Vec<Event>>, Error>
     where
         I: IntoIterator<Item = U>,
         U: TryIntoUrl,
@@ -623,7 +1486,21 @@ impl InternalRelayPool {
         if urls.len() == 1 {
             let url: Url = urls.into_iter().next().ok_or(Error::RelayNotFound)?;
             let relay: Relay = self.internal_relay(&url).await?;
-            Ok(relay.get_events_of(filters, timeout, opts).await?)
+            let mut events: Vec<Event> = relay.get_events_of(filters, timeout, opts).await?;
+
+            if self.opts.sticky_relays {
+                for event in events.iter() {
+                    update_sticky_relay(&self.sticky_relays, &url, event).await;
+                }
+            }
+
+            self.opts.event_order.sort(&mut events);
+
+            Ok(Output {
+                val: events,
+                success: HashSet::from([url]),
+                failed: HashMap::new(),
+            })
         } else {
             let relays: HashMap<Url, Relay> = self.relays().await;
 
@@ -641,8 +1518,8 @@ impl InternalRelayPool {
             // Compose IDs and Events collections
             let ids: Arc<Mutex<HashSet<EventId>>> =
                 Arc::new(Mutex::new(stored_events.iter().map(|e| e.id()).collect()));
-            let events: Arc<Mutex<BTreeSet<Event>>> =
-                Arc::new(Mutex::new(stored_events.into_iter().collect()));
+            let events: Arc<Mutex<Vec<Event>>> = Arc::new(Mutex::new(stored_events));
+            let result: Arc<Mutex<Output<()>>> = Arc::new(Mutex::new(Output::default()));
 
             // Filter relays and start query
             let mut handles = Vec::with_capacity(urls.len());
@@ -650,19 +1527,37 @@ impl InternalRelayPool {
                 let filters = filters.clone();
                 let ids = ids.clone();
                 let events = events.clone();
+                let result = result.clone();
+                let sticky_relays: Option<StickyRelays> = if self.opts.sticky_relays {
+                    Some(self.sticky_relays.clone())
+                } else {
+                    None
+                };
                 let handle = thread::spawn(async move {
-                    if let Err(e) = relay
+                    let res = relay
                         .get_events_of_with_callback(filters, timeout, opts, |event| async {
+                            if let Some(sticky_relays) = &sticky_relays {
+                                update_sticky_relay(sticky_relays, &url, &event).await;
+                            }
+
                             let mut ids = ids.lock().await;
                             if !ids.contains(&event.id()) {
                                 let mut events = events.lock().await;
                                 ids.insert(event.id());
-                                events.insert(event);
+                                events.push(event);
                             }
                         })
-                        .await
-                    {
-                        tracing::error!("Failed to get events from {url}: {e}");
+                        .await;
+
+                    let mut result = result.lock().await;
+                    match res {
+                        Ok(()) => {
+                            result.success.insert(url);
+                        }
+                        Err(e) => {
+                            tracing::error!("Failed to get events from {url}: {e}");
+                            result.failed.insert(url, Some(e.to_string()));
+                        }
                     }
                 })?;
                 handles.push(handle);
@@ -673,22 +1568,144 @@ impl InternalRelayPool {
                 handle.join().await?;
             }
 
-            // Lock events, iterate set and revert order (events are sorted in ascending order in the BTreeSet)
-            let events: BTreeSet<Event> = events.lock().await.clone();
-            let iter: Rev<IntoIter<Event>> = events.into_iter().rev();
-
             // Check how many filters are passed and return the limit
             let limit: Option<usize> = match (filters.len(), filters.first()) {
                 (1, Some(filter)) => filter.limit,
                 _ => None,
             };
 
-            // Check limit
-            match limit {
-                Some(limit) => Ok(iter.take(limit).collect()),
-                None => Ok(iter.collect()),
+            // Keep the newest events and sort them
+            let mut events: Vec<Event> = util::take_mutex_ownership(events).await;
+            self.opts.event_order.truncate_and_sort(&mut events, limit);
+
+            let result: Output<()> = util::take_mutex_ownership(result).await;
+            Ok(Output {
+                val: events,
+                success: result.success,
+                failed: result.failed,
+            })
+        }
+    }
+
+    pub async fn reconnect_all(
+        &self,
+        connection_timeout: Duration,
+    ) -> HashMap<Url, Result<(), Error>> {
+        let relays: HashMap<Url, Relay> = self.relays().await;
+        let mut handles = Vec::with_capacity(relays.len());
+        let mut results: HashMap<Url, Result<(), Error>> = HashMap::with_capacity(relays.len());
+
+        for (url, relay) in relays.into_iter() {
+            match thread::spawn(async move {
+                let res = relay.reconnect(connection_timeout).await;
+                (url, res)
+            }) {
+                Ok(handle) => handles.push(handle),
+                Err(e) => tracing::error!("Impossible to spawn reconnection thread: {e}"),
             }
         }
+
+        for handle in handles.into_iter() {
+            match handle.join().await {
+                Ok((url, res)) => {
+                    if let Err(e) = &res {
+                        tracing::error!("Impossible to reconnect to '{url}': {e}");
+                    }
+                    results.insert(url, res.map_err(Error::from));
+                }
+                Err(e) => tracing::error!("Impossible to join thread: {e}"),
+            }
+        }
+
+        results
+    }
+
+    pub async fn migrate_subscriptions(
+        &self,
+        from: &Url,
+        to: &Url,
+        opts: RelaySendOptions,
+    ) -> Result<(), Error> {
+        if from == to {
+            return Ok(());
+        }
+
+        let from: Relay = self.internal_relay(from).await?;
+        let to: Relay = self.internal_relay(to).await?;
+
+        let subscriptions = from.inner.subscriptions_since_last_seen().await;
+        for (id, filters) in subscriptions.into_iter() {
+            tracing::info!(
+                "Migrating subscription '{id}' from '{}' to '{}'",
+                from.url(),
+                to.url()
+            );
+
+            // Subscribe to the new relay first, to avoid gaps
+            if let Err(e) = to
+                .subscribe_with_id(
+                    id.clone(),
+                    filters,
+                    SubscribeOptions::default().send_opts(opts),
+                )
+                .await
+            {
+                tracing::error!(
+                    "Impossible to migrate subscription '{id}' to '{}': {e}",
+                    to.url()
+                );
+                continue;
+            }
+
+            // The old relay is usually dead: the CLOSE may fail
+            if let Err(e) = from.unsubscribe(id.clone(), opts).await {
+                tracing::warn!(
+                    "Impossible to close subscription '{id}' on '{}': {e}",
+                    from.url()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn notify_network_changed(&self) {
+        let relays = self.relays.read().await;
+        for relay in relays.values() {
+            relay.inner.notify_network_changed().await;
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn ping_all(&self) -> HashMap<Url, Result<Duration, Error>> {
+        let relays: HashMap<Url, Relay> = self.relays().await;
+        let mut handles = Vec::with_capacity(relays.len());
+        let mut results: HashMap<Url, Result<Duration, Error>> =
+            HashMap::with_capacity(relays.len());
+
+        for (url, relay) in relays.into_iter() {
+            match thread::spawn(async move {
+                let res = relay.ping_now().await;
+                (url, res)
+            }) {
+                Ok(handle) => handles.push(handle),
+                Err(e) => tracing::error!("Impossible to spawn ping thread: {e}"),
+            }
+        }
+
+        for handle in handles.into_iter() {
+            match handle.join().await {
+                Ok((url, res)) => {
+                    if let Err(e) = &res {
+                        tracing::warn!("Impossible to ping '{url}': {e}");
+                    }
+                    results.insert(url, res.map_err(Error::from));
+                }
+                Err(e) => tracing::error!("Impossible to join thread: {e}"),
+            }
+        }
+
+        results
     }
 
     pub async fn connect(&self, connection_timeout: Option<Duration>) {
@@ -697,8 +1714,21 @@ impl InternalRelayPool {
         if connection_timeout.is_some() {
             let mut handles = Vec::with_capacity(relays.len());
 
+            // Bound the number of relays that are connecting at the same time, if requested
+            let semaphore: Option<Arc<Semaphore>> = self
+                .opts
+                .connect_concurrency
+                .map(|max| Arc::new(Semaphore::new(max.max(1))));
+
             for relay in relays.into_values() {
+                let semaphore: Option<Arc<Semaphore>> = semaphore.clone();
                 let handle = thread::spawn(async move {
+                    // Wait for a free slot (released when the connection attempt completes)
+                    let _permit: Option<OwnedSemaphorePermit> = match semaphore {
+                        Some(semaphore) => semaphore.acquire_owned().await.ok(),
+                        None => None,
+                    };
+
                     relay.connect(connection_timeout).await;
                 });
                 handles.push(handle);
@@ -729,8 +1759,8 @@ impl InternalRelayPool {
         &self,
         filter: Filter,
         opts: NegentropyOptions,
-    ) -> Result<Output<()>, Error> {
-        let relays: HashMap<Url, Relay> = self.relays().await;
+    ) -> Result<Output<Reconciliation>, Error> {
+        let relays: HashMap<Url, Relay> = self.routine_relays().await;
         self.reconcile_with(relays.into_keys(), filter, opts).await
     }
 
@@ -740,15 +1770,21 @@ impl InternalRelayPool {
         urls: I,
         filter: Filter,
         opts: NegentropyOptions,
-    ) -> Result<Output<()>, Error>
+    ) -> Result<Output<Reconciliation>, Error>
     where
         I: IntoIterator<Item = U>,
         U: TryIntoUrl,
         Error: From<<U as TryIntoUrl>::Err>,
     {
+        let urls: HashSet<Url> = urls
+            .into_iter()
+            .map(|u| u.try_into_url())
+            .collect::<Result<_, _>>()?;
+        let filter: Filter = opts.constrain_filter(filter);
         let items: Vec<(EventId, Timestamp)> =
             self.database.negentropy_items(filter.clone()).await?;
-        self.reconcile_advanced(urls, filter, items, opts).await
+        self.reconcile_targeted(urls, filter, items, opts, None, None)
+            .await
     }
 
     #[inline]
@@ -757,19 +1793,20 @@ impl InternalRelayPool {
         filter: Filter,
         items: Vec<(EventId, Timestamp)>,
         opts: NegentropyOptions,
-    ) -> Result<Output<()>, Error> {
-        let relays: HashMap<Url, Relay> = self.relays().await;
+    ) -> Result<Output<Reconciliation>, Error> {
+        let relays: HashMap<Url, Relay> = self.routine_relays().await;
         self.reconcile_advanced(relays.into_keys(), filter, items, opts)
             .await
     }
 
+    #[inline]
     pub async fn reconcile_advanced<I, U>(
         &self,
         urls: I,
         filter: Filter,
         items: Vec<(EventId, Timestamp)>,
         opts: NegentropyOptions,
-    ) -> Result<Output<()>, Error>
+    ) -> Result<Output<Reconciliation>, Error>
     where
         I: IntoIterator<Item = U>,
         U: TryIntoUrl,
@@ -779,7 +1816,58 @@ impl InternalRelayPool {
             .into_iter()
             .map(|u| u.try_into_url())
             .collect::<Result<_, _>>()?;
+        let filter: Filter = opts.constrain_filter(filter);
+        self.reconcile_targeted(urls, filter, items, opts, None, None)
+            .await
+    }
 
+    pub async fn reconcile_with_callback<F>(
+        &self,
+        filter: Filter,
+        opts: NegentropyOptions,
+        on_item: F,
+    ) -> Result<Output<Reconciliation>, Error>
+    where
+        F: Fn(&Url, ReconcileItem) + Send + Sync + 'static,
+    {
+        let urls: HashSet<Url> = self.routine_relays().await.into_keys().collect();
+        let filter: Filter = opts.constrain_filter(filter);
+        let items: Vec<(EventId, Timestamp)> =
+            self.database.negentropy_items(filter.clone()).await?;
+        self.reconcile_targeted(urls, filter, items, opts, Some(Arc::new(on_item)), None)
+            .await
+    }
+
+    pub fn reconcile_cancellable(
+        &self,
+        filter: Filter,
+        opts: NegentropyOptions,
+    ) -> Result<SyncHandle<Output<Reconciliation>, Error>, Error> {
+        let (abort, abort_rx) = watch::channel(false);
+        let pool = self.clone();
+        let handle = thread::spawn(async move {
+            let urls: HashSet<Url> = pool.routine_relays().await.into_keys().collect();
+            let filter: Filter = opts.constrain_filter(filter);
+            let items: Vec<(EventId, Timestamp)> =
+                pool.database.negentropy_items(filter.clone()).await?;
+            pool.reconcile_targeted(urls, filter, items, opts, None, Some(abort_rx))
+                .await
+        })?;
+        Ok(SyncHandle::new(abort, handle))
+    }
+
+    /// Reconcile with the relays
+    ///
+    /// The filter must be already constrained (see [`NegentropyOptions::metadata_only`]).
+    async fn reconcile_targeted(
+        &self,
+        urls: HashSet<Url>,
+        filter: Filter,
+        items: Vec<(EventId, Timestamp)>,
+        opts: NegentropyOptions,
+        on_item: Option<Arc<ReconcileCallback>>,
+        abort: Option<watch::Receiver<bool>>,
+    ) -> Result<Output<Reconciliation>, Error> {
         // Check if urls set is empty
         if urls.is_empty() {
             return Err(Error::NoRelaysSpecified);
@@ -788,8 +1876,24 @@ impl InternalRelayPool {
         if urls.len() == 1 {
             let url: Url = urls.into_iter().next().ok_or(Error::RelayNotFound)?;
             let relay: Relay = self.internal_relay(&url).await?;
-            relay.reconcile_with_items(filter, items, opts).await?;
-            Ok(Output::success(url))
+            let reconciliation: Reconciliation = relay
+                .inner
+                .reconcile_with_items_and_callback(
+                    filter,
+                    items,
+                    opts,
+                    |item| {
+                        if let Some(on_item) = &on_item {
+                            on_item(&url, item);
+                        }
+                    },
+                    abort,
+                )
+                .await?;
+            let mut output: Output<Reconciliation> = Output::default();
+            output.val = reconciliation;
+            output.success.insert(url);
+            Ok(output)
         } else {
             let relays: HashMap<Url, Relay> = self.relays().await;
 
@@ -799,18 +1903,31 @@ impl InternalRelayPool {
             }
 
             // Filter relays and start query
-            let result: Arc<Mutex<Output<()>>> = Arc::new(Mutex::new(Output::default()));
+            let result: Arc<Mutex<Output<Reconciliation>>> =
+                Arc::new(Mutex::new(Output::default()));
             let mut handles: Vec<JoinHandle<()>> = Vec::with_capacity(urls.len());
             for (url, relay) in relays.into_iter().filter(|(url, ..)| urls.contains(url)) {
                 let filter: Filter = filter.clone();
                 let my_items: Vec<(EventId, Timestamp)> = items.clone();
-                let result: Arc<Mutex<Output<()>>> = result.clone();
+                let result: Arc<Mutex<Output<Reconciliation>>> = result.clone();
+                let on_item: Option<Arc<ReconcileCallback>> = on_item.clone();
+                let abort: Option<watch::Receiver<bool>> = abort.clone();
                 let handle: JoinHandle<()> = thread::spawn(async move {
-                    match relay.reconcile_with_items(filter, my_items, opts).await {
-                        Ok(_) => {
-                            // Success, insert relay url in 'success' set result
+                    let callback = |item| {
+                        if let Some(on_item) = &on_item {
+                            on_item(&url, item);
+                        }
+                    };
+                    let res: Result<Reconciliation, RelayError> = relay
+                        .inner
+                        .reconcile_with_items_and_callback(filter, my_items, opts, callback, abort)
+                        .await;
+                    match res {
+                        Ok(reconciliation) => {
+                            // Success, insert relay url in 'success' set result and merge the diff
                             let mut result = result.lock().await;
                             result.success.insert(url);
+                            result.val.merge(reconciliation);
                         }
                         Err(e) => {
                             tracing::error!("Failed to get reconcile with {url}: {e}");
@@ -828,7 +1945,7 @@ impl InternalRelayPool {
                 handle.join().await?;
             }
 
-            let result: Output<()> = util::take_mutex_ownership(result).await;
+            let result: Output<Reconciliation> = util::take_mutex_ownership(result).await;
 
             if result.success.is_empty() {
                 return Err(Error::NegentropyReconciliationFailed);
@@ -838,3 +1955,222 @@ impl InternalRelayPool {
         }
     }
 }
+
+/// Hold the concurrency `permit` until the relay sends the `EOSE` for the subscription (or until timeout)
+async fn release_on_eose(
+    mut notifications: broadcast::Receiver<RelayNotification>,
+    id: SubscriptionId,
+    timeout: Duration,
+    permit: OwnedSemaphorePermit,
+) {
+    time::timeout(Some(timeout), async {
+        while let Ok(notification) = notifications.recv().await {
+            match notification {
+                RelayNotification::Message {
+                    message: RelayMessage::EndOfStoredEvents(subscription_id),
+                } => {
+                    if subscription_id == id {
+                        break;
+                    }
+                }
+                RelayNotification::Shutdown => break,
+                _ => (),
+            }
+        }
+    })
+    .await;
+
+    drop(permit);
+}
+
+/// Check if two filter sets are equal, regardless of the order
+fn same_filters(a: &[Filter], b: &[Filter]) -> bool {
+    a.len() == b.len() && a.iter().all(|f| b.contains(f)) && b.iter().all(|f| a.contains(f))
+}
+
+/// Get the `(author, kind)` pair if filters are targeting a single replaceable event
+fn sticky_target(filters: &[Filter]) -> Option<(PublicKey, Kind)> {
+    match filters {
+        [filter] => {
+            let authors = filter.authors.as_ref()?;
+            let kinds = filter.kinds.as_ref()?;
+
+            if authors.len() != 1 || kinds.len() != 1 {
+                return None;
+            }
+
+            let public_key: PublicKey = *authors.iter().next()?;
+            let kind: Kind = *kinds.iter().next()?;
+
+            if kind.is_replaceable() {
+                Some((public_key, kind))
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Save `url` as preferred relay if it delivered a newer version of a replaceable event
+async fn update_sticky_relay(sticky_relays: &StickyRelays, url: &Url, event: &Event) {
+    if !event.is_replaceable() {
+        return;
+    }
+
+    let mut sticky_relays = sticky_relays.write().await;
+    let key: (PublicKey, Kind) = (event.author(), event.kind());
+    let is_newer: bool = match sticky_relays.get(&key) {
+        Some((_, created_at)) => event.created_at() > *created_at,
+        None => true,
+    };
+
+    if is_newer {
+        sticky_relays.insert(key, (url.clone(), event.created_at()));
+    }
+}
+
+/// Check if a relay supports a NIP, by the `supported_nips` field of its NIP-11 document
+///
+/// `include_unknown` is returned if the relay doesn't advertise the supported NIPs.
+#[cfg(feature = "nip11")]
+fn supports_nip(supported_nips: Option<&[u16]>, nip: u16, include_unknown: bool) -> bool {
+    match supported_nips {
+        Some(nips) => nips.contains(&nip),
+        None => include_unknown,
+    }
+}
+
+struct PruneCandidate {
+    url: Url,
+    score: f64,
+    read: bool,
+    write: bool,
+}
+
+/// Get the relays with a score lower than `threshold`, the lowest first,
+/// without removing the last `READ` or the last `WRITE` relay.
+fn prunable_relays(mut candidates: Vec<PruneCandidate>, threshold: f64) -> HashSet<Url> {
+    let mut reads: usize = candidates.iter().filter(|c| c.read).count();
+    let mut writes: usize = candidates.iter().filter(|c| c.write).count();
+
+    candidates.sort_by(|a, b| a.score.total_cmp(&b.score));
+
+    let mut urls: HashSet<Url> = HashSet::new();
+    for candidate in candidates.into_iter() {
+        if candidate.score >= threshold {
+            break;
+        }
+
+        if (candidate.read && reads <= 1) || (candidate.write && writes <= 1) {
+            continue;
+        }
+
+        if candidate.read {
+            reads -= 1;
+        }
+
+        if candidate.write {
+            writes -= 1;
+        }
+
+        urls.insert(candidate.url);
+    }
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr::FromBech32;
+
+    use super::*;
+
+    const PUBLIC_KEY: &str = "npub1drvpzev3syqt0kjrls50050uzf25gehpz9vgdw08hvex7e0vgfeq0eseet";
+
+    #[test]
+    fn test_same_filters() {
+        let public_key = PublicKey::from_bech32(PUBLIC_KEY).unwrap();
+        let a = Filter::new().author(public_key).kind(Kind::TextNote);
+        let b = Filter::new().kind(Kind::Metadata).limit(10);
+
+        assert!(same_filters(
+            &[a.clone(), b.clone()],
+            &[b.clone(), a.clone()]
+        ));
+        assert!(!same_filters(&[a.clone()], &[a.clone(), b.clone()]));
+        assert!(!same_filters(&[a.clone(), a], &[b.clone(), b]));
+    }
+
+    #[test]
+    fn test_sticky_target() {
+        let public_key = PublicKey::from_bech32(PUBLIC_KEY).unwrap();
+
+        // Single replaceable event
+        let filter = Filter::new().author(public_key).kind(Kind::Metadata);
+        assert_eq!(
+            sticky_target(&[filter.clone()]),
+            Some((public_key, Kind::Metadata))
+        );
+
+        // Not replaceable
+        let not_replaceable = Filter::new().author(public_key).kind(Kind::TextNote);
+        assert_eq!(sticky_target(&[not_replaceable]), None);
+
+        // Missing author
+        let no_author = Filter::new().kind(Kind::ContactList);
+        assert_eq!(sticky_target(&[no_author]), None);
+
+        // Multiple filters
+        assert_eq!(sticky_target(&[filter.clone(), filter]), None);
+    }
+
+    #[test]
+    #[cfg(feature = "nip11")]
+    fn test_supports_nip() {
+        let nips: &[u16] = &[1, 11, 50];
+        assert!(supports_nip(Some(nips), 50, false));
+        assert!(!supports_nip(Some(nips), 45, true));
+        assert!(!supports_nip(Some(&[]), 50, true));
+
+        // Unknown
+        assert!(supports_nip(None, 50, true));
+        assert!(!supports_nip(None, 50, false));
+    }
+
+    #[test]
+    fn test_prunable_relays() {
+        let candidate = |url: &str, score: f64, read: bool, write: bool| PruneCandidate {
+            url: Url::parse(url).unwrap(),
+            score,
+            read,
+            write,
+        };
+
+        let candidates = vec![
+            candidate("wss://a.com", 0.9, true, true),
+            candidate("wss://b.com", 0.2, true, false),
+            candidate("wss://c.com", 0.4, false, true),
+            candidate("wss://d.com", 0.1, false, false),
+        ];
+        let urls = prunable_relays(candidates, 0.5);
+        assert_eq!(urls.len(), 3);
+        assert!(!urls.contains(&Url::parse("wss://a.com").unwrap()));
+
+        // Never remove the last READ or WRITE relay
+        let candidates = vec![
+            candidate("wss://a.com", 0.1, true, false),
+            candidate("wss://b.com", 0.2, true, true),
+            candidate("wss://c.com", 0.3, false, true),
+        ];
+        let urls = prunable_relays(candidates, 0.5);
+        assert_eq!(
+            urls,
+            [
+                Url::parse("wss://a.com").unwrap(),
+                Url::parse("wss://c.com").unwrap()
+            ]
+            .into_iter()
+            .collect()
+        );
+    }
+}
@@ -4,30 +4,45 @@
 
 //! Relay Pool
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
 use std::sync::Arc;
 use std::time::Duration;
 
 use atomic_destructor::{AtomicDestructor, StealthClone};
 use nostr::{
-    ClientMessage, Event, EventId, Filter, RelayMessage, Result, SubscriptionId, Timestamp,
-    TryIntoUrl, Url,
+    ClientMessage, Event, EventId, Filter, Kind, PublicKey, RelayMessage, Result, SubscriptionId,
+    Timestamp, TryIntoUrl, Url,
 };
 use nostr_database::{DynNostrDatabase, IntoNostrDatabase, MemoryDatabase};
-use tokio::sync::broadcast;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
 
 mod error;
+mod filtered;
+mod global_limit;
+mod handle;
 mod internal;
 pub mod options;
 mod result;
+mod status;
 
 pub use self::error::Error;
+pub(crate) use self::filtered::FilteredNotifications;
+pub(crate) use self::global_limit::GlobalLimits;
+pub use self::handle::{SubscriptionHandle, SubscriptionStream};
 use self::internal::InternalRelayPool;
-pub use self::options::RelayPoolOptions;
-pub use self::result::Output;
+pub use self::options::{
+    AddRelayBehavior, EventOrder, EventOverflowPolicy, InvalidSigPolicy, RelayMatcher, RelayPolicy,
+    RelayPoolOptions, RelayScoreWeights,
+};
+pub use self::result::{Output, PublishOutput, ReconcileItem, Reconciliation};
+pub use self::status::RelayStatusReceiver;
 use crate::relay::options::{FilterOptions, NegentropyOptions, RelayOptions, RelaySendOptions};
-use crate::relay::{Relay, RelayBlacklist, RelayStatus};
+use crate::relay::{
+    EventValidator, MessageDirection, MessageTracer, Relay, RelayBlacklist, RelayConnectionStats,
+    RelayStats, RelayStatus, SyncHandle,
+};
 use crate::SubscribeOptions;
 
 /// Relay Pool Notification
@@ -49,6 +64,11 @@ pub enum RelayPoolNotification {
         /// Relay Message
         message: RelayMessage,
     },
+    /// Relay capabilities prefetched (see [`RelayOptions::warm_up`])
+    RelayReady {
+        /// Relay url
+        relay_url: Url,
+    },
     /// Relay status changed
     RelayStatus {
         /// Relay url
@@ -56,6 +76,62 @@ pub enum RelayPoolNotification {
         /// Relay Status
         status: RelayStatus,
     },
+    /// Reconnection triggered by [`RelayPool::notify_network_changed`]
+    NetworkReconnect {
+        /// Relay url
+        relay_url: Url,
+    },
+    /// Received `EOSE` from a relay
+    ///
+    /// Sent in addition to the [`RelayPoolNotification::Message`] variant.
+    Eose {
+        /// Relay url
+        relay_url: Url,
+        /// Subscription ID
+        subscription_id: SubscriptionId,
+    },
+    /// Subscription re-sent to a relay after a reconnection
+    Resubscribed {
+        /// Relay url
+        relay_url: Url,
+        /// Subscription ID
+        subscription_id: SubscriptionId,
+    },
+    /// Event rejected by the custom validator (see [`RelayPool::set_validator`]),
+    /// missing a required tag (see [`RelayPoolOptions::require_tags`])
+    /// or out of the [`RelayOptions::max_event_age`] and [`RelayOptions::max_future_drift`] bounds
+    Rejected {
+        /// Relay url
+        relay_url: Url,
+        /// Event ID
+        id: EventId,
+        /// Rejection reason
+        reason: String,
+    },
+    /// Received an event with invalid signature
+    ///
+    /// Sent only if enabled by [`RelayPoolOptions::on_invalid_signature`].
+    InvalidSignature {
+        /// Relay url
+        relay_url: Url,
+        /// Event ID
+        id: EventId,
+    },
+    /// Events of a relay dropped because the consumers are falling behind
+    ///
+    /// Sent once when the notification channel becomes full, only if enabled by [`RelayPoolOptions::event_overflow_policy`].
+    EventDropped {
+        /// Relay url
+        relay_url: Url,
+    },
+    /// Notifications dropped because the consumer is falling behind
+    ///
+    /// The oldest notifications are dropped when the channel is full (see [`RelayPoolOptions::notification_channel_size`]).
+    /// Delivered only by [`RelayPool::handle_notifications`].
+    NotificationsDropped {
+        /// Number of dropped notifications
+        count: u64,
+    },
     /// Shutdown
     Shutdown,
 }
@@ -107,12 +183,43 @@ impl RelayPool {
 
     /// Get new **pool** notification listener
     ///
+    /// Events of the same relay are notified in receive order (per-relay FIFO),
+    /// while the events of different relays are processed in parallel and may be interleaved in any order.
+    ///
     /// <div class="warning">When you call this method, you subscribe to the notifications channel from that precise moment. Anything received by relay/s before that moment is not included in the channel!</div>
     #[inline]
     pub fn notifications(&self) -> broadcast::Receiver<RelayPoolNotification> {
         self.inner.notifications()
     }
 
+    /// Get new **pool** notification listener, receiving only the notifications matching the `predicate`
+    ///
+    /// The predicate is checked before cloning the notification for the listener:
+    /// with [`RelayPool::notifications`], every listener clones every notification (events included)
+    /// also if then discarded by the handler, so prefer this when only few notifications are relevant
+    /// (i.e. events of a single kind).
+    ///
+    /// If the listener is not consumed fast enough and the channel is full (see [`RelayPoolOptions::notification_channel_size`]),
+    /// the new matching notifications are dropped for it. The listener is removed when the receiver is dropped.
+    ///
+    /// <div class="warning">When you call this method, you subscribe to the notifications channel from that precise moment. Anything received by relay/s before that moment is not included in the channel!</div>
+    #[inline]
+    pub async fn subscribe_filtered<F>(&self, predicate: F) -> mpsc::Receiver<RelayPoolNotification>
+    where
+        F: Fn(&RelayPoolNotification) -> bool + Send + Sync + 'static,
+    {
+        self.inner.subscribe_filtered(predicate).await
+    }
+
+    /// Get relay status changes listener
+    ///
+    /// Lighter than [`RelayPool::notifications`] when only the connection status is needed (i.e. for a connection indicator).
+    /// The current status of every relay is delivered first, followed by the status changes.
+    #[inline]
+    pub async fn status_stream(&self) -> RelayStatusReceiver {
+        self.inner.status_stream().await
+    }
+
     /// Get database
     #[inline]
     pub fn database(&self) -> Arc<DynNostrDatabase> {
@@ -125,12 +232,192 @@ impl RelayPool {
         self.inner.blacklist()
     }
 
+    /// Mute public key
+    ///
+    /// Events authored by muted public keys are dropped, for all relays, without notifications.
+    #[inline]
+    pub async fn mute(&self, public_key: PublicKey) {
+        self.inner.mutes().add_public_keys([public_key]).await
+    }
+
+    /// Unmute public key
+    #[inline]
+    pub async fn unmute(&self, public_key: &PublicKey) {
+        self.inner.mutes().remove_public_key(public_key).await
+    }
+
+    /// Mute words or phrases
+    ///
+    /// Events with content containing a muted word (case-insensitive) are dropped, for all relays, without notifications.
+    #[inline]
+    pub async fn mute_words<I, S>(&self, words: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.inner.mutes().add_words(words).await
+    }
+
+    /// Unmute words or phrases
+    #[inline]
+    pub async fn unmute_words<I, S>(&self, words: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.inner.mutes().remove_words(words).await
+    }
+
     /// Get relays
     #[inline]
     pub async fn relays(&self) -> HashMap<Url, Relay> {
         self.inner.relays().await
     }
 
+    /// Get the number of received events per kind, aggregated for all relays
+    ///
+    /// Use [`Relay::kind_histogram`] for the per-relay histogram.
+    #[inline]
+    pub async fn kind_histogram(&self) -> HashMap<Kind, u64> {
+        self.inner.kind_histogram().await
+    }
+
+    /// Reset the number of received events per kind of all relays
+    #[inline]
+    pub async fn reset_kind_histogram(&self) {
+        self.inner.reset_kind_histogram().await
+    }
+
+    /// Get archive relays
+    ///
+    /// Archive relays are skipped by subscriptions and queries sent to all relays (see [`RelayOptions::archive`]):
+    /// target them explicitly for historical queries (i.e. [`RelayPool::get_events_from`]).
+    #[inline]
+    pub async fn archive_relays(&self) -> HashMap<Url, Relay> {
+        self.inner.archive_relays().await
+    }
+
+    /// Get relays that belong to a group
+    ///
+    /// Groups are set with [`RelayOptions::group`].
+    #[inline]
+    pub async fn relays_in_group(&self, group: &str) -> HashMap<Url, Relay> {
+        self.inner.relays_in_group(group).await
+    }
+
+    /// Get relays that support a NIP, according to their NIP-11 document
+    ///
+    /// Relays that don't advertise the supported NIPs are included by default (see [`RelayPoolOptions::include_unknown_nips`]).
+    #[cfg(feature = "nip11")]
+    #[inline]
+    pub async fn relays_supporting(&self, nip: u16) -> HashSet<Url> {
+        self.inner.relays_supporting(nip).await
+    }
+
+    /// Get connected relays with an events throughput lower than `below` events per second
+    ///
+    /// The throughput is calculated over [`RelayPoolOptions::throughput_window`].
+    #[inline]
+    pub async fn slow_relays(&self, below: f64) -> HashSet<Url> {
+        self.inner.slow_relays(below).await
+    }
+
+    /// Handle events as if they were received from a relay
+    ///
+    /// Events go through the same checks (limits, blacklist, deletion, signature, custom validator),
+    /// the deduplication and the notifications of the events received from relays,
+    /// with `injected` as subscription ID.
+    /// If the relay isn't in the pool, the events are attributed to a virtual relay (not added to the pool).
+    ///
+    /// Useful to test app logic without network.
+    #[inline]
+    pub async fn inject_events<U>(&self, relay_url: U, events: Vec<Event>) -> Result<(), Error>
+    where
+        U: TryIntoUrl,
+        Error: From<<U as TryIntoUrl>::Err>,
+    {
+        self.inner.inject_events(relay_url, events).await
+    }
+
+    /// Get relay scores
+    ///
+    /// The score, in the `0.0..=1.0` range, combines the average latency, the reconnections, the uptime
+    /// and the events throughput, weighted by [`RelayPoolOptions::score_weights`].
+    #[inline]
+    pub async fn scores(&self) -> HashMap<Url, f64> {
+        self.inner.scores().await
+    }
+
+    /// Remove relays with a score lower than `threshold` (see [`RelayPool::scores`])
+    ///
+    /// The last `READ` and the last `WRITE` relays are never removed.
+    ///
+    /// Return the removed relays.
+    #[inline]
+    pub async fn prune_low_scoring(&self, threshold: f64) -> Result<HashSet<Url>, Error> {
+        self.inner.prune_low_scoring(threshold).await
+    }
+
+    /// Set a custom event validator, applied to the events received by all relays
+    ///
+    /// The validator is called after the built-in checks (size and tags limits, blacklist, POW, deletion),
+    /// the deduplication bookkeeping and the signature verification, but before saving the event into the database
+    /// and sending the notifications.
+    /// An event rejected by the validator is dropped and a [`RelayPoolNotification::Rejected`] is sent.
+    #[inline]
+    pub async fn set_validator<F>(&self, validator: F)
+    where
+        F: Fn(&Event) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.inner
+            .set_validator(EventValidator::new(validator))
+            .await
+    }
+
+    /// Set a tracer called for every raw JSON message sent to or received from relays, before parsing
+    ///
+    /// Useful to debug relay interoperability. Use [`RelayPool::remove_message_tracer`] to remove it.
+    ///
+    /// <div class="warning">The traced messages may contain sensitive data (i.e. `AUTH` events): don't log them in production!</div>
+    #[inline]
+    pub async fn set_message_tracer<F>(&self, tracer: F)
+    where
+        F: Fn(&Url, MessageDirection, &str) + Send + Sync + 'static,
+    {
+        self.inner
+            .set_message_tracer(Some(MessageTracer::new(tracer)))
+            .await
+    }
+
+    /// Remove message tracer
+    #[inline]
+    pub async fn remove_message_tracer(&self) {
+        self.inner.set_message_tracer(None).await
+    }
+
+    /// Get [`RelayConnectionStats`] of all relays
+    ///
+    /// The reconnection state is available with [`RelayConnectionStats::retry_state`].
+    #[inline]
+    pub async fn stats(&self) -> HashMap<Url, RelayConnectionStats> {
+        self.inner.stats().await
+    }
+
+    /// Take a snapshot of the stats of all relays (see [`Relay::stats_snapshot`])
+    #[inline]
+    pub async fn stats_snapshot(&self) -> HashMap<Url, RelayStats> {
+        self.inner.stats_snapshot().await
+    }
+
+    /// Ping all relays and measure the round-trip time
+    ///
+    /// Return the result for every relay.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[inline]
+    pub async fn ping_all(&self) -> HashMap<Url, Result<Duration, Error>> {
+        self.inner.ping_all().await
+    }
+
     /// Get [`Relay`]
     #[inline]
     pub async fn relay<U>(&self, url: U) -> Result<Relay, Error>
@@ -141,6 +428,19 @@ impl RelayPool {
         self.inner.relay(url).await
     }
 
+    /// Get the timestamp of the newest event seen from a relay for the filters
+    ///
+    /// Used to advance `since` on re-subscription (see [`RelayOptions::resubscribe_advance_since`]).
+    /// Check [`Relay::last_seen`] for more details.
+    pub async fn last_seen<U>(&self, url: U, filters: &[Filter]) -> Result<Option<Timestamp>, Error>
+    where
+        U: TryIntoUrl,
+        Error: From<<U as TryIntoUrl>::Err>,
+    {
+        let relay: Relay = self.relay(url).await?;
+        Ok(relay.last_seen(filters).await)
+    }
+
     /// Add new relay
     ///
     /// If are set pool subscriptions, the new added relay will inherit them. Use `subscribe_to` method instead of `subscribe`,
@@ -151,7 +451,26 @@ impl RelayPool {
         U: TryIntoUrl,
         Error: From<<U as TryIntoUrl>::Err>,
     {
-        self.inner.add_relay(url, opts).await
+        self.inner
+            .add_relay(url, opts, AddRelayBehavior::Ignore)
+            .await
+    }
+
+    /// Add new relay, choosing the [`AddRelayBehavior`] if the relay is already in the pool
+    ///
+    /// Return `true` only if the relay has been added.
+    #[inline]
+    pub async fn add_relay_with_behavior<U>(
+        &self,
+        url: U,
+        opts: RelayOptions,
+        behavior: AddRelayBehavior,
+    ) -> Result<bool, Error>
+    where
+        U: TryIntoUrl,
+        Error: From<<U as TryIntoUrl>::Err>,
+    {
+        self.inner.add_relay(url, opts, behavior).await
     }
 
     /// Disconnect and remove relay
@@ -164,6 +483,22 @@ impl RelayPool {
         self.inner.remove_relay(url).await
     }
 
+    /// Remove relay, waiting for its active subscriptions to receive the `EOSE`
+    ///
+    /// The relay is removed from the pool immediately, so new subscriptions are not sent to it,
+    /// while the events received during the drain are still notified.
+    /// The relay is disconnected as soon as all subscriptions received the `EOSE`, or when the `timeout` elapses.
+    ///
+    /// Return `true` if the drain completed, `false` if timed out.
+    #[inline]
+    pub async fn remove_relay_graceful<U>(&self, url: U, timeout: Duration) -> Result<bool, Error>
+    where
+        U: TryIntoUrl,
+        Error: From<<U as TryIntoUrl>::Err>,
+    {
+        self.inner.remove_relay_graceful(url, timeout).await
+    }
+
     /// Disconnect and remove all relays
     #[inline]
     pub async fn remove_all_relays(&self) -> Result<(), Error> {
@@ -176,6 +511,55 @@ impl RelayPool {
         self.inner.connect(connection_timeout).await
     }
 
+    /// Force reconnection of all relays
+    ///
+    /// Close every connection, reset the retry state of each relay and immediately try to re-establish it
+    /// (i.e. after a network change), without waiting for the auto-reconnect loops.
+    ///
+    /// Return the result for every relay.
+    #[inline]
+    pub async fn reconnect_all(
+        &self,
+        connection_timeout: Duration,
+    ) -> HashMap<Url, Result<(), Error>> {
+        self.inner.reconnect_all(connection_timeout).await
+    }
+
+    /// Move the active subscriptions of a relay to another one (i.e. failover of a degraded relay)
+    ///
+    /// The subscriptions are re-sent to `to`, with the same IDs and the `since` advanced to the newest event seen from `from`
+    /// (tracked only if [`RelayOptions::resubscribe_advance_since`] is enabled), and then closed on `from`. Already received events are not notified again.
+    ///
+    /// The subscriptions that can't be sent to `to` are kept on `from` and the errors on `from` are ignored:
+    /// the migration continues with the other subscriptions.
+    pub async fn migrate_subscriptions<U, V>(
+        &self,
+        from: U,
+        to: V,
+        opts: RelaySendOptions,
+    ) -> Result<(), Error>
+    where
+        U: TryIntoUrl,
+        V: TryIntoUrl,
+        Error: From<<U as TryIntoUrl>::Err>,
+        Error: From<<V as TryIntoUrl>::Err>,
+    {
+        let from: Url = from.try_into_url()?;
+        let to: Url = to.try_into_url()?;
+        self.inner.migrate_subscriptions(&from, &to, opts).await
+    }
+
+    /// Notify a change of the system network (i.e. wifi to cellular)
+    ///
+    /// Dead connections are retried immediately, resetting their retry backoff, without waiting for the `retry_interval`.
+    /// Healthy connections are left alone.
+    ///
+    /// A [`RelayPoolNotification::NetworkReconnect`] is sent for every reconnected relay.
+    #[inline]
+    pub async fn notify_network_changed(&self) {
+        self.inner.notify_network_changed().await
+    }
+
     /// Disconnect from all relays
     #[inline]
     pub async fn disconnect(&self) -> Result<(), Error> {
@@ -286,6 +670,17 @@ impl RelayPool {
         self.inner.batch_event(events, opts).await
     }
 
+    /// Send event to all the relays of a **group** and wait for `OK` message
+    pub async fn send_event_to_group(
+        &self,
+        group: &str,
+        event: Event,
+        opts: RelaySendOptions,
+    ) -> Result<Output<EventId>, Error> {
+        let relays: HashMap<Url, Relay> = self.relays_in_group(group).await;
+        self.send_event_to(relays.into_keys(), event, opts).await
+    }
+
     /// Send event to **specific relays** and wait for `OK` message
     #[inline]
     pub async fn send_event_to<I, U>(
@@ -334,6 +729,39 @@ impl RelayPool {
         self.inner.subscribe(filters, opts).await
     }
 
+    /// Subscribe to filters to all connected relays, returning a [`SubscriptionHandle`] to cancel it
+    ///
+    /// Same as [`RelayPool::subscribe`], but the subscription can be closed with [`SubscriptionHandle::cancel`].
+    pub async fn subscribe_cancellable(
+        &self,
+        filters: Vec<Filter>,
+        opts: SubscribeOptions,
+    ) -> Result<Output<SubscriptionHandle>, Error> {
+        let output: Output<SubscriptionId> = self.inner.subscribe(filters, opts).await?;
+        Ok(Output {
+            val: SubscriptionHandle::new(output.val, InternalRelayPool::clone(&self.inner)),
+            success: output.success,
+            failed: output.failed,
+        })
+    }
+
+    /// Subscribe to filters to all connected relays, returning a [`SubscriptionStream`] of the received events
+    ///
+    /// The subscription is closed when the stream is dropped or cancelled with [`SubscriptionStream::handle`].
+    pub async fn subscribe_stream(
+        &self,
+        filters: Vec<Filter>,
+        opts: SubscribeOptions,
+    ) -> Result<Output<SubscriptionStream>, Error> {
+        let notifications = self.inner.notifications();
+        let output: Output<SubscriptionHandle> = self.subscribe_cancellable(filters, opts).await?;
+        Ok(Output {
+            val: SubscriptionStream::new(output.val, notifications),
+            success: output.success,
+            failed: output.failed,
+        })
+    }
+
     /// Subscribe to filters with custom [SubscriptionId] to all connected relays
     ///
     /// ### Auto-closing subscription
@@ -371,6 +799,21 @@ impl RelayPool {
         self.inner.subscribe_to(urls, filters, opts).await
     }
 
+    /// Subscribe to filters to all the relays of a **group**
+    ///
+    /// ### Auto-closing subscription
+    ///
+    /// It's possible to automatically close a subscription by configuring the [SubscribeOptions].
+    pub async fn subscribe_to_group(
+        &self,
+        group: &str,
+        filters: Vec<Filter>,
+        opts: SubscribeOptions,
+    ) -> Result<Output<SubscriptionId>, Error> {
+        let relays: HashMap<Url, Relay> = self.relays_in_group(group).await;
+        self.subscribe_to(relays.into_keys(), filters, opts).await
+    }
+
     /// Subscribe to filters with custom [SubscriptionId] to specific relays
     ///
     /// ### Auto-closing subscription
@@ -394,6 +837,36 @@ impl RelayPool {
             .await
     }
 
+    /// Wait for `EOSE` from all the relays that are participating in the subscription
+    ///
+    /// Return the relays that sent the `EOSE` (`success`) and the ones that didn't before the `timeout` (`failed`).
+    ///
+    /// Only the relays that have the subscription saved in their subscriptions map are taken into account,
+    /// so auto-closing subscriptions aren't supported.
+    ///
+    /// <div class="warning">The `EOSE` messages received before calling this method are not taken into account: call it immediately after the subscription!</div>
+    #[inline]
+    pub async fn wait_for_eose(
+        &self,
+        id: &SubscriptionId,
+        timeout: Duration,
+    ) -> Result<Output<()>, Error> {
+        self.inner.wait_for_eose(id, timeout).await
+    }
+
+    /// Update the filters of a subscription, without closing it
+    ///
+    /// Only the relays participating in the subscription are updated (see [`Relay::update_subscription`]).
+    #[inline]
+    pub async fn update_subscription(
+        &self,
+        id: SubscriptionId,
+        filters: Vec<Filter>,
+        opts: RelaySendOptions,
+    ) -> Result<Output<()>, Error> {
+        self.inner.update_subscription(id, filters, opts).await
+    }
+
     /// Unsubscribe from subscription
     #[inline]
     pub async fn unsubscribe(&self, id: SubscriptionId, opts: RelaySendOptions) {
@@ -409,6 +882,8 @@ impl RelayPool {
     /// Get events of filters
     ///
     /// Get events both from **local database** and **relays**
+    ///
+    /// If any filter has a `search` field, the request is sent only to the relays that support NIP-50 (`nip11` feature).
     #[inline]
     pub async fn get_events_of(
         &self,
@@ -416,9 +891,44 @@ impl RelayPool {
         timeout: Duration,
         opts: FilterOptions,
     ) -> Result<Vec<Event>, Error> {
-        let relays = self.relays().await;
-        self.get_events_from(relays.into_keys(), filters, timeout, opts)
-            .await
+        self.inner.get_events_of(filters, timeout, opts).await
+    }
+
+    /// Fetch events of filters
+    ///
+    /// Like [`RelayPool::get_events_of`], but return also the relays that replied (`success`) and the ones that failed.
+    #[inline]
+    pub async fn fetch_events(
+        &self,
+        filters: Vec<Filter>,
+        timeout: Duration,
+        opts: FilterOptions,
+    ) -> Result<Output<Vec<Event>>, Error> {
+        self.inner.fetch_events(filters, timeout, opts).await
+    }
+
+    /// Get the relay that delivered the newest version of a replaceable event
+    ///
+    /// Available only if [`RelayPoolOptions::sticky_relays`] is enabled.
+    #[inline]
+    pub async fn preferred_relay_for(&self, public_key: &PublicKey, kind: &Kind) -> Option<Url> {
+        self.inner.preferred_relay_for(public_key, kind).await
+    }
+
+    /// Count events of filters (NIP-45)
+    ///
+    /// Send `COUNT` only to the relays that advertise NIP-45 support in their NIP-11 document.
+    /// Relays without a known document are skipped by default (see [`RelayPoolOptions::include_unknown_nips`]).
+    /// If `nip11` feature is disabled, send to all relays.
+    /// Relays without support are reported in [`Output::failed`].
+    /// Archive relays are skipped (see [`RelayOptions::archive`]).
+    #[inline]
+    pub async fn count_events(
+        &self,
+        filters: Vec<Filter>,
+        timeout: Duration,
+    ) -> Result<Output<HashMap<Url, usize>>, Error> {
+        self.inner.count_events(filters, timeout).await
     }
 
     /// Get events of filters from **specific relays**
@@ -442,13 +952,36 @@ impl RelayPool {
             .await
     }
 
-    /// Negentropy reconciliation with all connected relays
+    /// Fetch events of filters from **specific relays**
+    ///
+    /// Like [`RelayPool::get_events_from`], but return also the relays that replied (`success`) and the ones that failed.
     #[inline]
-    pub async fn reconcile(
+    pub async fn fetch_events_from<I, U>(
+        &self,
+        urls: I,
+        filters: Vec<Filter>,
+        timeout: Duration,
+        opts: FilterOptions,
+    ) -> Result<Output<Vec<Event>>, Error>
+    where
+        I: IntoIterator<Item = U>,
+        U: TryIntoUrl,
+        Error: From<<U as TryIntoUrl>::Err>,
+    {
+        self.inner
+            .fetch_events_from(urls, filters, timeout, opts)
+            .await
+    }
+
+    /// Negentropy reconciliation with all connected relays
+    ///
+    /// Archive relays are skipped (see [`RelayOptions::archive`]).
+    #[inline]
+    pub async fn reconcile(
         &self,
         filter: Filter,
         opts: NegentropyOptions,
-    ) -> Result<Output<()>, Error> {
+    ) -> Result<Output<Reconciliation>, Error> {
         self.inner.reconcile(filter, opts).await
     }
 
@@ -459,7 +992,7 @@ impl RelayPool {
         urls: I,
         filter: Filter,
         opts: NegentropyOptions,
-    ) -> Result<Output<()>, Error>
+    ) -> Result<Output<Reconciliation>, Error>
     where
         I: IntoIterator<Item = U>,
         U: TryIntoUrl,
@@ -469,13 +1002,15 @@ impl RelayPool {
     }
 
     /// Negentropy reconciliation with all relays and custom items
+    ///
+    /// Archive relays are skipped (see [`RelayOptions::archive`]).
     #[inline]
     pub async fn reconcile_with_items(
         &self,
         filter: Filter,
         items: Vec<(EventId, Timestamp)>,
         opts: NegentropyOptions,
-    ) -> Result<Output<()>, Error> {
+    ) -> Result<Output<Reconciliation>, Error> {
         self.inner.reconcile_with_items(filter, items, opts).await
     }
 
@@ -487,7 +1022,7 @@ impl RelayPool {
         filter: Filter,
         items: Vec<(EventId, Timestamp)>,
         opts: NegentropyOptions,
-    ) -> Result<Output<()>, Error>
+    ) -> Result<Output<Reconciliation>, Error>
     where
         I: IntoIterator<Item = U>,
         U: TryIntoUrl,
@@ -498,6 +1033,43 @@ impl RelayPool {
             .await
     }
 
+    /// Negentropy reconciliation with all relays, calling `on_item` for every [`ReconcileItem`] as soon as it's discovered
+    ///
+    /// The items are emitted during the negentropy exchange, before the events transfer (also in dry run),
+    /// with the URL of the relay that reported them.
+    /// Archive relays are skipped (see [`RelayOptions::archive`]).
+    ///
+    /// Use events stored in database
+    #[inline]
+    pub async fn reconcile_with_callback<F>(
+        &self,
+        filter: Filter,
+        opts: NegentropyOptions,
+        on_item: F,
+    ) -> Result<Output<Reconciliation>, Error>
+    where
+        F: Fn(&Url, ReconcileItem) + Send + Sync + 'static,
+    {
+        self.inner
+            .reconcile_with_callback(filter, opts, on_item)
+            .await
+    }
+
+    /// Negentropy reconciliation with all relays that can be aborted
+    ///
+    /// The sync runs in background: use [`SyncHandle::abort`] to stop it and [`SyncHandle::output`] to get the result.
+    /// Archive relays are skipped (see [`RelayOptions::archive`]).
+    ///
+    /// Use events stored in database
+    #[inline]
+    pub fn reconcile_cancellable(
+        &self,
+        filter: Filter,
+        opts: NegentropyOptions,
+    ) -> Result<SyncHandle<Output<Reconciliation>, Error>, Error> {
+        self.inner.reconcile_cancellable(filter, opts)
+    }
+
     /// Handle notifications
     pub async fn handle_notifications<F, Fut>(&self, func: F) -> Result<(), Error>
     where
@@ -505,7 +1077,7 @@ impl RelayPool {
         Fut: Future<Output = Result<bool>>,
     {
         let mut notifications = self.notifications();
-        while let Ok(notification) = notifications.recv().await {
+        while let Some(notification) = next_notification(notifications.recv().await) {
             let shutdown: bool = RelayPoolNotification::Shutdown == notification;
             let exit: bool = func(notification)
                 .await
@@ -517,3 +1089,1446 @@ impl RelayPool {
         Ok(())
     }
 }
+
+/// Convert a notification channel result to the next notification to handle
+///
+/// Lagging (dropped notifications) is reported instead of terminating the handler.
+fn next_notification(
+    res: Result<RelayPoolNotification, RecvError>,
+) -> Option<RelayPoolNotification> {
+    match res {
+        Ok(notification) => Some(notification),
+        Err(RecvError::Lagged(count)) => {
+            tracing::warn!("Notification handler is falling behind: {count} notifications dropped");
+            Some(RelayPoolNotification::NotificationsDropped { count })
+        }
+        Err(RecvError::Closed) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_utility::{futures_util, thread, time};
+    use nostr::{Alphabet, EventBuilder, JsonUtil, Keys, Metadata, SingleLetterTag};
+
+    use super::*;
+    use crate::mock::{MockRelay, MockRelayOptions};
+
+    #[tokio::test]
+    async fn test_inject_events() {
+        let pool = RelayPool::default();
+        let mut notifications = pool.notifications();
+
+        let url = Url::parse("wss://relay.example.com").unwrap();
+        let keys = Keys::generate();
+        let a = EventBuilder::text_note("a", []).to_event(&keys).unwrap();
+        let b = EventBuilder::text_note("b", []).to_event(&keys).unwrap();
+
+        // Duplicated event is notified only once
+        pool.inject_events(url.clone(), vec![a.clone(), b.clone(), a.clone()])
+            .await
+            .unwrap();
+
+        let mut ids: Vec<EventId> = Vec::new();
+        while let Ok(notification) = notifications.try_recv() {
+            if let RelayPoolNotification::Event {
+                relay_url,
+                subscription_id,
+                event,
+            } = notification
+            {
+                assert_eq!(relay_url, url);
+                assert_eq!(subscription_id, SubscriptionId::new("injected"));
+                ids.push(event.id());
+            }
+        }
+        assert_eq!(ids, vec![a.id(), b.id()]);
+
+        // Custom validator
+        pool.set_validator(|_| Err(String::from("rejected"))).await;
+        let c = EventBuilder::text_note("c", []).to_event(&keys).unwrap();
+        pool.inject_events(url.clone(), vec![c.clone()])
+            .await
+            .unwrap();
+
+        let mut rejected: bool = false;
+        while let Ok(notification) = notifications.try_recv() {
+            match notification {
+                RelayPoolNotification::Event { .. } => panic!("Event not rejected"),
+                RelayPoolNotification::Rejected { id, reason, .. } => {
+                    assert_eq!(id, c.id());
+                    assert_eq!(reason, "rejected");
+                    rejected = true;
+                }
+                _ => (),
+            }
+        }
+        assert!(rejected);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_sig_policy() {
+        let opts =
+            RelayPoolOptions::new().on_invalid_signature(InvalidSigPolicy::DropAndBanRelay(2));
+        let pool = RelayPool::new(opts);
+        let mut notifications = pool.notifications();
+
+        let url = Url::parse("wss://relay.example.com").unwrap();
+        pool.add_relay(url.clone(), RelayOptions::default())
+            .await
+            .unwrap();
+        let relay: Relay = pool.relay(url.clone()).await.unwrap();
+
+        let keys = Keys::generate();
+        let other = EventBuilder::text_note("other", [])
+            .to_event(&keys)
+            .unwrap();
+        let bad_sig = |content: &str| {
+            let event = EventBuilder::text_note(content, [])
+                .to_event(&keys)
+                .unwrap();
+            Event::new(
+                event.id(),
+                event.author(),
+                event.created_at(),
+                event.kind(),
+                event.tags().to_vec(),
+                event.content(),
+                other.signature(),
+            )
+        };
+
+        // Below threshold
+        let a = bad_sig("a");
+        pool.inject_events(url.clone(), vec![a.clone()])
+            .await
+            .unwrap();
+        assert!(!relay.is_banned());
+
+        let mut invalid: Vec<EventId> = Vec::new();
+        while let Ok(notification) = notifications.try_recv() {
+            match notification {
+                RelayPoolNotification::Event { .. } => panic!("Invalid event notified"),
+                RelayPoolNotification::InvalidSignature { relay_url, id } => {
+                    assert_eq!(relay_url, url);
+                    invalid.push(id);
+                }
+                _ => (),
+            }
+        }
+        assert_eq!(invalid, vec![a.id()]);
+
+        // Wrong ID is not counted as invalid signature
+        let bad_id = Event::new(
+            other.id(),
+            a.author(),
+            a.created_at(),
+            a.kind(),
+            a.tags().to_vec(),
+            a.content(),
+            a.signature(),
+        );
+        pool.inject_events(url.clone(), vec![bad_id]).await.unwrap();
+        assert!(!relay.is_banned());
+        while let Ok(notification) = notifications.try_recv() {
+            if let RelayPoolNotification::InvalidSignature { .. } = notification {
+                panic!("Wrong ID notified as invalid signature");
+            }
+        }
+
+        // Virtual relay
+        let virtual_url = Url::parse("wss://virtual.example.com").unwrap();
+        let c = bad_sig("c");
+        pool.inject_events(virtual_url.clone(), vec![c.clone()])
+            .await
+            .unwrap();
+
+        let mut invalid: Vec<(Url, EventId)> = Vec::new();
+        while let Ok(notification) = notifications.try_recv() {
+            if let RelayPoolNotification::InvalidSignature { relay_url, id } = notification {
+                invalid.push((relay_url, id));
+            }
+        }
+        assert_eq!(invalid, vec![(virtual_url, c.id())]);
+
+        // Threshold reached
+        pool.inject_events(url, vec![bad_sig("b")]).await.unwrap();
+        assert!(relay.is_banned());
+    }
+
+    #[tokio::test]
+    async fn test_require_tags() {
+        let mut require_tags = HashMap::new();
+        require_tags.insert(Kind::Repost, vec![SingleLetterTag::lowercase(Alphabet::E)]);
+        let pool = RelayPool::new(RelayPoolOptions::new().require_tags(require_tags));
+        let mut notifications = pool.notifications();
+
+        let url = Url::parse("wss://relay.example.com").unwrap();
+        pool.add_relay(url.clone(), RelayOptions::default())
+            .await
+            .unwrap();
+
+        let keys = Keys::generate();
+        let note = EventBuilder::text_note("hello", [])
+            .to_event(&keys)
+            .unwrap();
+        let repost = EventBuilder::repost(&note, None).to_event(&keys).unwrap();
+        let malformed = EventBuilder::new(Kind::Repost, "", [])
+            .to_event(&keys)
+            .unwrap();
+
+        pool.inject_events(url, vec![repost.clone(), malformed.clone()])
+            .await
+            .unwrap();
+
+        let mut received: Vec<EventId> = Vec::new();
+        let mut rejected: Vec<EventId> = Vec::new();
+        while let Ok(notification) = notifications.try_recv() {
+            match notification {
+                RelayPoolNotification::Event { event, .. } => received.push(event.id()),
+                RelayPoolNotification::Rejected { id, reason, .. } => {
+                    assert_eq!(reason, "missing required 'e' tag");
+                    rejected.push(id);
+                }
+                _ => (),
+            }
+        }
+        assert_eq!(received, vec![repost.id()]);
+        assert_eq!(rejected, vec![malformed.id()]);
+
+        // Virtual relay
+        let virtual_url = Url::parse("wss://virtual.example.com").unwrap();
+        let malformed = EventBuilder::new(Kind::Repost, "virtual", [])
+            .to_event(&keys)
+            .unwrap();
+        pool.inject_events(virtual_url, vec![malformed.clone()])
+            .await
+            .unwrap();
+
+        let mut rejected: Vec<EventId> = Vec::new();
+        while let Ok(notification) = notifications.try_recv() {
+            match notification {
+                RelayPoolNotification::Event { .. } => panic!("Event without required tags"),
+                RelayPoolNotification::Rejected { id, .. } => rejected.push(id),
+                _ => (),
+            }
+        }
+        assert_eq!(rejected, vec![malformed.id()]);
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_in_receive_order() {
+        let pool = RelayPool::default();
+        let url = Url::parse("wss://relay.example.com").unwrap();
+        pool.add_relay(
+            url.clone(),
+            RelayOptions::default().resubscribe_advance_since(true),
+        )
+        .await
+        .unwrap();
+        let relay: Relay = pool.relay(url.clone()).await.unwrap();
+
+        let id = SubscriptionId::new("feed");
+        relay
+            .inner
+            .store_subscription(id.clone(), vec![Filter::new()], false)
+            .await;
+
+        // Not sorted by timestamp
+        let keys = Keys::generate();
+        let events: Vec<Event> = (0..100u64)
+            .map(|i| {
+                EventBuilder::text_note(format!("{i}"), [])
+                    .custom_created_at(Timestamp::from((i * 7919) % 100))
+                    .to_event(&keys)
+                    .unwrap()
+            })
+            .collect();
+
+        let mut notifications = pool.notifications();
+        relay.inner.inject_events(&id, events.clone()).await;
+
+        let mut received: Vec<EventId> = Vec::with_capacity(events.len());
+        while let Ok(notification) = notifications.try_recv() {
+            if let RelayPoolNotification::Event { event, .. } = notification {
+                received.push(event.id());
+            }
+        }
+        assert_eq!(
+            received,
+            events.iter().map(|e| e.id()).collect::<Vec<EventId>>()
+        );
+        assert_eq!(
+            relay.last_seen(&[Filter::new()]).await,
+            Some(Timestamp::from(99))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_relay_notifications() {
+        let pool = RelayPool::default();
+        let url = Url::parse("wss://relay.example.com").unwrap();
+        pool.add_relay(
+            url.clone(),
+            RelayOptions::default().notification_channel_size(4),
+        )
+        .await
+        .unwrap();
+        let relay: Relay = pool.relay(url.clone()).await.unwrap();
+
+        let id = SubscriptionId::new("feed");
+        relay
+            .inner
+            .store_subscription(id.clone(), vec![Filter::new()], false)
+            .await;
+
+        let keys = Keys::generate();
+        let events: Vec<Event> = (0..10)
+            .map(|i| {
+                EventBuilder::text_note(format!("{i}"), [])
+                    .to_event(&keys)
+                    .unwrap()
+            })
+            .collect();
+
+        let mut pool_notifications = pool.notifications();
+        let mut relay_notifications = relay.notifications();
+        relay.inner.inject_events(&id, events.clone()).await;
+
+        // The relay channel is full: the oldest notifications are dropped
+        assert!(matches!(
+            relay_notifications.try_recv(),
+            Err(broadcast::error::TryRecvError::Lagged(..))
+        ));
+
+        // Dropping the relay receiver doesn't affect the pool notifications
+        drop(relay_notifications);
+        let keys = Keys::generate();
+        let event = EventBuilder::text_note("after", [])
+            .to_event(&keys)
+            .unwrap();
+        relay.inner.inject_events(&id, vec![event.clone()]).await;
+
+        let mut received: Vec<EventId> = Vec::new();
+        while let Ok(notification) = pool_notifications.try_recv() {
+            if let RelayPoolNotification::Event {
+                relay_url, event, ..
+            } = notification
+            {
+                assert_eq!(relay_url, url);
+                received.push(event.id());
+            }
+        }
+        assert_eq!(received.len(), events.len() + 1);
+        assert_eq!(received.last(), Some(&event.id()));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_eose() {
+        let fast =
+            MockRelay::run_with_opts(MockRelayOptions::new().req_delay(Duration::from_millis(300)))
+                .await;
+        let slow =
+            MockRelay::run_with_opts(MockRelayOptions::new().req_delay(Duration::from_secs(1)))
+                .await;
+        let silent = MockRelay::run_with_opts(MockRelayOptions::new().no_eose()).await;
+
+        let pool = RelayPool::default();
+        for mock in [&fast, &slow, &silent] {
+            pool.add_relay(mock.url(), RelayOptions::default())
+                .await
+                .unwrap();
+        }
+        pool.connect(Some(Duration::from_secs(5))).await;
+
+        let mut notifications = pool.notifications();
+        let id: SubscriptionId = pool
+            .subscribe(vec![Filter::new()], SubscribeOptions::default())
+            .await
+            .unwrap()
+            .val;
+        let output = pool
+            .wait_for_eose(&id, Duration::from_secs(3))
+            .await
+            .unwrap();
+        assert_eq!(output.success, HashSet::from([fast.url(), slow.url()]));
+        assert_eq!(
+            output.failed.into_keys().collect::<Vec<Url>>(),
+            vec![silent.url()]
+        );
+
+        // One EOSE notification per relay, in the order received
+        let mut eose: Vec<Url> = Vec::new();
+        while let Ok(notification) = notifications.try_recv() {
+            if let RelayPoolNotification::Eose {
+                relay_url,
+                subscription_id,
+            } = notification
+            {
+                assert_eq!(subscription_id, id);
+                eose.push(relay_url);
+            }
+        }
+        assert_eq!(eose, vec![fast.url(), slow.url()]);
+    }
+
+    #[tokio::test]
+    async fn test_max_concurrent_relays() {
+        // Many relays sharing the same counters
+        let mock =
+            MockRelay::run_with_opts(MockRelayOptions::new().req_delay(Duration::from_millis(300)))
+                .await;
+        let pool = RelayPool::default();
+        for i in 0..5 {
+            pool.add_relay(
+                mock.url_with_path(&format!("/{i}")),
+                RelayOptions::default(),
+            )
+            .await
+            .unwrap();
+        }
+        pool.connect(Some(Duration::from_secs(5))).await;
+
+        // Return when the REQ is sent to every relay
+        let opts = SubscribeOptions::default().max_concurrent_relays(Some(2));
+        let output = pool.subscribe(vec![Filter::new()], opts).await.unwrap();
+        assert_eq!(output.success.len(), 5);
+
+        // Queued relays subscribed as the active ones sent EOSE
+        time::timeout(Some(Duration::from_secs(5)), async {
+            while mock
+                .received()
+                .await
+                .iter()
+                .filter(|msg| msg.is_req())
+                .count()
+                < 5
+            {
+                thread::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("REQ not sent");
+        assert_eq!(mock.max_concurrent_reqs(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_relay_groups() {
+        let personal = MockRelay::run().await;
+        let public = MockRelay::run().await;
+        let other = MockRelay::run().await;
+
+        let pool = RelayPool::default();
+        pool.add_relay(personal.url(), RelayOptions::default().group("personal"))
+            .await
+            .unwrap();
+        pool.add_relay(public.url(), RelayOptions::default().group("public"))
+            .await
+            .unwrap();
+        pool.add_relay(other.url(), RelayOptions::default())
+            .await
+            .unwrap();
+        pool.connect(Some(Duration::from_secs(5))).await;
+
+        let relays: Vec<Url> = pool.relays_in_group("personal").await.into_keys().collect();
+        assert_eq!(relays, vec![personal.url()]);
+        assert!(pool.relays_in_group("paid").await.is_empty());
+
+        // Send event only to the group
+        let keys = Keys::generate();
+        let event = EventBuilder::text_note("personal", [])
+            .to_event(&keys)
+            .unwrap();
+        let output = pool
+            .send_event_to_group("personal", event.clone(), RelaySendOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(output.success, HashSet::from([personal.url()]));
+        assert_eq!(personal.events().await, vec![event]);
+        assert!(public.events().await.is_empty());
+        assert!(other.events().await.is_empty());
+
+        // Subscribe only to the group
+        let output = pool
+            .subscribe_to_group("public", vec![Filter::new()], SubscribeOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(output.success, HashSet::from([public.url()]));
+        time::timeout(Some(Duration::from_secs(5)), async {
+            while !public.received().await.iter().any(|msg| msg.is_req()) {
+                thread::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("REQ not sent");
+        for (mock, expected) in [(&personal, 0), (&public, 1), (&other, 0)] {
+            let reqs: usize = mock
+                .received()
+                .await
+                .iter()
+                .filter(|msg| msg.is_req())
+                .count();
+            assert_eq!(reqs, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connect_concurrency() {
+        // Many relays sharing the same counters
+        let mock = MockRelay::run_with_opts(
+            MockRelayOptions::new().handshake_delay(Duration::from_millis(300)),
+        )
+        .await;
+        let pool = RelayPool::new(RelayPoolOptions::new().connect_concurrency(Some(2)));
+        for i in 0..5 {
+            pool.add_relay(
+                mock.url_with_path(&format!("/{i}")),
+                RelayOptions::default(),
+            )
+            .await
+            .unwrap();
+        }
+
+        let mut notifications = pool.notifications();
+        pool.connect(Some(Duration::from_secs(5))).await;
+
+        for relay in pool.relays().await.values() {
+            assert!(relay.is_connected().await);
+        }
+        assert_eq!(mock.connections(), 5);
+        assert_eq!(mock.max_concurrent_handshakes(), 2);
+
+        // Connection notified for every relay
+        let mut connected: HashSet<Url> = HashSet::new();
+        while let Ok(notification) = notifications.try_recv() {
+            if let RelayPoolNotification::RelayStatus {
+                relay_url,
+                status: RelayStatus::Connected,
+            } = notification
+            {
+                connected.insert(relay_url);
+            }
+        }
+        assert_eq!(connected.len(), 5);
+    }
+
+    async fn reqs(mock: &MockRelay) -> usize {
+        mock.received()
+            .await
+            .iter()
+            .filter(|msg| msg.is_req())
+            .count()
+    }
+
+    #[tokio::test]
+    async fn test_sticky_relays() {
+        let keys = Keys::generate();
+        let metadata = |name: &str, created_at: u64| {
+            EventBuilder::metadata(&Metadata::new().name(name))
+                .custom_created_at(Timestamp::from(created_at))
+                .to_event(&keys)
+                .unwrap()
+        };
+        let old_metadata = metadata("old", 10);
+        let new_metadata = metadata("new", 20);
+
+        let old = MockRelay::run().await;
+        old.add_events([old_metadata.clone()]).await;
+        let new = MockRelay::run().await;
+        new.add_events([new_metadata.clone()]).await;
+
+        let pool = RelayPool::new(RelayPoolOptions::new().sticky_relays(true));
+        for mock in [&old, &new] {
+            pool.add_relay(mock.url(), RelayOptions::default())
+                .await
+                .unwrap();
+        }
+        pool.connect(Some(Duration::from_secs(5))).await;
+
+        let filter = Filter::new().author(keys.public_key()).kind(Kind::Metadata);
+        let timeout = Duration::from_secs(5);
+
+        // First query: all relays
+        pool.get_events_of(vec![filter.clone()], timeout, FilterOptions::ExitOnEOSE)
+            .await
+            .unwrap();
+        assert_eq!(
+            pool.preferred_relay_for(&keys.public_key(), &Kind::Metadata)
+                .await,
+            Some(new.url())
+        );
+        assert_eq!((reqs(&old).await, reqs(&new).await), (1, 1));
+
+        // Sticky relay queried first
+        let events = pool
+            .get_events_of(vec![filter.clone()], timeout, FilterOptions::ExitOnEOSE)
+            .await
+            .unwrap();
+        assert_eq!(events, vec![new_metadata]);
+        assert_eq!((reqs(&old).await, reqs(&new).await), (1, 2));
+
+        // Sticky relay has no events: query widened to all relays
+        let events = pool
+            .get_events_of(
+                vec![filter.until(Timestamp::from(15))],
+                timeout,
+                FilterOptions::ExitOnEOSE,
+            )
+            .await
+            .unwrap();
+        assert_eq!(events, vec![old_metadata]);
+        assert_eq!((reqs(&old).await, reqs(&new).await), (2, 3));
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_filtered() {
+        let pool = RelayPool::default();
+        let url = Url::parse("wss://relay.example.com").unwrap();
+        pool.add_relay(url.clone(), RelayOptions::default())
+            .await
+            .unwrap();
+        let relay: Relay = pool.relay(url).await.unwrap();
+
+        let id = SubscriptionId::new("feed");
+        relay
+            .inner
+            .store_subscription(id.clone(), vec![Filter::new()], false)
+            .await;
+
+        let mut reposts = pool
+            .subscribe_filtered(|notification| match notification {
+                RelayPoolNotification::Event { event, .. } => event.kind() == Kind::Repost,
+                _ => false,
+            })
+            .await;
+
+        let keys = Keys::generate();
+        let note = EventBuilder::text_note("note", []).to_event(&keys).unwrap();
+        let repost = EventBuilder::repost(&note, None).to_event(&keys).unwrap();
+        relay
+            .inner
+            .inject_events(&id, vec![note, repost.clone()])
+            .await;
+
+        match reposts.try_recv() {
+            Ok(RelayPoolNotification::Event { event, .. }) => assert_eq!(event.id(), repost.id()),
+            _ => panic!("expected repost"),
+        }
+        assert!(reposts.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_remove_relay_graceful() {
+        let pool = RelayPool::default();
+        let url = Url::parse("wss://relay.example.com").unwrap();
+        pool.add_relay(url.clone(), RelayOptions::default())
+            .await
+            .unwrap();
+        let relay: Relay = pool.relay(url.clone()).await.unwrap();
+
+        let id = SubscriptionId::new("feed");
+        relay
+            .inner
+            .store_subscription(id.clone(), vec![Filter::new()], true)
+            .await;
+
+        // Stored events arrive during the drain
+        let event = EventBuilder::text_note("late", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let r = relay.clone();
+        let (sub_id, e) = (id.clone(), event.clone());
+        let _ = thread::spawn(async move {
+            thread::sleep(Duration::from_millis(100)).await;
+            r.inner.inject_events(&sub_id, vec![e]).await;
+            let json: String = RelayMessage::eose(sub_id).as_json();
+            r.inner
+                .handle_relay_message_infallible(json.as_bytes())
+                .await;
+        });
+
+        let mut notifications = pool.notifications();
+        let drained: bool = pool
+            .remove_relay_graceful(url.clone(), Duration::from_secs(2))
+            .await
+            .unwrap();
+        assert!(drained);
+        assert!(pool.relay(url).await.is_err());
+
+        let mut received: bool = false;
+        while let Ok(notification) = notifications.try_recv() {
+            if let RelayPoolNotification::Event { event: e, .. } = notification {
+                received = e.id() == event.id();
+            }
+        }
+        assert!(received);
+    }
+
+    #[tokio::test]
+    async fn test_stats_snapshot() {
+        let pool = RelayPool::default();
+        let url = Url::parse("wss://relay.example.com").unwrap();
+        pool.add_relay(url.clone(), RelayOptions::default())
+            .await
+            .unwrap();
+        let relay: Relay = pool.relay(url.clone()).await.unwrap();
+        relay
+            .inner
+            .store_subscription(SubscriptionId::new("feed"), vec![Filter::new()], false)
+            .await;
+
+        let snapshot = pool.stats_snapshot().await;
+        let stats: &RelayStats = snapshot.get(&url).unwrap();
+        assert_eq!(stats.status, RelayStatus::Initialized);
+        assert_eq!(stats.latency, None);
+        assert_eq!(stats.subscriptions, 1);
+        assert_eq!(stats.reconnections, 0);
+    }
+
+    #[tokio::test]
+    async fn test_archive_relays() {
+        let pool = RelayPool::default();
+
+        let live = Url::parse("wss://live.example.com").unwrap();
+        let archive = Url::parse("wss://archive.example.com").unwrap();
+        pool.add_relay(live.clone(), RelayOptions::default())
+            .await
+            .unwrap();
+        pool.add_relay(archive.clone(), RelayOptions::default().archive(true))
+            .await
+            .unwrap();
+        assert_eq!(
+            pool.archive_relays().await.into_keys().collect::<Vec<_>>(),
+            vec![archive.clone()]
+        );
+
+        // Routine subscription
+        let send_opts = RelaySendOptions::default().skip_send_confirmation(true);
+        let opts = SubscribeOptions::default().send_opts(send_opts);
+        let id = SubscriptionId::new("feed");
+        let filters = vec![Filter::new().kind(Kind::TextNote)];
+        let output = pool
+            .subscribe_with_id(id.clone(), filters.clone(), opts.clone())
+            .await
+            .unwrap();
+        assert_eq!(output.success, HashSet::from([live.clone()]));
+
+        let archive_relay: Relay = pool.relay(archive.clone()).await.unwrap();
+        assert!(archive_relay.subscription(&id).await.is_none());
+
+        // Archive relays added later don't inherit the pool subscriptions
+        let other = Url::parse("wss://archive2.example.com").unwrap();
+        pool.add_relay(other.clone(), RelayOptions::default().archive(true))
+            .await
+            .unwrap();
+        let other_relay: Relay = pool.relay(other).await.unwrap();
+        assert!(other_relay.subscription(&id).await.is_none());
+
+        // Explicit historical query
+        let history = SubscriptionId::new("history");
+        pool.subscribe_with_id_to([archive], history.clone(), filters, opts)
+            .await
+            .unwrap();
+        assert!(archive_relay.subscription(&history).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_migrate_subscriptions() {
+        let pool = RelayPool::default();
+        let mut notifications = pool.notifications();
+
+        let from = Url::parse("wss://from.example.com").unwrap();
+        let to = Url::parse("wss://to.example.com").unwrap();
+        pool.add_relay(
+            from.clone(),
+            RelayOptions::default().resubscribe_advance_since(true),
+        )
+        .await
+        .unwrap();
+        pool.add_relay(to.clone(), RelayOptions::default())
+            .await
+            .unwrap();
+        let from_relay: Relay = pool.relay(from.clone()).await.unwrap();
+        let to_relay: Relay = pool.relay(to.clone()).await.unwrap();
+
+        let send_opts = RelaySendOptions::default().skip_send_confirmation(true);
+        let id = SubscriptionId::new("feed");
+        from_relay
+            .subscribe_with_id(
+                id.clone(),
+                vec![Filter::new().kind(Kind::TextNote)],
+                SubscribeOptions::default().send_opts(send_opts),
+            )
+            .await
+            .unwrap();
+
+        let keys = Keys::generate();
+        let note = |content: &str, created_at: u64| {
+            EventBuilder::text_note(content, [])
+                .custom_created_at(Timestamp::from(created_at))
+                .to_event(&keys)
+                .unwrap()
+        };
+        let a = note("a", 100);
+        let b = note("b", 200);
+
+        from_relay.inner.inject_events(&id, vec![a.clone()]).await;
+
+        pool.migrate_subscriptions(from.clone(), to.clone(), send_opts)
+            .await
+            .unwrap();
+
+        assert!(from_relay.subscription(&id).await.is_none());
+        let filters = to_relay.subscription(&id).await.unwrap();
+        assert_eq!(filters[0].since, Some(Timestamp::from(100)));
+
+        // Events keep flowing from the new relay, without duplicates
+        to_relay
+            .inner
+            .inject_events(&id, vec![a.clone(), b.clone()])
+            .await;
+
+        let mut received: Vec<(Url, EventId)> = Vec::new();
+        while let Ok(notification) = notifications.try_recv() {
+            if let RelayPoolNotification::Event {
+                relay_url,
+                subscription_id,
+                event,
+            } = notification
+            {
+                assert_eq!(subscription_id, id);
+                received.push((relay_url, event.id()));
+            }
+        }
+        assert_eq!(received, vec![(from, a.id()), (to, b.id())]);
+    }
+
+    #[tokio::test]
+    async fn test_migrate_subscriptions_from_dead_relay() {
+        let pool = RelayPool::default();
+
+        let from = Url::parse("wss://from.example.com").unwrap();
+        let to = Url::parse("wss://to.example.com").unwrap();
+        pool.add_relay(from.clone(), RelayOptions::default())
+            .await
+            .unwrap();
+        pool.add_relay(to.clone(), RelayOptions::default())
+            .await
+            .unwrap();
+        let from_relay: Relay = pool.relay(from.clone()).await.unwrap();
+        let to_relay: Relay = pool.relay(to.clone()).await.unwrap();
+
+        let ids = [SubscriptionId::new("a"), SubscriptionId::new("b")];
+        for id in ids.iter() {
+            from_relay
+                .subscribe_with_id(
+                    id.clone(),
+                    vec![Filter::new().kind(Kind::TextNote)],
+                    SubscribeOptions::default()
+                        .send_opts(RelaySendOptions::default().skip_send_confirmation(true)),
+                )
+                .await
+                .unwrap();
+        }
+
+        // Dead relay: the CLOSE fails
+        for _ in 0..3 {
+            from_relay.stats().new_attempt();
+        }
+        assert!(matches!(
+            from_relay
+                .inner
+                .batch_msg(
+                    vec![ClientMessage::close(ids[0].clone())],
+                    RelaySendOptions::default()
+                )
+                .await,
+            Err(crate::relay::Error::NotConnected)
+        ));
+
+        pool.migrate_subscriptions(
+            from.clone(),
+            "wss://to.example.com",
+            RelaySendOptions::default().skip_send_confirmation(true),
+        )
+        .await
+        .unwrap();
+
+        // All subscriptions migrated
+        for id in ids.iter() {
+            assert!(from_relay.subscription(id).await.is_none());
+            assert!(to_relay.subscription(id).await.is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_global_limit() {
+        let pool = RelayPool::default();
+        let mut notifications = pool.notifications();
+
+        let a = Url::parse("wss://a.example.com").unwrap();
+        let b = Url::parse("wss://b.example.com").unwrap();
+        pool.add_relay(a.clone(), RelayOptions::default())
+            .await
+            .unwrap();
+        pool.add_relay(b.clone(), RelayOptions::default())
+            .await
+            .unwrap();
+
+        let send_opts = RelaySendOptions::default().skip_send_confirmation(true);
+        let opts = SubscribeOptions::default()
+            .send_opts(send_opts)
+            .global_limit(Some(50));
+        let id = SubscriptionId::new("feed");
+        pool.subscribe_with_id(id.clone(), vec![Filter::new().limit(40)], opts)
+            .await
+            .unwrap();
+
+        // Each relay returns 40 events, 10 in common
+        let keys = Keys::generate();
+        let events: Vec<Event> = (0..70)
+            .map(|i| {
+                EventBuilder::text_note(i.to_string(), [])
+                    .to_event(&keys)
+                    .unwrap()
+            })
+            .collect();
+        let relay_a: Relay = pool.relay(a).await.unwrap();
+        let relay_b: Relay = pool.relay(b).await.unwrap();
+        relay_a
+            .inner
+            .inject_events(&id, events[..40].to_vec())
+            .await;
+        relay_b
+            .inner
+            .inject_events(&id, events[30..].to_vec())
+            .await;
+
+        let mut received: HashSet<EventId> = HashSet::new();
+        let mut count: usize = 0;
+        while let Ok(notification) = notifications.try_recv() {
+            if let RelayPoolNotification::Event { event, .. } = notification {
+                received.insert(event.id());
+                count += 1;
+            }
+        }
+        assert_eq!(count, 50);
+        assert_eq!(received.len(), 50);
+
+        // Closed on all relays
+        time::timeout(Some(Duration::from_secs(2)), async {
+            while relay_a.subscription(&id).await.is_some()
+                || relay_b.subscription(&id).await.is_some()
+            {
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("subscription not closed");
+    }
+
+    #[tokio::test]
+    async fn test_global_limit_reset() {
+        let pool = RelayPool::default();
+        let mut notifications = pool.notifications();
+
+        let url = Url::parse("wss://relay.example.com").unwrap();
+        pool.add_relay(url.clone(), RelayOptions::default())
+            .await
+            .unwrap();
+        let relay: Relay = pool.relay(url).await.unwrap();
+
+        let send_opts = RelaySendOptions::default().skip_send_confirmation(true);
+        let id = SubscriptionId::new("feed");
+
+        // Zero limit
+        let opts = SubscribeOptions::default()
+            .send_opts(send_opts)
+            .global_limit(Some(0));
+        assert!(matches!(
+            pool.subscribe_with_id(id.clone(), vec![Filter::new()], opts)
+                .await,
+            Err(Error::ZeroGlobalLimit)
+        ));
+
+        // Reach the limit
+        let opts = SubscribeOptions::default()
+            .send_opts(send_opts)
+            .global_limit(Some(1));
+        pool.subscribe_with_id(id.clone(), vec![Filter::new()], opts)
+            .await
+            .unwrap();
+
+        let keys = Keys::generate();
+        let events: Vec<Event> = (0..4)
+            .map(|i| {
+                EventBuilder::text_note(i.to_string(), [])
+                    .to_event(&keys)
+                    .unwrap()
+            })
+            .collect();
+        relay.inner.inject_events(&id, events[..2].to_vec()).await;
+
+        // Wait for the close
+        time::timeout(Some(Duration::from_secs(2)), async {
+            while relay.subscription(&id).await.is_some() {
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        // Subscribe again with the same ID and without limit
+        let opts = SubscribeOptions::default().send_opts(send_opts);
+        pool.subscribe_with_id(id.clone(), vec![Filter::new()], opts)
+            .await
+            .unwrap();
+        relay.inner.inject_events(&id, events[2..].to_vec()).await;
+
+        let mut count: usize = 0;
+        while let Ok(notification) = notifications.try_recv() {
+            if let RelayPoolNotification::Event { .. } = notification {
+                count += 1;
+            }
+        }
+        assert_eq!(count, 3);
+    }
+
+    #[tokio::test]
+    async fn test_mute() {
+        let pool = RelayPool::default();
+        let mut notifications = pool.notifications();
+
+        let url = Url::parse("wss://relay.example.com").unwrap();
+        let muted = Keys::generate();
+        let keys = Keys::generate();
+
+        pool.mute(muted.public_key()).await;
+        pool.mute_words(["Spam Phrase"]).await;
+
+        let from_muted = EventBuilder::text_note("hello", [])
+            .to_event(&muted)
+            .unwrap();
+        let with_word = EventBuilder::text_note("buy this spam phrase now", [])
+            .to_event(&keys)
+            .unwrap();
+        let valid = EventBuilder::text_note("hello", [])
+            .to_event(&keys)
+            .unwrap();
+
+        pool.inject_events(
+            url.clone(),
+            vec![from_muted.clone(), with_word, valid.clone()],
+        )
+        .await
+        .unwrap();
+
+        let mut ids: Vec<EventId> = Vec::new();
+        while let Ok(notification) = notifications.try_recv() {
+            match notification {
+                RelayPoolNotification::Event { event, .. } => ids.push(event.id()),
+                RelayPoolNotification::Rejected { .. } => panic!("Muted event notified"),
+                _ => (),
+            }
+        }
+        assert_eq!(ids, vec![valid.id()]);
+
+        // Clearing the blacklist must not unmute
+        pool.blacklist().clear().await;
+        pool.inject_events(url.clone(), vec![from_muted.clone()])
+            .await
+            .unwrap();
+        while let Ok(notification) = notifications.try_recv() {
+            if let RelayPoolNotification::Event { .. } = notification {
+                panic!("Muted event notified after blacklist clear");
+            }
+        }
+
+        // Unmute
+        pool.unmute(&muted.public_key()).await;
+        pool.inject_events(url, vec![from_muted.clone()])
+            .await
+            .unwrap();
+
+        let mut ids: Vec<EventId> = Vec::new();
+        while let Ok(notification) = notifications.try_recv() {
+            if let RelayPoolNotification::Event { event, .. } = notification {
+                ids.push(event.id());
+            }
+        }
+        assert_eq!(ids, vec![from_muted.id()]);
+    }
+
+    #[tokio::test]
+    async fn test_add_relay_behavior() {
+        let pool = RelayPool::default();
+        let url = Url::parse("wss://relay.example.com").unwrap();
+
+        let opts = RelayOptions::new().write(false);
+        assert!(pool.add_relay(url.clone(), opts).await.unwrap());
+        let relay: Relay = pool.relay(url.clone()).await.unwrap();
+        assert!(!relay.flags().has_write());
+
+        // Error
+        let res = pool
+            .add_relay_with_behavior(url.clone(), RelayOptions::new(), AddRelayBehavior::Error)
+            .await;
+        assert!(matches!(res, Err(Error::RelayAlreadyExists(..))));
+
+        // Ignore
+        let added = pool
+            .add_relay_with_behavior(url.clone(), RelayOptions::new(), AddRelayBehavior::Ignore)
+            .await
+            .unwrap();
+        assert!(!added);
+        assert!(!relay.flags().has_write());
+
+        // Update options: the existing relay instance is kept
+        let added = pool
+            .add_relay_with_behavior(
+                url.clone(),
+                RelayOptions::new(),
+                AddRelayBehavior::UpdateOptions,
+            )
+            .await
+            .unwrap();
+        assert!(!added);
+        assert!(relay.flags().has_write());
+        assert_eq!(pool.relays().await.len(), 1);
+        assert_eq!(relay.status().await, RelayStatus::Initialized);
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_with_callback() {
+        let keys = Keys::generate();
+        let mut mocks: Vec<MockRelay> = Vec::new();
+        let mut expected: Vec<(Url, ReconcileItem)> = Vec::new();
+        for content in ["a", "b"] {
+            let event = EventBuilder::text_note(content, [])
+                .to_event(&keys)
+                .unwrap();
+            let mock = MockRelay::run().await;
+            expected.push((mock.url(), ReconcileItem::NeedDownload(event.id())));
+            mock.add_events([event]).await;
+            mocks.push(mock);
+        }
+
+        let pool = RelayPool::default();
+        for mock in mocks.iter() {
+            pool.add_relay(mock.url(), RelayOptions::default())
+                .await
+                .unwrap();
+        }
+        pool.connect(Some(Duration::from_secs(5))).await;
+
+        let items: Arc<std::sync::Mutex<Vec<(Url, ReconcileItem)>>> =
+            Arc::new(std::sync::Mutex::new(Vec::new()));
+        let i = items.clone();
+        let output = pool
+            .reconcile_with_callback(
+                Filter::new(),
+                NegentropyOptions::new().dry_run(true),
+                move |url, item| i.lock().unwrap().push((url.clone(), item)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(output.success.len(), 2);
+        assert!(output.received.is_empty());
+
+        let mut items = items.lock().unwrap().clone();
+        items.sort_by(|a, b| a.0.cmp(&b.0));
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(items, expected);
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_metadata_only() {
+        let keys = Keys::generate();
+        let metadata = EventBuilder::metadata(&Metadata::new().name("alice"))
+            .to_event(&keys)
+            .unwrap();
+        let note = EventBuilder::text_note("content", [])
+            .to_event(&keys)
+            .unwrap();
+        let mock = MockRelay::run().await;
+        mock.add_events([metadata.clone(), note]).await;
+
+        let pool = RelayPool::default();
+        pool.add_relay(mock.url(), RelayOptions::default())
+            .await
+            .unwrap();
+        pool.connect(Some(Duration::from_secs(5))).await;
+
+        let output = pool
+            .reconcile(Filter::new(), NegentropyOptions::new().metadata_only(true))
+            .await
+            .unwrap();
+        let expected: HashSet<EventId> = HashSet::from([metadata.id()]);
+        assert_eq!(output.local_missing, expected);
+        assert_eq!(output.received, expected);
+
+        // Filter constrained to the metadata kinds
+        let filters: Vec<Filter> = mock
+            .received()
+            .await
+            .into_iter()
+            .filter_map(|msg| match msg {
+                ClientMessage::NegOpen { filter, .. } => Some(*filter),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            filters,
+            vec![NegentropyOptions::new()
+                .metadata_only(true)
+                .constrain_filter(Filter::new())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_coalesce_subscriptions() {
+        let mock = MockRelay::run().await;
+        let pool = RelayPool::new(RelayPoolOptions::new().coalesce_subscriptions(true));
+        pool.add_relay(mock.url(), RelayOptions::default())
+            .await
+            .unwrap();
+        pool.connect(Some(Duration::from_secs(5))).await;
+
+        // Concurrent subscriptions with the same filters
+        let filters = vec![Filter::new().kind(Kind::TextNote)];
+        let (first, second) = tokio::join!(
+            pool.subscribe(filters.clone(), SubscribeOptions::default()),
+            pool.subscribe(filters, SubscribeOptions::default())
+        );
+        let id: SubscriptionId = first.unwrap().val;
+        assert_eq!(second.unwrap().val, id);
+
+        // First consumer unsubscribes: subscription kept
+        pool.unsubscribe(id.clone(), RelaySendOptions::default())
+            .await;
+        assert!(pool.subscription(&id).await.is_some());
+
+        // Last consumer unsubscribes: `CLOSE` sent
+        pool.unsubscribe(id.clone(), RelaySendOptions::default())
+            .await;
+        assert!(pool.subscription(&id).await.is_none());
+        time::timeout(Some(Duration::from_secs(5)), async {
+            while !mock.received().await.iter().any(|msg| msg.is_close()) {
+                thread::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("CLOSE not sent");
+
+        let received: Vec<ClientMessage> = mock.received().await;
+        assert_eq!(received.iter().filter(|msg| msg.is_req()).count(), 1);
+        assert_eq!(received.iter().filter(|msg| msg.is_close()).count(), 1);
+    }
+
+    #[cfg(feature = "nip11")]
+    #[tokio::test]
+    async fn test_count_events_unknown_nips() {
+        let mock = MockRelay::run().await;
+        let is_count = |msg: &ClientMessage| matches!(msg, ClientMessage::Count { .. });
+
+        // Mock relay doesn't serve a NIP-11 document: skipped by default
+        let pool = RelayPool::default();
+        pool.add_relay(mock.url(), RelayOptions::default())
+            .await
+            .unwrap();
+        pool.connect(Some(Duration::from_secs(5))).await;
+
+        let output = pool
+            .count_events(vec![Filter::new()], Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert!(output.success.is_empty());
+        assert!(output.failed.contains_key(&mock.url()));
+        assert!(!mock.received().await.iter().any(is_count));
+        pool.shutdown().await.unwrap();
+
+        // Included if explicitly requested
+        let pool = RelayPool::new(RelayPoolOptions::new().include_unknown_nips(true));
+        pool.add_relay(mock.url(), RelayOptions::default())
+            .await
+            .unwrap();
+        pool.connect(Some(Duration::from_secs(5))).await;
+
+        let output = pool
+            .count_events(vec![Filter::new()], Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(output.val.get(&mock.url()), Some(&0));
+        assert!(mock.received().await.iter().any(is_count));
+    }
+
+    #[cfg(feature = "nip11")]
+    #[tokio::test]
+    async fn test_search_skips_archive_relays() {
+        let live = MockRelay::run().await;
+        let archive = MockRelay::run().await;
+
+        let pool = RelayPool::default();
+        pool.add_relay(live.url(), RelayOptions::default())
+            .await
+            .unwrap();
+        pool.add_relay(archive.url(), RelayOptions::default().archive(true))
+            .await
+            .unwrap();
+        pool.connect(Some(Duration::from_secs(5))).await;
+
+        let filters = vec![Filter::new().search("nostr")];
+        let output = pool
+            .fetch_events(filters, Duration::from_secs(5), FilterOptions::ExitOnEOSE)
+            .await
+            .unwrap();
+        assert_eq!(output.success, HashSet::from([live.url()]));
+        assert_eq!(reqs(&live).await, 1);
+        assert_eq!(reqs(&archive).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_abort_reconciliation() {
+        let keys = Keys::generate();
+        let opts = MockRelayOptions::new().req_delay(Duration::from_secs(2));
+        let mut mocks: Vec<MockRelay> = Vec::new();
+        let mut ids: Vec<EventId> = Vec::new();
+        for content in ["a", "b"] {
+            let event = EventBuilder::text_note(content, [])
+                .to_event(&keys)
+                .unwrap();
+            ids.push(event.id());
+            let mock = MockRelay::run_with_opts(opts.clone()).await;
+            mock.add_events([event]).await;
+            mocks.push(mock);
+        }
+
+        let pool = RelayPool::default();
+        for mock in mocks.iter() {
+            pool.add_relay(mock.url(), RelayOptions::default())
+                .await
+                .unwrap();
+        }
+        pool.connect(Some(Duration::from_secs(5))).await;
+
+        let handle = pool
+            .reconcile_cancellable(Filter::new(), NegentropyOptions::new())
+            .unwrap();
+
+        // Abort while the relays are slow to send the missing events
+        for mock in mocks.iter() {
+            time::timeout(Some(Duration::from_secs(5)), async {
+                while !mock.received().await.iter().any(|msg| msg.is_req()) {
+                    thread::sleep(Duration::from_millis(50)).await;
+                }
+            })
+            .await
+            .expect("Download not requested");
+        }
+        handle.abort();
+
+        let output = time::timeout(Some(Duration::from_secs(1)), handle.output())
+            .await
+            .expect("Sync not aborted")
+            .unwrap();
+        assert_eq!(output.success.len(), 2);
+        assert!(ids.iter().all(|id| output.local_missing.contains(id)));
+        assert!(output.received.is_empty());
+
+        // Negentropy sessions closed
+        for mock in mocks.iter() {
+            time::timeout(Some(Duration::from_secs(5)), async {
+                while !mock
+                    .received()
+                    .await
+                    .iter()
+                    .any(|msg| matches!(msg, ClientMessage::NegClose { .. }))
+                {
+                    thread::sleep(Duration::from_millis(50)).await;
+                }
+            })
+            .await
+            .expect("Negentropy session not closed");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_custom_id() {
+        let mock = MockRelay::run().await;
+        let pool = RelayPool::new(RelayPoolOptions::new().subscription_id_prefix("myapp-"));
+        pool.add_relay(mock.url(), RelayOptions::new())
+            .await
+            .unwrap();
+        pool.connect(Some(Duration::from_secs(5))).await;
+        let relay: Relay = pool.relay(mock.url()).await.unwrap();
+
+        let send_opts = RelaySendOptions::new();
+        let filters = vec![Filter::new().limit(10)];
+
+        // Custom ID is used verbatim
+        let id = SubscriptionId::new("profile-feed");
+        let opts = SubscribeOptions::default()
+            .id(id.clone())
+            .send_opts(send_opts);
+        let output = pool.subscribe(filters.clone(), opts.clone()).await.unwrap();
+        assert_eq!(output.val, id);
+        assert!(relay.subscription(&id).await.is_some());
+
+        // Duplicated ID
+        let res = pool.subscribe(filters.clone(), opts).await;
+        assert!(matches!(res, Err(Error::DuplicateSubscriptionId(..))));
+
+        // Concurrent subscriptions with the same ID: only one succeed
+        let other = SubscriptionId::new("other-feed");
+        let opts = SubscribeOptions::default()
+            .id(other.clone())
+            .send_opts(send_opts);
+        let (a, b) = futures_util::future::join(
+            pool.subscribe(filters.clone(), opts.clone()),
+            pool.subscribe(filters.clone(), opts),
+        )
+        .await;
+        assert!(a.is_ok() ^ b.is_ok());
+
+        // Auto-generated ID with prefix
+        let opts = SubscribeOptions::default().send_opts(send_opts);
+        let output = pool.subscribe(filters.clone(), opts).await.unwrap();
+        assert!(output.val.to_string().starts_with("myapp-"));
+
+        // CLOSE uses the same ID
+        pool.unsubscribe(id.clone(), send_opts).await;
+        assert!(relay.subscription(&id).await.is_none());
+
+        let close = ClientMessage::close(id.clone());
+        time::timeout(Some(Duration::from_secs(5)), async {
+            while !mock.received().await.contains(&close) {
+                thread::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("CLOSE not sent");
+
+        let received: Vec<ClientMessage> = mock.received().await;
+        assert!(received.contains(&ClientMessage::req(id, filters)));
+        assert_eq!(
+            received
+                .iter()
+                .filter(|msg| matches!(msg, ClientMessage::Req { subscription_id, .. } if subscription_id == &other))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_next_notification() {
+        assert_eq!(
+            next_notification(Ok(RelayPoolNotification::Shutdown)),
+            Some(RelayPoolNotification::Shutdown)
+        );
+        assert_eq!(
+            next_notification(Err(RecvError::Lagged(5))),
+            Some(RelayPoolNotification::NotificationsDropped { count: 5 })
+        );
+        assert_eq!(next_notification(Err(RecvError::Closed)), None);
+    }
+}
@@ -4,16 +4,268 @@
 
 //! Pool options
 
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::time::Duration;
+
+use nostr::types::url::ParseError;
+use nostr::{Event, EventId, Kind, SingleLetterTag, SubscriptionId, Url};
+
+/// Order of the events returned by queries
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EventOrder {
+    /// Newest events first (ties are broken by event ID)
+    #[default]
+    NewestFirst,
+    /// Oldest events first (ties are broken by event ID)
+    OldestFirst,
+    /// Events from the database first, then the events in the order they are received from relays
+    AsReceived,
+}
+
+impl EventOrder {
+    pub(crate) fn sort(&self, events: &mut [Event]) {
+        match self {
+            Self::NewestFirst => events.sort_by(|a, b| b.cmp(a)),
+            Self::OldestFirst => events.sort(),
+            Self::AsReceived => (),
+        }
+    }
+
+    /// Keep the newest `limit` events, then sort them
+    pub(crate) fn truncate_and_sort(&self, events: &mut Vec<Event>, limit: Option<usize>) {
+        if let Some(limit) = limit {
+            if events.len() > limit {
+                let mut newest: Vec<&Event> = events.iter().collect();
+                newest.sort_by(|a, b| b.cmp(a));
+                let keep: HashSet<EventId> =
+                    newest.into_iter().take(limit).map(|e| e.id()).collect();
+                events.retain(|e| keep.contains(&e.id()));
+            }
+        }
+
+        self.sort(events);
+    }
+}
+
+/// Relay URL matcher
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RelayMatcher {
+    /// Match host, port and path of the normalized URL, ignoring the scheme (`ws` or `wss`)
+    Url(Url),
+    /// Match a domain and all its subdomains
+    Domain(String),
+}
+
+impl From<Url> for RelayMatcher {
+    fn from(url: Url) -> Self {
+        Self::Url(url)
+    }
+}
+
+impl FromStr for RelayMatcher {
+    type Err = ParseError;
+
+    /// Parse matcher
+    ///
+    /// `*.example.com` is parsed as [`RelayMatcher::Domain`], anything else as [`RelayMatcher::Url`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("*.") {
+            Some(domain) => {
+                // Parse as URL to validate and normalize the domain
+                let url: Url = Url::parse(&format!("wss://{domain}"))?;
+                match url.host_str() {
+                    Some(host) => Ok(Self::Domain(host.to_string())),
+                    None => Err(ParseError::EmptyHost),
+                }
+            }
+            None => Ok(Self::Url(Url::parse(s)?)),
+        }
+    }
+}
+
+impl RelayMatcher {
+    /// Check if relay URL match
+    pub fn matches(&self, url: &Url) -> bool {
+        match self {
+            Self::Url(expected) => {
+                expected.host_str() == url.host_str()
+                    && expected.port() == url.port()
+                    && expected.path().trim_end_matches('/') == url.path().trim_end_matches('/')
+            }
+            Self::Domain(domain) => match url.host_str() {
+                Some(host) => {
+                    host == domain
+                        || host
+                            .strip_suffix(domain.as_str())
+                            .map_or(false, |sub| sub.ends_with('.'))
+                }
+                None => false,
+            },
+        }
+    }
+}
+
+/// Behavior when adding a relay already in the pool
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum AddRelayBehavior {
+    /// Return [`Error::RelayAlreadyExists`](super::Error::RelayAlreadyExists)
+    Error,
+    /// Keep the existing relay
+    #[default]
+    Ignore,
+    /// Update the options of the existing relay, without dropping the connection
+    ///
+    /// Only flags, POW difficulty, reconnection options and subscription limits are updated.
+    UpdateOptions,
+}
+
+/// Policy about the relays that can be added to the pool
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum RelayPolicy {
+    /// Allow any relay
+    #[default]
+    AllowAll,
+    /// Allow only the relays matching at least one of the matchers
+    Allowlist(HashSet<RelayMatcher>),
+    /// Allow any relay except the ones matching at least one of the matchers
+    Denylist(HashSet<RelayMatcher>),
+}
+
+impl RelayPolicy {
+    /// Check if relay is allowed
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        match self {
+            Self::AllowAll => true,
+            Self::Allowlist(matchers) => matchers.iter().any(|m| m.matches(url)),
+            Self::Denylist(matchers) => !matchers.iter().any(|m| m.matches(url)),
+        }
+    }
+}
+
+/// Behavior for the events failing the signature verification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidSigPolicy {
+    /// Drop the event
+    #[default]
+    Drop,
+    /// Drop the event and send the [`RelayPoolNotification::InvalidSignature`](super::RelayPoolNotification::InvalidSignature) notification
+    DropAndNotify,
+    /// Same as [`InvalidSigPolicy::DropAndNotify`] but, when a relay sends the specified number of invalid signatures,
+    /// the relay is disconnected and can't be connected again
+    DropAndBanRelay(u16),
+}
+
+/// Behavior for the events received when the notification channel is full
+///
+/// Applied to the [`RelayPoolNotification::Event`](super::RelayPoolNotification::Event) notifications
+/// and to the [`RelayPoolNotification::Message`](super::RelayPoolNotification::Message) notifications wrapping an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EventOverflowPolicy {
+    /// Drop the oldest notifications
+    ///
+    /// Reported by [`RelayPoolNotification::NotificationsDropped`](super::RelayPoolNotification::NotificationsDropped).
+    #[default]
+    DropOldest,
+    /// Drop the new events
+    ///
+    /// Reported by [`RelayPoolNotification::EventDropped`](super::RelayPoolNotification::EventDropped).
+    DropNewest,
+    /// Stop handling the messages of the relay until the consumers catch up
+    Block,
+}
+
+/// Weights of the relay score components
+///
+/// Every component is normalized in the `0.0..=1.0` range, where `1.0` is the best value.
+/// The score is the weighted average of the components.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RelayScoreWeights {
+    /// Average latency weight (`1 / (1 + secs)`; `0.0` if unknown)
+    pub latency: f64,
+    /// Reconnections weight (`1 / (1 + reconnections)`)
+    pub reconnections: f64,
+    /// Uptime weight (successful connections / attempts)
+    pub uptime: f64,
+    /// Events throughput weight (`eps / (1 + eps)`)
+    pub events: f64,
+}
+
+impl Default for RelayScoreWeights {
+    fn default() -> Self {
+        Self {
+            latency: 1.0,
+            reconnections: 1.0,
+            uptime: 1.0,
+            events: 1.0,
+        }
+    }
+}
+
+impl RelayScoreWeights {
+    pub(crate) fn score(
+        &self,
+        latency: Option<Duration>,
+        reconnections: usize,
+        uptime: f64,
+        events_per_second: f64,
+    ) -> f64 {
+        let total: f64 = self.latency + self.reconnections + self.uptime + self.events;
+        if total <= 0.0 {
+            return 0.0;
+        }
+
+        let latency: f64 = match latency {
+            Some(latency) => 1.0 / (1.0 + latency.as_secs_f64()),
+            None => 0.0,
+        };
+        let reconnections: f64 = 1.0 / (1.0 + reconnections as f64);
+        let events: f64 = events_per_second / (1.0 + events_per_second);
+
+        (self.latency * latency
+            + self.reconnections * reconnections
+            + self.uptime * uptime.clamp(0.0, 1.0)
+            + self.events * events)
+            / total
+    }
+}
+
 /// Relay Pool Options
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct RelayPoolOptions {
     pub(super) notification_channel_size: usize,
+    pub(super) sticky_relays: bool,
+    pub(super) coalesce_subscriptions: bool,
+    pub(super) event_order: EventOrder,
+    #[cfg(feature = "nip11")]
+    pub(super) include_unknown_nips: Option<bool>,
+    pub(super) event_overflow_policy: EventOverflowPolicy,
+    pub(super) connect_concurrency: Option<usize>,
+    pub(super) relay_policy: RelayPolicy,
+    pub(super) throughput_window: Duration,
+    pub(super) score_weights: RelayScoreWeights,
+    subscription_id_prefix: Option<String>,
+    pub(super) on_invalid_signature: InvalidSigPolicy,
+    pub(super) require_tags: HashMap<Kind, Vec<SingleLetterTag>>,
 }
 
 impl Default for RelayPoolOptions {
     fn default() -> Self {
         Self {
             notification_channel_size: 4096,
+            sticky_relays: false,
+            coalesce_subscriptions: false,
+            event_order: EventOrder::default(),
+            #[cfg(feature = "nip11")]
+            include_unknown_nips: None,
+            event_overflow_policy: EventOverflowPolicy::default(),
+            connect_concurrency: None,
+            relay_policy: RelayPolicy::AllowAll,
+            throughput_window: Duration::from_secs(60),
+            score_weights: RelayScoreWeights::default(),
+            subscription_id_prefix: None,
+            on_invalid_signature: InvalidSigPolicy::default(),
+            require_tags: HashMap::new(),
         }
     }
 }
@@ -25,8 +277,310 @@ impl RelayPoolOptions {
     }
 
     /// Notification channel size (default: 4096)
+    ///
+    /// When a consumer is slower than the relays, the oldest notifications are dropped
+    /// and [`RelayPoolNotification::NotificationsDropped`](super::RelayPoolNotification::NotificationsDropped) is delivered
+    /// by [`RelayPool::handle_notifications`](super::RelayPool::handle_notifications).
     pub fn notification_channel_size(mut self, size: usize) -> Self {
         self.notification_channel_size = size;
         self
     }
+
+    /// Behavior for the events received when the notification channel is full (default: [`EventOverflowPolicy::DropOldest`])
+    pub fn event_overflow_policy(mut self, policy: EventOverflowPolicy) -> Self {
+        self.event_overflow_policy = policy;
+        self
+    }
+
+    /// Prefer the relay that delivered the newest version of a replaceable event (default: false)
+    ///
+    /// When enabled, the pool remembers which relay gave the newest version of a `(public key, kind)` replaceable event
+    /// and, on subsequent queries for the same event, query only that relay.
+    /// The query is widened to all relays if the preferred one is disconnected or returns no events.
+    pub fn sticky_relays(mut self, enable: bool) -> Self {
+        self.sticky_relays = enable;
+        self
+    }
+
+    /// Reuse active subscriptions with the same filters (default: false)
+    ///
+    /// When enabled, [`RelayPool::subscribe`](super::RelayPool::subscribe) returns the ID of an already active subscription
+    /// with the same filters, instead of sending a new `REQ` to relays.
+    /// The subscription is reference-counted: the `CLOSE` is sent only when the last consumer unsubscribes.
+    ///
+    /// Auto-closing subscriptions and subscriptions with custom ID are never coalesced.
+    pub fn coalesce_subscriptions(mut self, enable: bool) -> Self {
+        self.coalesce_subscriptions = enable;
+        self
+    }
+
+    /// Order of the events returned by `get_events_of` and `get_events_from` (default: [`EventOrder::NewestFirst`])
+    pub fn event_order(mut self, order: EventOrder) -> Self {
+        self.event_order = order;
+        self
+    }
+
+    /// Consider relays without a known NIP-11 document as supporting every NIP
+    ///
+    /// Used by [`RelayPool::relays_supporting`](super::RelayPool::relays_supporting) and when routing search (NIP-50)
+    /// or count (NIP-45) requests.
+    ///
+    /// By default, relays without a known document are included for search and excluded for count.
+    #[cfg(feature = "nip11")]
+    pub fn include_unknown_nips(mut self, include: bool) -> Self {
+        self.include_unknown_nips = Some(include);
+        self
+    }
+
+    /// Max number of relays that attempt the initial connection at the same time (default: `None`, all at once)
+    ///
+    /// The other relays are queued until a connection attempt completes.
+    /// Used only by [`RelayPool::connect`](super::RelayPool::connect) when a connection timeout is set.
+    pub fn connect_concurrency(mut self, max: Option<usize>) -> Self {
+        self.connect_concurrency = max;
+        self
+    }
+
+    /// Relays that can be added to the pool (default: [`RelayPolicy::AllowAll`])
+    ///
+    /// Adding a relay not allowed by the policy returns an error.
+    pub fn relay_policy(mut self, policy: RelayPolicy) -> Self {
+        self.relay_policy = policy;
+        self
+    }
+
+    /// Window used to calculate the events throughput of relays (default: 60 secs, max: 10 min)
+    ///
+    /// Used by [`RelayPool::slow_relays`](super::RelayPool::slow_relays).
+    pub fn throughput_window(mut self, window: Duration) -> Self {
+        self.throughput_window = window;
+        self
+    }
+
+    /// Weights used to calculate the relay scores (default: every component weights `1.0`)
+    ///
+    /// Used by [`RelayPool::scores`](super::RelayPool::scores) and [`RelayPool::prune_low_scoring`](super::RelayPool::prune_low_scoring).
+    pub fn score_weights(mut self, weights: RelayScoreWeights) -> Self {
+        self.score_weights = weights;
+        self
+    }
+
+    /// Prefix of the auto-generated subscription IDs (default: none)
+    ///
+    /// Useful to correlate the subscriptions in the relay logs (i.e. `myapp-`).
+    pub fn subscription_id_prefix<S>(mut self, prefix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.subscription_id_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Behavior for the events failing the signature verification (default: [`InvalidSigPolicy::Drop`])
+    pub fn on_invalid_signature(mut self, policy: InvalidSigPolicy) -> Self {
+        self.on_invalid_signature = policy;
+        self
+    }
+
+    /// Tags required per [`Kind`] (default: none)
+    ///
+    /// Received events missing a required tag (i.e. a kind `6` repost without the `e` tag) are dropped
+    /// and a [`RelayPoolNotification::Rejected`](super::RelayPoolNotification::Rejected) is sent.
+    pub fn require_tags(mut self, tags: HashMap<Kind, Vec<SingleLetterTag>>) -> Self {
+        self.require_tags = tags;
+        self
+    }
+
+    /// Generate new random [`SubscriptionId`], with the prefix (if any)
+    pub(crate) fn generate_subscription_id(&self) -> SubscriptionId {
+        let id: SubscriptionId = SubscriptionId::generate();
+        match &self.subscription_id_prefix {
+            Some(prefix) => SubscriptionId::new(format!("{prefix}{id}")),
+            None => id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr::{EventBuilder, Keys, Timestamp};
+
+    use super::*;
+
+    fn events() -> Vec<Event> {
+        let keys = Keys::generate();
+        [2, 1, 2]
+            .into_iter()
+            .enumerate()
+            .map(|(i, created_at)| {
+                EventBuilder::text_note(format!("Text note {i}"), [])
+                    .custom_created_at(Timestamp::from(created_at))
+                    .to_event(&keys)
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_event_order() {
+        let received: Vec<Event> = events();
+
+        // As received
+        let mut as_received = received.clone();
+        EventOrder::AsReceived.sort(&mut as_received);
+        assert_eq!(as_received, received);
+
+        // Oldest first, with ties sorted by ID
+        let mut oldest = received.clone();
+        EventOrder::OldestFirst.sort(&mut oldest);
+        assert_eq!(oldest[0].created_at(), Timestamp::from(1));
+        assert_eq!(oldest[1].created_at(), oldest[2].created_at());
+        assert!(oldest[1].id() < oldest[2].id());
+
+        // Newest first is the reverse of oldest first
+        let mut newest = received;
+        EventOrder::NewestFirst.sort(&mut newest);
+        oldest.reverse();
+        assert_eq!(newest, oldest);
+    }
+
+    #[test]
+    fn test_event_order_truncate() {
+        let received: Vec<Event> = events();
+
+        // The newest events are kept, whatever is the order
+        let mut oldest = received.clone();
+        EventOrder::OldestFirst.truncate_and_sort(&mut oldest, Some(2));
+        assert_eq!(oldest.len(), 2);
+        assert!(oldest.iter().all(|e| e.created_at() == Timestamp::from(2)));
+        assert!(oldest[0].id() < oldest[1].id());
+
+        // As received: keep the order of the kept events
+        let mut as_received = received.clone();
+        EventOrder::AsReceived.truncate_and_sort(&mut as_received, Some(2));
+        assert_eq!(as_received, vec![received[0].clone(), received[2].clone()]);
+
+        // No limit
+        let mut newest = received.clone();
+        EventOrder::NewestFirst.truncate_and_sort(&mut newest, None);
+        assert_eq!(newest.len(), received.len());
+    }
+
+    #[test]
+    fn test_relay_policy() {
+        let damus = Url::parse("wss://relay.damus.io").unwrap();
+        let nos = Url::parse("wss://nos.lol").unwrap();
+
+        assert!(RelayPolicy::AllowAll.is_allowed(&damus));
+
+        let allowlist =
+            RelayPolicy::Allowlist([RelayMatcher::from(damus.clone())].into_iter().collect());
+        assert!(allowlist.is_allowed(&damus));
+        assert!(!allowlist.is_allowed(&nos));
+
+        let denylist =
+            RelayPolicy::Denylist([RelayMatcher::from(damus.clone())].into_iter().collect());
+        assert!(!denylist.is_allowed(&damus));
+        assert!(denylist.is_allowed(&nos));
+
+        // Normalized URLs
+        let normalized = Url::parse("wss://Relay.Damus.io/").unwrap();
+        assert!(allowlist.is_allowed(&normalized));
+        assert!(!denylist.is_allowed(&normalized));
+
+        // Scheme-agnostic
+        let ws = Url::parse("ws://relay.damus.io").unwrap();
+        assert!(allowlist.is_allowed(&ws));
+
+        // Different path or port
+        let port = Url::parse("wss://relay.damus.io:4848").unwrap();
+        assert!(!allowlist.is_allowed(&port));
+        let path = Url::parse("wss://relay.damus.io/path").unwrap();
+        assert!(!allowlist.is_allowed(&path));
+        assert!(denylist.is_allowed(&path));
+    }
+
+    #[test]
+    fn test_relay_score() {
+        let weights = RelayScoreWeights::default();
+
+        let best: f64 = weights.score(Some(Duration::from_millis(50)), 0, 1.0, 20.0);
+        let slow: f64 = weights.score(Some(Duration::from_secs(2)), 0, 1.0, 20.0);
+        let unstable: f64 = weights.score(Some(Duration::from_millis(50)), 10, 0.5, 20.0);
+        let idle: f64 = weights.score(Some(Duration::from_millis(50)), 0, 1.0, 0.0);
+        let unknown: f64 = weights.score(None, 0, 0.0, 0.0);
+
+        assert!(best > slow);
+        assert!(slow > unstable);
+        assert!(best > idle);
+        assert!(unknown < idle);
+        assert!((0.0..=1.0).contains(&best));
+
+        // Only latency
+        let weights = RelayScoreWeights {
+            latency: 1.0,
+            reconnections: 0.0,
+            uptime: 0.0,
+            events: 0.0,
+        };
+        assert_eq!(
+            weights.score(Some(Duration::from_secs(1)), 10, 0.0, 0.0),
+            0.5
+        );
+
+        // No weights
+        let weights = RelayScoreWeights {
+            latency: 0.0,
+            reconnections: 0.0,
+            uptime: 0.0,
+            events: 0.0,
+        };
+        assert_eq!(
+            weights.score(Some(Duration::from_secs(1)), 0, 1.0, 10.0),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_relay_matcher() {
+        let matcher = RelayMatcher::from_str("*.Example.com").unwrap();
+        assert_eq!(matcher, RelayMatcher::Domain(String::from("example.com")));
+
+        // Suffix matches
+        assert!(matcher.matches(&Url::parse("wss://example.com").unwrap()));
+        assert!(matcher.matches(&Url::parse("wss://relay.example.com").unwrap()));
+        assert!(matcher.matches(&Url::parse("ws://a.b.example.com:8080/path").unwrap()));
+
+        // Lookalike hosts
+        assert!(!matcher.matches(&Url::parse("wss://evilexample.com").unwrap()));
+        assert!(!matcher.matches(&Url::parse("wss://example.com.evil.org").unwrap()));
+        assert!(!matcher.matches(&Url::parse("wss://example.co").unwrap()));
+
+        // Exact matches
+        let matcher = RelayMatcher::from_str("wss://relay.damus.io").unwrap();
+        assert!(matcher.matches(&Url::parse("wss://relay.damus.io/").unwrap()));
+        assert!(matcher.matches(&Url::parse("ws://relay.damus.io").unwrap()));
+        assert!(!matcher.matches(&Url::parse("wss://nostr.relay.damus.io").unwrap()));
+
+        let policy = RelayPolicy::Denylist(
+            [matcher, RelayMatcher::from_str("*.example.com").unwrap()]
+                .into_iter()
+                .collect(),
+        );
+        assert!(!policy.is_allowed(&Url::parse("wss://relay.example.com").unwrap()));
+        assert!(policy.is_allowed(&Url::parse("wss://nos.lol").unwrap()));
+
+        assert!(RelayMatcher::from_str("*.").is_err());
+    }
+
+    #[test]
+    fn test_generate_subscription_id() {
+        let opts = RelayPoolOptions::new().subscription_id_prefix("myapp-");
+        let id: SubscriptionId = opts.generate_subscription_id();
+        assert!(id.to_string().starts_with("myapp-"));
+        assert_ne!(id, opts.generate_subscription_id());
+
+        let id: SubscriptionId = RelayPoolOptions::new().generate_subscription_id();
+        assert_eq!(id.to_string().len(), 32);
+    }
 }
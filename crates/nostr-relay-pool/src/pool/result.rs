@@ -6,12 +6,13 @@ use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::ops::Deref;
 
+use nostr::message::MachineReadablePrefix;
 use nostr::{EventId, SubscriptionId, Url};
 
 /// Output
 ///
-/// Send or negentropy reconciliation output
-// TODO: use a better name?
+/// Result of an operation sent to many relays (i.e. send, fetch, count or negentropy reconciliation):
+/// the value and the outcome of every relay.
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct Output<T>
 where
@@ -25,6 +26,85 @@ where
     pub failed: HashMap<Url, Option<String>>,
 }
 
+/// Publish output of a single relay
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublishOutput {
+    /// Whether the relay accepted the event
+    pub accepted: bool,
+    /// Machine-readable prefix of the rejection message (if any)
+    pub reason: Option<MachineReadablePrefix>,
+    /// Rejection message (if any)
+    pub message: Option<String>,
+}
+
+/// Difference discovered during a negentropy reconciliation
+///
+/// Emitted as soon as it's discovered, before the events transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReconcileItem {
+    /// The relay has this event, missing locally
+    NeedDownload(EventId),
+    /// This event is stored locally, missing on the relay
+    NeedUpload(EventId),
+}
+
+/// Negentropy reconciliation output
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reconciliation {
+    /// Events sent to the relays
+    pub sent: HashSet<EventId>,
+    /// Events received from the relays
+    pub received: HashSet<EventId>,
+    /// Events that the relays have but are missing locally
+    pub local_missing: HashSet<EventId>,
+    /// Events that are stored locally but are missing on the relays
+    pub remote_missing: HashSet<EventId>,
+}
+
+impl Reconciliation {
+    /// Merge with another [`Reconciliation`]
+    pub fn merge(&mut self, other: Self) {
+        self.sent.extend(other.sent);
+        self.received.extend(other.received);
+        self.local_missing.extend(other.local_missing);
+        self.remote_missing.extend(other.remote_missing);
+    }
+}
+
+impl<T> Output<T>
+where
+    T: Debug,
+{
+    /// Check if at least one relay succeeded
+    #[inline]
+    pub fn any_success(&self) -> bool {
+        !self.success.is_empty()
+    }
+
+    /// Check if all the relays failed
+    ///
+    /// Return `false` if no relay was involved.
+    #[inline]
+    pub fn all_failed(&self) -> bool {
+        self.success.is_empty() && !self.failed.is_empty()
+    }
+
+    /// Merge with another [`Output`]
+    ///
+    /// The values are combined with `f`.
+    /// A relay that succeeded in any of the outputs is removed from the failed ones.
+    pub fn merge<F>(&mut self, other: Self, f: F)
+    where
+        F: FnOnce(&mut T, T),
+    {
+        f(&mut self.val, other.val);
+        self.success.extend(other.success);
+        self.failed.extend(other.failed);
+        let success: &HashSet<Url> = &self.success;
+        self.failed.retain(|url, ..| !success.contains(url));
+    }
+}
+
 impl Output<()> {
     pub(super) fn success(url: Url) -> Self {
         let mut success: HashSet<Url> = HashSet::with_capacity(1);
@@ -54,6 +134,41 @@ impl Output<EventId> {
     pub fn id(&self) -> &EventId {
         self.deref()
     }
+
+    /// Get [`PublishOutput`] of every relay
+    ///
+    /// The rejection reason is parsed from the `OK` message sent by the relay.
+    pub fn publish_outputs(&self) -> HashMap<Url, PublishOutput> {
+        let accepted = self.success.iter().map(|url| {
+            (
+                url.clone(),
+                PublishOutput {
+                    accepted: true,
+                    reason: None,
+                    message: None,
+                },
+            )
+        });
+        let rejected = self.failed.iter().map(|(url, message)| {
+            (
+                url.clone(),
+                PublishOutput {
+                    accepted: false,
+                    reason: message.as_deref().and_then(MachineReadablePrefix::parse),
+                    message: message.clone(),
+                },
+            )
+        });
+        accepted.chain(rejected).collect()
+    }
+}
+
+impl Output<HashMap<Url, usize>> {
+    /// Get total count of all relays
+    #[inline]
+    pub fn total(&self) -> usize {
+        self.val.values().sum()
+    }
 }
 
 impl Output<SubscriptionId> {
@@ -63,3 +178,104 @@ impl Output<SubscriptionId> {
         self.deref()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_outputs() {
+        let accepted = Url::parse("wss://relay.damus.io").unwrap();
+        let rejected = Url::parse("wss://nos.lol").unwrap();
+
+        let mut output: Output<EventId> = Output::default();
+        output.success.insert(accepted.clone());
+        output.failed.insert(
+            rejected.clone(),
+            Some(String::from("pow: difficulty 10 is less than 20")),
+        );
+
+        let outputs = output.publish_outputs();
+        assert!(outputs.get(&accepted).unwrap().accepted);
+
+        let rejected = outputs.get(&rejected).unwrap();
+        assert!(!rejected.accepted);
+        assert_eq!(rejected.reason, Some(MachineReadablePrefix::Pow));
+    }
+
+    #[test]
+    fn test_output_predicates() {
+        let a = Url::parse("wss://relay.damus.io").unwrap();
+        let b = Url::parse("wss://nos.lol").unwrap();
+
+        let output: Output<()> = Output::default();
+        assert!(!output.any_success());
+        assert!(!output.all_failed());
+
+        // Mixed outcome
+        let mut output: Output<Vec<u8>> = Output::default();
+        output.success.insert(a.clone());
+        output
+            .failed
+            .insert(b.clone(), Some(String::from("timeout")));
+        assert!(output.any_success());
+        assert!(!output.all_failed());
+
+        // All failed
+        let mut failed: Output<Vec<u8>> = Output::default();
+        failed.failed.insert(a.clone(), None);
+        assert!(!failed.any_success());
+        assert!(failed.all_failed());
+
+        // Merge
+        let mut retry: Output<Vec<u8>> = Output {
+            val: vec![1, 2],
+            success: HashSet::from([b.clone()]),
+            failed: HashMap::new(),
+        };
+        failed.merge(output, |val, other| val.extend(other));
+        assert_eq!(failed.success, HashSet::from([a.clone()]));
+        assert!(failed.failed.contains_key(&b));
+
+        retry.merge(failed, |val, other| val.extend(other));
+        assert_eq!(retry.val, vec![1, 2]);
+        assert_eq!(retry.success, HashSet::from([a, b]));
+        assert!(retry.failed.is_empty());
+    }
+
+    #[test]
+    fn test_count_total() {
+        let mut output: Output<HashMap<Url, usize>> = Output::default();
+        assert_eq!(output.total(), 0);
+
+        output
+            .val
+            .insert(Url::parse("wss://relay.damus.io").unwrap(), 10);
+        output.val.insert(Url::parse("wss://nos.lol").unwrap(), 5);
+        assert_eq!(output.total(), 15);
+    }
+
+    #[test]
+    fn test_reconciliation_merge() {
+        let id1 =
+            EventId::from_hex("70b10f70c1318967eddf12527799411b1a9780ad9c43858f5e5fcd45486a13a5")
+                .unwrap();
+        let id2 =
+            EventId::from_hex("7469af3be8c8e06e1b50ef1caceba30392ddc0b6614507398b7d7daa4c218e96")
+                .unwrap();
+
+        let mut a = Reconciliation::default();
+        a.local_missing.insert(id1);
+        a.received.insert(id1);
+
+        let mut b = Reconciliation::default();
+        b.local_missing.insert(id1);
+        b.remote_missing.insert(id2);
+
+        a.merge(b);
+        assert_eq!(a.local_missing.len(), 1);
+        assert_eq!(a.received.len(), 1);
+        assert!(a.remote_missing.contains(&id2));
+        assert!(a.sent.is_empty());
+    }
+}
@@ -0,0 +1,92 @@
+// Copyright (c) 2022-2023 Yuki Kishimoto
+// Copyright (c) 2023-2024 Rust Nostr Developers
+// Distributed under the MIT software license
+
+//! Relay status receiver
+
+use std::collections::VecDeque;
+
+use nostr::Url;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+use tokio::sync::broadcast::Receiver;
+
+use crate::relay::RelayStatus;
+
+/// Receiver of relay status changes
+///
+/// Deliver the current status of every relay first and then the status changes.
+#[derive(Debug)]
+pub struct RelayStatusReceiver {
+    initial: VecDeque<(Url, RelayStatus)>,
+    receiver: Receiver<(Url, RelayStatus)>,
+}
+
+impl RelayStatusReceiver {
+    pub(crate) fn new<I>(initial: I, receiver: Receiver<(Url, RelayStatus)>) -> Self
+    where
+        I: IntoIterator<Item = (Url, RelayStatus)>,
+    {
+        Self {
+            initial: initial.into_iter().collect(),
+            receiver,
+        }
+    }
+
+    /// Wait for the next status
+    ///
+    /// Return `None` if the pool has been dropped.
+    /// If the receiver lags behind, the oldest status changes are skipped.
+    pub async fn recv(&mut self) -> Option<(Url, RelayStatus)> {
+        if let Some(status) = self.initial.pop_front() {
+            return Some(status);
+        }
+
+        loop {
+            match self.receiver.recv().await {
+                Ok(status) => return Some(status),
+                Err(RecvError::Lagged(..)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Get the next status, if immediately available
+    pub fn try_recv(&mut self) -> Option<(Url, RelayStatus)> {
+        if let Some(status) = self.initial.pop_front() {
+            return Some(status);
+        }
+
+        loop {
+            match self.receiver.try_recv() {
+                Ok(status) => return Some(status),
+                Err(TryRecvError::Lagged(..)) => continue,
+                Err(TryRecvError::Empty | TryRecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::broadcast;
+
+    use super::*;
+
+    #[test]
+    fn test_status_receiver() {
+        let url = Url::parse("wss://relay.damus.io").unwrap();
+        let (sender, receiver) = broadcast::channel(16);
+        let mut statuses =
+            RelayStatusReceiver::new([(url.clone(), RelayStatus::Initialized)], receiver);
+
+        sender.send((url.clone(), RelayStatus::Connecting)).unwrap();
+
+        // Initial status first, then the transition
+        assert_eq!(
+            statuses.try_recv(),
+            Some((url.clone(), RelayStatus::Initialized))
+        );
+        assert_eq!(statuses.try_recv(), Some((url, RelayStatus::Connecting)));
+        assert_eq!(statuses.try_recv(), None);
+    }
+}
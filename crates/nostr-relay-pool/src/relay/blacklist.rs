@@ -15,7 +15,8 @@ use tokio::sync::RwLock;
 pub struct RelayBlacklist {
     ids: Arc<RwLock<HashSet<EventId>>>,
     public_keys: Arc<RwLock<HashSet<PublicKey>>>,
-    //words: Arc<RwLock<HashSet<String>>>,
+    /// Lowercase words
+    words: Arc<RwLock<HashSet<String>>>,
 }
 
 impl RelayBlacklist {
@@ -24,13 +25,11 @@ impl RelayBlacklist {
     where
         I: IntoIterator<Item = EventId>,
         P: IntoIterator<Item = PublicKey>,
-        // W: IntoIterator<Item = S>,
-        // S: Into<String>
     {
         Self {
             ids: Arc::new(RwLock::new(ids.into_iter().collect())),
             public_keys: Arc::new(RwLock::new(public_keys.into_iter().collect())),
-            //words: Arc::new(RwLock::new(words.into_iter().map(|w| w.into()).collect())),
+            words: Arc::new(RwLock::new(HashSet::new())),
         }
     }
 
@@ -104,33 +103,50 @@ impl RelayBlacklist {
         public_keys.contains(public_key)
     }
 
-    // /// Add word to blacklist
-    // pub async fn add_words<I, S>(&self, iter: I)
-    // where
-    //     I: IntoIterator<Item = S>,
-    //     S: Into<String>
-    // {
-    //     let mut words = self.words.write().await;
-    //     words.extend(iter.into_iter().map(|w| w.into()));
-    // }
-    //
-    // /// Remove word from blacklist
-    // pub async fn remove_word<S>(&self, word: S)
-    // where
-    //     S: AsRef<str>
-    // {
-    //     let mut words = self.words.write().await;
-    //     words.remove(word.as_ref());
-    // }
-    //
-    // /// Check if blacklist contains word
-    // pub async fn has_word<S>(&self, word: S) -> bool
-    // where
-    //     S: AsRef<str>
-    // {
-    //     let words = self.words.read().await;
-    //     words.contains(word.as_ref())
-    // }
+    /// Add words or phrases to blacklist
+    ///
+    /// Words are matched case-insensitively.
+    pub async fn add_words<I, S>(&self, iter: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut words = self.words.write().await;
+        words.extend(iter.into_iter().map(|w| w.into().to_lowercase()));
+    }
+
+    /// Remove words or phrases from blacklist
+    pub async fn remove_words<I, S>(&self, iter: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut words = self.words.write().await;
+        for word in iter.into_iter() {
+            words.remove(&word.as_ref().to_lowercase());
+        }
+    }
+
+    /// Check if blacklist contains word
+    pub async fn has_word<S>(&self, word: S) -> bool
+    where
+        S: AsRef<str>,
+    {
+        let words = self.words.read().await;
+        words.contains(&word.as_ref().to_lowercase())
+    }
+
+    /// Check if content contains any blacklisted word
+    pub async fn has_word_in(&self, content: &str) -> bool {
+        let words = self.words.read().await;
+
+        if words.is_empty() {
+            return false;
+        }
+
+        let content: String = content.to_lowercase();
+        words.iter().any(|word| content.contains(word.as_str()))
+    }
 
     /// Remove everything
     pub async fn clear(&self) {
@@ -140,7 +156,7 @@ impl RelayBlacklist {
         let mut public_keys = self.public_keys.write().await;
         public_keys.clear();
 
-        // let mut words = self.words.write().await;
-        // words.clear();
+        let mut words = self.words.write().await;
+        words.clear();
     }
 }
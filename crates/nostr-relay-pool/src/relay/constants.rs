@@ -9,8 +9,21 @@ pub const MIN_UPTIME: f64 = 0.90;
 
 pub const PING_INTERVAL: Duration = Duration::from_secs(50); // Used also for latency calculation
 
+/// Max time to wait for the pong of an on-demand ping
+#[cfg(not(target_arch = "wasm32"))]
+pub const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub const WEBSOCKET_TX_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// Interval used to check if the notification channel has free slots
+pub const OVERFLOW_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Interval used to check if the relay is idle
+pub const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Max window used to calculate the events throughput
+pub const THROUGHPUT_MAX_WINDOW: Duration = Duration::from_secs(600);
+
 /// Maximum number of reads to be saved in memory to calculate latency
 #[cfg(not(target_arch = "wasm32"))]
 pub const LATENCY_MAX_VALUES: usize = 50;
@@ -7,7 +7,9 @@ use std::collections::HashMap;
 use async_utility::thread;
 use nostr::message::relay::NegentropyErrorCode;
 use nostr::message::MessageHandleError;
-use nostr::{event, negentropy, EventId, PublicKey};
+#[cfg(feature = "nip11")]
+use nostr::nips::nip11;
+use nostr::{event, negentropy, EventId, PublicKey, SubscriptionId};
 use nostr_database::DatabaseError;
 use thiserror::Error;
 
@@ -26,6 +28,10 @@ pub enum Error {
     /// Negentropy error
     #[error(transparent)]
     Negentropy(#[from] negentropy::Error),
+    /// NIP-11 error
+    #[cfg(feature = "nip11")]
+    #[error(transparent)]
+    NIP11(#[from] nip11::Error),
     /// Database error
     #[error(transparent)]
     Database(#[from] DatabaseError),
@@ -85,6 +91,9 @@ pub enum Error {
     /// Filters empty
     #[error("filters empty")]
     FiltersEmpty,
+    /// Subscription not found
+    #[error("subscription '{0}' not found")]
+    SubscriptionNotFound(SubscriptionId),
     /// Reconciliation error
     #[error("negentropy reconciliation error: {0}")]
     NegentropyReconciliation(NegentropyErrorCode),
@@ -118,6 +127,22 @@ pub enum Error {
         /// Max tags num
         max_size: usize,
     },
+    /// Too many subscriptions
+    #[error("too many subscriptions (max. {max})")]
+    TooManySubscriptions {
+        /// Max subscriptions
+        max: u16,
+    },
+    /// Filter exceeds the relay limits
+    #[error("filter `{field}` has too many values: {len} (max. {max})")]
+    FilterTooLarge {
+        /// Offending field
+        field: String,
+        /// Field length (or `limit` value)
+        len: usize,
+        /// Max allowed
+        max: usize,
+    },
     /// Event expired
     #[error("event expired")]
     EventExpired,
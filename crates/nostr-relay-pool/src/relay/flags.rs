@@ -32,6 +32,11 @@ impl RelayServiceFlags {
     /// PING means that
     pub const PING: Self = Self(1 << 2);
 
+    /// ARCHIVE means that the relay is used only for historical queries.
+    ///
+    /// Archive relays are skipped by subscriptions and queries sent to all relays of the pool.
+    pub const ARCHIVE: Self = Self(1 << 3);
+
     /// Add [RelayServiceFlags] together.
     pub fn add(&mut self, other: Self) -> Self {
         self.0 |= other.0;
@@ -123,6 +128,12 @@ impl AtomicRelayServiceFlags {
             });
     }
 
+    /// Replace flags with the ones of `other`
+    pub(crate) fn replace(&self, other: &Self) {
+        let other: u64 = other.flags.load(Ordering::SeqCst);
+        self.flags.store(other, Ordering::SeqCst);
+    }
+
     /// Check whether [RelayServiceFlags] are included in this one.
     pub fn has(&self, flags: RelayServiceFlags) -> bool {
         let _f: u64 = self.flags.load(Ordering::SeqCst);
@@ -144,6 +155,11 @@ impl AtomicRelayServiceFlags {
     pub fn has_ping(&self) -> bool {
         self.has(RelayServiceFlags::PING)
     }
+
+    /// Check if `ARCHIVE` service is enabled
+    pub fn has_archive(&self) -> bool {
+        self.has(RelayServiceFlags::ARCHIVE)
+    }
 }
 
 #[cfg(test)]
@@ -156,6 +172,7 @@ mod tests {
             RelayServiceFlags::READ,
             RelayServiceFlags::WRITE,
             RelayServiceFlags::PING,
+            RelayServiceFlags::ARCHIVE,
         ];
 
         let flags = RelayServiceFlags::NONE;
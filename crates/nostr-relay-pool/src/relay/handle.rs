@@ -0,0 +1,66 @@
+// Copyright (c) 2022-2023 Yuki Kishimoto
+// Copyright (c) 2023-2024 Rust Nostr Developers
+// Distributed under the MIT software license
+
+//! Negentropy sync handle
+
+use async_utility::futures_util::future;
+use async_utility::thread::{self, JoinHandle};
+use tokio::sync::watch;
+
+use super::Error;
+use crate::pool::Reconciliation;
+
+/// Negentropy sync handle
+///
+/// Returned by [`Relay::reconcile_cancellable`](super::Relay::reconcile_cancellable)
+/// and [`RelayPool::reconcile_cancellable`](crate::RelayPool::reconcile_cancellable).
+#[derive(Debug)]
+pub struct SyncHandle<T = Reconciliation, E = Error> {
+    abort: watch::Sender<bool>,
+    handle: JoinHandle<Result<T, E>>,
+}
+
+impl<T, E> SyncHandle<T, E>
+where
+    E: From<thread::Error>,
+{
+    pub(crate) fn new(abort: watch::Sender<bool>, handle: JoinHandle<Result<T, E>>) -> Self {
+        Self { abort, handle }
+    }
+
+    /// Abort the sync
+    ///
+    /// The negentropy sessions are closed (`NEG-CLOSE`) and [`SyncHandle::output`] resolves with the partial [`Reconciliation`].
+    /// Calling it after the sync is completed is a no-op.
+    #[inline]
+    pub fn abort(&self) {
+        self.abort.send_replace(true);
+    }
+
+    /// Check if the sync has been aborted
+    #[inline]
+    pub fn is_aborted(&self) -> bool {
+        *self.abort.borrow()
+    }
+
+    /// Wait for the sync to complete (or to be aborted)
+    pub async fn output(self) -> Result<T, E> {
+        self.handle.join().await?
+    }
+}
+
+/// Resolve when the sync is aborted
+///
+/// Never resolve if the handle is dropped without aborting.
+pub(super) async fn aborted(abort: &mut watch::Receiver<bool>) {
+    loop {
+        if *abort.borrow() {
+            return;
+        }
+
+        if abort.changed().await.is_err() {
+            future::pending::<()>().await;
+        }
+    }
+}
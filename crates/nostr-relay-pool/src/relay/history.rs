@@ -0,0 +1,87 @@
+// Copyright (c) 2022-2023 Yuki Kishimoto
+// Copyright (c) 2023-2024 Rust Nostr Developers
+// Distributed under the MIT software license
+
+//! Relay connection history
+
+use std::collections::VecDeque;
+
+use nostr::Timestamp;
+
+use super::RelayStatus;
+
+/// Connection lifecycle event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionEvent {
+    /// When the status changed
+    pub timestamp: Timestamp,
+    /// New status
+    pub status: RelayStatus,
+    /// Reason of the status change (if known)
+    pub reason: Option<String>,
+}
+
+/// Bounded connection history: the oldest events are evicted first
+#[derive(Debug, Clone)]
+pub(crate) struct ConnectionHistory {
+    events: VecDeque<ConnectionEvent>,
+    capacity: usize,
+}
+
+impl ConnectionHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, event: ConnectionEvent) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        while self.events.len() >= self.capacity {
+            self.events.pop_front();
+        }
+
+        self.events.push_back(event);
+    }
+
+    pub fn to_vec(&self) -> Vec<ConnectionEvent> {
+        self.events.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(timestamp: u64) -> ConnectionEvent {
+        ConnectionEvent {
+            timestamp: Timestamp::from(timestamp),
+            status: RelayStatus::Disconnected,
+            reason: None,
+        }
+    }
+
+    #[test]
+    fn test_history_eviction() {
+        let mut history = ConnectionHistory::new(3);
+
+        for timestamp in 0..4 {
+            history.push(event(timestamp));
+        }
+
+        let events = history.to_vec();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events, vec![event(1), event(2), event(3)]);
+    }
+
+    #[test]
+    fn test_history_disabled() {
+        let mut history = ConnectionHistory::new(0);
+        history.push(event(0));
+        assert!(history.to_vec().is_empty());
+    }
+}
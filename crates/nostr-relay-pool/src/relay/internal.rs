@@ -7,9 +7,11 @@
 use std::cmp;
 use std::collections::{BTreeSet, HashMap, HashSet};
 use std::net::SocketAddr;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
 
 use async_utility::{futures_util, thread, time};
 use async_wsocket::futures_util::{Future, SinkExt, StreamExt};
@@ -23,25 +25,40 @@ use nostr::nips::nip11::RelayInformationDocument;
 use nostr::secp256k1::rand::{self, Rng};
 use nostr::{
     ClientMessage, Event, EventId, Filter, JsonUtil, Keys, Kind, MissingPartialEvent, PartialEvent,
-    RawRelayMessage, RelayMessage, SubscriptionId, Timestamp, Url,
+    RawRelayMessage, RelayMessage, SingleLetterTag, SubscriptionId, Timestamp, Url,
 };
 use nostr_database::{DynNostrDatabase, Order};
 use tokio::sync::mpsc::{self, Receiver, Sender};
-use tokio::sync::{broadcast, oneshot, watch, Mutex, MutexGuard, RwLock};
+use tokio::sync::{broadcast, oneshot, watch, Mutex, MutexGuard, Notify, RwLock};
 
 use super::blacklist::RelayBlacklist;
 #[cfg(not(target_arch = "wasm32"))]
 use super::constants::HIGH_LATENCY;
-use super::constants::{MIN_ATTEMPTS, MIN_UPTIME, PING_INTERVAL, WEBSOCKET_TX_TIMEOUT};
+#[cfg(not(target_arch = "wasm32"))]
+use super::constants::PING_TIMEOUT;
+use super::constants::{
+    IDLE_CHECK_INTERVAL, MIN_ATTEMPTS, MIN_UPTIME, OVERFLOW_CHECK_INTERVAL, PING_INTERVAL,
+    WEBSOCKET_TX_TIMEOUT,
+};
 use super::flags::AtomicRelayServiceFlags;
+use super::handle::{self, SyncHandle};
+use super::history::{ConnectionEvent, ConnectionHistory};
+use super::last_seen;
+use super::limits::RelaySubscriptionLimits;
 use super::options::{
     FilterOptions, NegentropyOptions, RelayOptions, RelaySendOptions, SubscribeAutoCloseOptions,
     SubscribeOptions, MAX_ADJ_RETRY_SEC, MIN_RETRY_SEC, NEGENTROPY_BATCH_SIZE_DOWN,
     NEGENTROPY_HIGH_WATER_UP, NEGENTROPY_LOW_WATER_UP,
 };
-use super::stats::RelayConnectionStats;
+use super::split::SplitSubscriptions;
+use super::stats::{RelayConnectionStats, RelayStats};
+use super::tracer::{MessageDirection, MessageTracer};
+use super::validator::EventValidator;
 use super::{Error, RelayNotification, RelayStatus};
-use crate::pool::RelayPoolNotification;
+use crate::pool::{
+    EventOverflowPolicy, FilteredNotifications, GlobalLimits, InvalidSigPolicy, ReconcileItem,
+    Reconciliation, RelayPoolNotification,
+};
 
 struct NostrMessage {
     msgs: Vec<ClientMessage>,
@@ -54,6 +71,10 @@ enum RelayServiceEvent {
     None,
     /// Completely disconnect
     Terminate,
+    /// Close the current connection, without terminating the auto-reconnect loop
+    Reconnect,
+    /// Close the current connection for inactivity
+    Idle,
 }
 
 #[derive(Debug, Clone)]
@@ -140,6 +161,20 @@ impl RelayChannels {
 struct SubscriptionData {
     pub filters: Vec<Filter>,
     pub subscribed_at: Timestamp,
+    /// Timestamp of the newest event received for this subscription
+    pub last_event_at: Timestamp,
+    /// Signature of the filters (see [`last_seen::filters_hash`])
+    pub filters_hash: u64,
+    /// `EOSE` received for the last `REQ`
+    pub eose: bool,
+}
+
+/// Overflow policy of the external notification channel
+#[derive(Debug, Clone, Copy, Default)]
+struct EventOverflow {
+    policy: EventOverflowPolicy,
+    /// Size of the external notification channel
+    capacity: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -148,15 +183,53 @@ pub(crate) struct InternalRelay {
     status: Arc<RwLock<RelayStatus>>,
     #[cfg(feature = "nip11")]
     document: Arc<RwLock<RelayInformationDocument>>,
+    /// Unix timestamp of the last NIP-11 document update (`0` if never fetched)
+    #[cfg(feature = "nip11")]
+    document_updated_at: Arc<AtomicU64>,
     opts: RelayOptions,
     stats: RelayConnectionStats,
     blacklist: RelayBlacklist,
     database: Arc<DynNostrDatabase>,
     channels: RelayChannels,
     scheduled_for_termination: Arc<AtomicBool>,
+    history: Arc<RwLock<ConnectionHistory>>,
     pub(super) internal_notification_sender: broadcast::Sender<RelayNotification>,
+    /// Notifications for the [`Relay::notifications`](super::Relay::notifications) listeners
+    pub(super) user_notification_sender: broadcast::Sender<RelayNotification>,
     external_notification_sender: Arc<RwLock<Option<broadcast::Sender<RelayPoolNotification>>>>,
+    status_sender: Arc<RwLock<Option<broadcast::Sender<(Url, RelayStatus)>>>>,
     subscriptions: Arc<RwLock<HashMap<SubscriptionId, SubscriptionData>>>,
+    /// Explicit subscription limits (can be updated)
+    subscription_limits: Arc<RwLock<RelaySubscriptionLimits>>,
+    splits: Arc<RwLock<SplitSubscriptions>>,
+    /// Newest event seen per filters signature, kept across reconnections and re-subscriptions
+    last_seen: Arc<RwLock<HashMap<u64, Timestamp>>>,
+    validator: Arc<RwLock<Option<EventValidator>>>,
+    invalid_sig_policy: Arc<RwLock<InvalidSigPolicy>>,
+    event_overflow: Arc<RwLock<EventOverflow>>,
+    /// Events dropped since the last event sent to the external channel
+    overflowing: Arc<AtomicBool>,
+    required_tags: Arc<RwLock<HashMap<Kind, Vec<SingleLetterTag>>>>,
+    global_limits: Arc<RwLock<Option<GlobalLimits>>>,
+    /// Pool-wide mute list
+    mutes: Arc<RwLock<Option<RelayBlacklist>>>,
+    filtered_notifications: Arc<RwLock<Option<FilteredNotifications>>>,
+    /// Number of events received with invalid signature
+    invalid_signatures: Arc<AtomicUsize>,
+    banned: Arc<AtomicBool>,
+    /// Negentropy support in the current session (`None` if unknown)
+    negentropy_support: Arc<RwLock<Option<bool>>>,
+    tracer: Arc<RwLock<Option<MessageTracer>>>,
+    /// Avoid to lock the tracer if not set
+    has_tracer: Arc<AtomicBool>,
+    /// Timestamp of the last message sent or received (excluding pings)
+    last_activity: Arc<AtomicU64>,
+    waking_up: Arc<AtomicBool>,
+    /// Wake up the auto-reconnect loop, skipping the retry interval
+    retry_now: Arc<Notify>,
+    /// Pending on-demand pings, by nonce
+    #[cfg(not(target_arch = "wasm32"))]
+    on_demand_pings: Arc<Mutex<HashMap<u64, (Instant, oneshot::Sender<Duration>)>>>,
 }
 
 impl AtomicDestroyer for InternalRelay {
@@ -182,21 +255,51 @@ impl InternalRelay {
         opts: RelayOptions,
     ) -> Self {
         let (relay_notification_sender, ..) = broadcast::channel::<RelayNotification>(2048);
+        let (user_notification_sender, ..) =
+            broadcast::channel::<RelayNotification>(opts.notification_channel_size.max(1));
+        let history: ConnectionHistory = ConnectionHistory::new(opts.history_size);
+        let subscription_limits: RelaySubscriptionLimits = opts.limits.subscriptions;
 
         Self {
             url,
             status: Arc::new(RwLock::new(RelayStatus::Initialized)),
             #[cfg(feature = "nip11")]
             document: Arc::new(RwLock::new(RelayInformationDocument::new())),
+            #[cfg(feature = "nip11")]
+            document_updated_at: Arc::new(AtomicU64::new(0)),
             opts,
             stats: RelayConnectionStats::new(),
             blacklist,
             database,
             channels: RelayChannels::new(),
             scheduled_for_termination: Arc::new(AtomicBool::new(false)),
+            history: Arc::new(RwLock::new(history)),
             internal_notification_sender: relay_notification_sender,
+            user_notification_sender,
             external_notification_sender: Arc::new(RwLock::new(None)),
+            status_sender: Arc::new(RwLock::new(None)),
             subscriptions: Arc::new(RwLock::new(HashMap::new())),
+            subscription_limits: Arc::new(RwLock::new(subscription_limits)),
+            splits: Arc::new(RwLock::new(SplitSubscriptions::default())),
+            validator: Arc::new(RwLock::new(None)),
+            invalid_sig_policy: Arc::new(RwLock::new(InvalidSigPolicy::default())),
+            event_overflow: Arc::new(RwLock::new(EventOverflow::default())),
+            overflowing: Arc::new(AtomicBool::new(false)),
+            required_tags: Arc::new(RwLock::new(HashMap::new())),
+            global_limits: Arc::new(RwLock::new(None)),
+            mutes: Arc::new(RwLock::new(None)),
+            filtered_notifications: Arc::new(RwLock::new(None)),
+            invalid_signatures: Arc::new(AtomicUsize::new(0)),
+            banned: Arc::new(AtomicBool::new(false)),
+            negentropy_support: Arc::new(RwLock::new(None)),
+            last_seen: Arc::new(RwLock::new(HashMap::new())),
+            tracer: Arc::new(RwLock::new(None)),
+            has_tracer: Arc::new(AtomicBool::new(false)),
+            last_activity: Arc::new(AtomicU64::new(0)),
+            waking_up: Arc::new(AtomicBool::new(false)),
+            retry_now: Arc::new(Notify::new()),
+            #[cfg(not(target_arch = "wasm32"))]
+            on_demand_pings: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -221,9 +324,24 @@ impl InternalRelay {
     }
 
     async fn set_status(&self, status: RelayStatus, log: bool) {
+        self.set_status_with_reason(status, log, None).await
+    }
+
+    async fn set_status_with_reason(&self, status: RelayStatus, log: bool, reason: Option<String>) {
         // Change status
         let mut s = self.status.write().await;
+        let changed: bool = *s != status;
         *s = status;
+        drop(s);
+
+        // Save in history
+        let mut history = self.history.write().await;
+        history.push(ConnectionEvent {
+            timestamp: Timestamp::now(),
+            status,
+            reason,
+        });
+        drop(history);
 
         // Log
         if log {
@@ -233,17 +351,31 @@ impl InternalRelay {
                 RelayStatus::Connecting => tracing::debug!("Connecting to '{}'", self.url),
                 RelayStatus::Connected => tracing::info!("Connected to '{}'", self.url),
                 RelayStatus::Disconnected => tracing::info!("Disconnected from '{}'", self.url),
+                RelayStatus::Idle => tracing::info!("'{}' relay is idle, disconnected", self.url),
                 RelayStatus::Terminated => {
                     tracing::info!("Completely disconnected from '{}'", self.url)
                 }
             }
         }
 
+        // Send status change
+        if changed {
+            let status_sender = self.status_sender.read().await;
+            if let Some(status_sender) = status_sender.as_ref() {
+                let _ = status_sender.send((self.url(), status));
+            }
+        }
+
         // Send notification
         self.send_notification(RelayNotification::RelayStatus { status }, true)
             .await;
     }
 
+    pub async fn history(&self) -> Vec<ConnectionEvent> {
+        let history = self.history.read().await;
+        history.to_vec()
+    }
+
     #[inline]
     pub fn flags(&self) -> AtomicRelayServiceFlags {
         self.opts.flags.clone()
@@ -275,6 +407,119 @@ impl InternalRelay {
     async fn set_document(&self, document: RelayInformationDocument) {
         let mut d = self.document.write().await;
         *d = document;
+
+        self.document_updated_at
+            .store(Timestamp::now().as_u64(), Ordering::SeqCst);
+    }
+
+    #[cfg(feature = "nip11")]
+    pub async fn refresh_information(&self) -> Result<(), Error> {
+        let document = RelayInformationDocument::get(self.url(), self.proxy()).await?;
+        self.set_document(document).await;
+        Ok(())
+    }
+
+    #[cfg(feature = "nip11")]
+    pub fn information_age(&self) -> Option<Duration> {
+        let updated_at: u64 = self.document_updated_at.load(Ordering::SeqCst);
+        if updated_at == 0 {
+            return None;
+        }
+        let now: u64 = Timestamp::now().as_u64();
+        Some(Duration::from_secs(now.saturating_sub(updated_at)))
+    }
+
+    /// Get effective subscription limits
+    ///
+    /// Explicit limits, filled with the advertised ones if `auto_limits_from_nip11` is enabled.
+    async fn subscription_limits(&self) -> RelaySubscriptionLimits {
+        let limits: RelaySubscriptionLimits = *self.subscription_limits.read().await;
+
+        #[cfg(feature = "nip11")]
+        if self.opts.auto_limits_from_nip11 {
+            let document = self.document.read().await;
+            if let Some(limitation) = &document.limitation {
+                return limits.merge_advertised(limitation);
+            }
+        }
+
+        limits
+    }
+
+    /// Compose the `REQ` messages, checking the filters against the subscription limits
+    ///
+    /// Filters are split if exceed the `max_filters` limit.
+    ///
+    /// If `check_limits` is set, fail if the `REQ` exceed the `max_subscriptions` limit.
+    async fn req_msgs(
+        &self,
+        id: SubscriptionId,
+        filters: Vec<Filter>,
+        check_limits: bool,
+    ) -> Result<Vec<ClientMessage>, Error> {
+        let limits: RelaySubscriptionLimits = self.subscription_limits().await;
+        let filters: Vec<Filter> = limits.check_filters(filters)?;
+        let subscriptions = self.subscriptions.read().await;
+        let mut splits = self.splits.write().await;
+
+        if check_limits {
+            // Count the REQ actually open (also the ones of the splits) and the ones to open
+            let active: usize = subscriptions.keys().filter(|sub_id| *sub_id != &id).count()
+                + splits.extra_reqs(&id);
+            let new: usize = SplitSubscriptions::reqs_count(filters.len(), limits.max_filters);
+            limits.check(active, new)?;
+        }
+
+        Ok(splits
+            .split(id, filters, limits.max_filters)
+            .into_iter()
+            .map(|(id, filters)| ClientMessage::req(id, filters))
+            .collect())
+    }
+
+    /// Compose the `CLOSE` messages, one for every `REQ` of the subscription
+    async fn close_msgs(&self, id: &SubscriptionId) -> Vec<ClientMessage> {
+        let mut splits = self.splits.write().await;
+        splits
+            .remove(id)
+            .into_iter()
+            .map(ClientMessage::close)
+            .collect()
+    }
+
+    /// Map the subscription ID of split subscriptions to the logical one
+    ///
+    /// `EOSE` is returned only when received for every `REQ` of the subscription.
+    async fn logical_message(&self, message: RelayMessage) -> Option<RelayMessage> {
+        match message {
+            RelayMessage::EndOfStoredEvents(subscription_id) => {
+                let mut splits = self.splits.write().await;
+                splits
+                    .eose(subscription_id)
+                    .map(RelayMessage::EndOfStoredEvents)
+            }
+            RelayMessage::Closed {
+                subscription_id,
+                message,
+            } => {
+                let splits = self.splits.read().await;
+                Some(RelayMessage::Closed {
+                    subscription_id: splits.logical_id(subscription_id),
+                    message,
+                })
+            }
+            message => Some(message),
+        }
+    }
+
+    /// Update options in place, without dropping the connection
+    ///
+    /// Only flags, POW difficulty, reconnection options and subscription limits are updated.
+    pub(crate) async fn update_opts(&self, opts: RelayOptions) {
+        self.opts.update(&opts);
+
+        let mut subscription_limits = self.subscription_limits.write().await;
+        *subscription_limits = opts.limits.subscriptions;
     }
 
     pub async fn subscriptions(&self) -> HashMap<SubscriptionId, Vec<Filter>> {
@@ -290,7 +535,7 @@ impl InternalRelay {
         subscription.get(id).map(|d| d.filters.clone())
     }
 
-    pub(crate) async fn update_subscription(
+    pub(crate) async fn store_subscription(
         &self,
         id: SubscriptionId,
         filters: Vec<Filter>,
@@ -298,13 +543,56 @@ impl InternalRelay {
     ) {
         let mut subscriptions = self.subscriptions.write().await;
         let data: &mut SubscriptionData = subscriptions.entry(id).or_default();
+        data.filters_hash = last_seen::filters_hash(&filters);
         data.filters = filters;
+        data.eose = false;
 
         if update_subscribed_at {
             data.subscribed_at = Timestamp::now();
         }
     }
 
+    async fn update_last_event_at(&self, id: &SubscriptionId, created_at: Timestamp) {
+        let mut subscriptions = self.subscriptions.write().await;
+        if let Some(data) = subscriptions.get_mut(id) {
+            if created_at > data.last_event_at {
+                data.last_event_at = created_at;
+            }
+
+            let mut last_seen = self.last_seen.write().await;
+            let seen: &mut Timestamp = last_seen.entry(data.filters_hash).or_default();
+            if created_at > *seen {
+                *seen = created_at;
+            }
+        }
+    }
+
+    /// Get the subscriptions, with `since` advanced to the newest seen event
+    pub(crate) async fn subscriptions_since_last_seen(
+        &self,
+    ) -> HashMap<SubscriptionId, Vec<Filter>> {
+        let subscriptions = self.subscriptions.read().await;
+        let last_seen = self.last_seen.read().await;
+        subscriptions
+            .iter()
+            .filter(|(.., data)| !data.filters.is_empty())
+            .map(|(id, data)| {
+                let seen: Timestamp = last_seen
+                    .get(&data.filters_hash)
+                    .copied()
+                    .unwrap_or_default();
+                let filters = advance_since(data.filters.clone(), seen.max(data.last_event_at));
+                (id.clone(), filters)
+            })
+            .collect()
+    }
+
+    /// Get the timestamp of the newest event seen for the filters
+    pub async fn last_seen(&self, filters: &[Filter]) -> Option<Timestamp> {
+        let last_seen = self.last_seen.read().await;
+        last_seen.get(&last_seen::filters_hash(filters)).copied()
+    }
+
     /// Check if should subscribe for current websocket session
     pub(crate) async fn should_resubscribe(&self, id: &SubscriptionId) -> bool {
         let subscriptions = self.subscriptions.read().await;
@@ -323,9 +611,69 @@ impl InternalRelay {
         }
     }
 
+    /// Mark the `EOSE` of the subscription as received (or as pending, if a new `REQ` is sent)
+    async fn set_eose(&self, id: &SubscriptionId, received: bool) {
+        let mut subscriptions = self.subscriptions.write().await;
+        if let Some(data) = subscriptions.get_mut(id) {
+            data.eose = received;
+        }
+    }
+
+    /// Get the subscriptions still waiting for `EOSE`
+    async fn pending_eose(&self) -> Vec<SubscriptionId> {
+        let subscriptions = self.subscriptions.read().await;
+        subscriptions
+            .iter()
+            .filter(|(.., data)| !data.eose)
+            .map(|(id, ..)| id.clone())
+            .collect()
+    }
+
+    /// Wait until every subscription received the `EOSE`, for at most `timeout`
+    ///
+    /// Return `false` if the timeout elapsed or if the relay disconnected before.
+    /// If the relay is not connected, return immediately.
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        if !self.is_connected().await {
+            return self.pending_eose().await.is_empty();
+        }
+
+        let mut notifications = self.internal_notification_sender.subscribe();
+        time::timeout(Some(timeout), async {
+            loop {
+                if self.pending_eose().await.is_empty() {
+                    return true;
+                }
+
+                match notifications.recv().await {
+                    Ok(RelayNotification::RelayStatus { status }) => {
+                        if status.is_disconnected() {
+                            return false;
+                        }
+                    }
+                    Ok(RelayNotification::Shutdown) | Err(broadcast::error::RecvError::Closed) => {
+                        return false
+                    }
+                    _ => (),
+                }
+            }
+        })
+        .await
+        .unwrap_or(false)
+    }
+
     pub(crate) async fn remove_subscription(&self, id: &SubscriptionId) {
         let mut subscriptions = self.subscriptions.write().await;
-        subscriptions.remove(id);
+        if let Some(data) = subscriptions.remove(id) {
+            // Prune the last seen event, if no other subscription has the same filters
+            if !subscriptions
+                .values()
+                .any(|d| d.filters_hash == data.filters_hash)
+            {
+                let mut last_seen = self.last_seen.write().await;
+                last_seen.remove(&data.filters_hash);
+            }
+        }
     }
 
     #[inline]
@@ -362,44 +710,291 @@ impl InternalRelay {
         *external_notification_sender = notification_sender;
     }
 
+    pub async fn set_event_overflow_policy(&self, policy: EventOverflowPolicy, capacity: usize) {
+        let mut event_overflow = self.event_overflow.write().await;
+        *event_overflow = EventOverflow { policy, capacity };
+    }
+
+    pub async fn set_status_sender(&self, sender: Option<broadcast::Sender<(Url, RelayStatus)>>) {
+        let mut status_sender = self.status_sender.write().await;
+        *status_sender = sender;
+    }
+
+    pub async fn set_validator(&self, validator: Option<EventValidator>) {
+        let mut v = self.validator.write().await;
+        *v = validator;
+    }
+
+    pub async fn set_invalid_sig_policy(&self, policy: InvalidSigPolicy) {
+        let mut p = self.invalid_sig_policy.write().await;
+        *p = policy;
+    }
+
+    pub async fn set_required_tags(&self, tags: HashMap<Kind, Vec<SingleLetterTag>>) {
+        let mut required_tags = self.required_tags.write().await;
+        *required_tags = tags;
+    }
+
+    pub async fn set_filtered_notifications(&self, filtered: Option<FilteredNotifications>) {
+        let mut filtered_notifications = self.filtered_notifications.write().await;
+        *filtered_notifications = filtered;
+    }
+
+    pub async fn set_global_limits(&self, limits: Option<GlobalLimits>) {
+        let mut global_limits = self.global_limits.write().await;
+        *global_limits = limits;
+    }
+
+    pub async fn set_mutes(&self, list: Option<RelayBlacklist>) {
+        let mut mutes = self.mutes.write().await;
+        *mutes = list;
+    }
+
+    /// Check if the event is within the global limit of the subscription (if any)
+    async fn within_global_limit(&self, id: &SubscriptionId) -> bool {
+        let global_limits = self.global_limits.read().await;
+        match global_limits.as_ref() {
+            Some(limits) => limits.deliver(id).await,
+            None => true,
+        }
+    }
+
+    /// Check if relay has been banned for too many invalid signatures
+    #[inline]
+    pub fn is_banned(&self) -> bool {
+        self.banned.load(Ordering::SeqCst)
+    }
+
+    async fn handle_invalid_signature(&self, id: EventId) {
+        let policy: InvalidSigPolicy = *self.invalid_sig_policy.read().await;
+
+        if policy == InvalidSigPolicy::Drop {
+            return;
+        }
+
+        self.send_external_notification(RelayPoolNotification::InvalidSignature {
+            relay_url: self.url(),
+            id,
+        })
+        .await;
+
+        if let InvalidSigPolicy::DropAndBanRelay(threshold) = policy {
+            let count: usize = self.invalid_signatures.fetch_add(1, Ordering::SeqCst) + 1;
+            if count >= threshold as usize && !self.banned.swap(true, Ordering::SeqCst) {
+                tracing::warn!(
+                    "Banned '{}': {count} events with invalid signature received",
+                    self.url
+                );
+                if let Err(e) = self.disconnect().await {
+                    tracing::error!("Impossible to disconnect banned relay '{}': {e}", self.url);
+                }
+            }
+        }
+    }
+
+    pub async fn set_message_tracer(&self, tracer: Option<MessageTracer>) {
+        let mut t = self.tracer.write().await;
+        self.has_tracer.store(tracer.is_some(), Ordering::SeqCst);
+        *t = tracer;
+    }
+
+    async fn trace_message(&self, direction: MessageDirection, msg: &str) {
+        if self.has_tracer.load(Ordering::SeqCst) {
+            let tracer = self.tracer.read().await;
+            if let Some(tracer) = tracer.as_ref() {
+                tracer.trace(&self.url, direction, msg);
+            }
+        }
+    }
+
+    #[inline]
+    fn update_last_activity(&self) {
+        self.last_activity
+            .store(Timestamp::now().as_u64(), Ordering::SeqCst);
+    }
+
+    pub async fn stats_snapshot(&self) -> RelayStats {
+        let subscriptions: usize = self.subscriptions.read().await.len();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let latency: Option<Duration> = self.stats.latency().await;
+        #[cfg(target_arch = "wasm32")]
+        let latency: Option<Duration> = None;
+
+        RelayStats {
+            status: self.status().await,
+            latency,
+            bytes_sent: self.stats.bytes_sent(),
+            bytes_received: self.stats.bytes_received(),
+            subscriptions,
+            reconnections: self.stats.success().saturating_sub(1),
+        }
+    }
+
+    #[inline]
+    async fn has_subscriptions(&self) -> bool {
+        let subscriptions = self.subscriptions.read().await;
+        !subscriptions.is_empty()
+    }
+
+    async fn is_idle(&self, idle_timeout: Duration) -> bool {
+        is_idle(
+            self.last_activity.load(Ordering::SeqCst),
+            Timestamp::now().as_u64(),
+            idle_timeout,
+            self.has_subscriptions().await,
+        )
+    }
+
+    /// Reconnect if relay is idle
+    async fn wake_up(&self) {
+        if self.status().await == RelayStatus::Idle
+            && self
+                .waking_up
+                .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        {
+            tracing::debug!("Waking up '{}'", self.url);
+            self.try_connect(None).await;
+            self.waking_up.store(false, Ordering::SeqCst);
+        }
+    }
+
+    async fn validate_event(&self, event: &Event) -> Result<(), String> {
+        // Check required tags
+        {
+            let required_tags = self.required_tags.read().await;
+            if let Some(tags) = required_tags.get(&event.kind()) {
+                for tag in tags.iter() {
+                    if !event
+                        .iter_tags()
+                        .any(|t| t.single_letter_tag().as_ref() == Some(tag))
+                    {
+                        return Err(format!("missing required '{tag}' tag"));
+                    }
+                }
+            }
+        }
+
+        let validator = self.validator.read().await;
+        match validator.as_ref() {
+            Some(validator) => validator.validate(event),
+            None => Ok(()),
+        }
+    }
+
     async fn send_notification(&self, notification: RelayNotification, external: bool) {
         // Send internal notification
         let _ = self.internal_notification_sender.send(notification.clone());
+        let _ = self.user_notification_sender.send(notification.clone());
 
         // Send external notification
         if external {
-            let external_notification_sender = self.external_notification_sender.read().await;
-            if let Some(external_notification_sender) = external_notification_sender.as_ref() {
-                // Convert relay to notification to pool notification
-                let notification: RelayPoolNotification = match notification {
-                    RelayNotification::Event {
-                        subscription_id,
-                        event,
-                    } => RelayPoolNotification::Event {
-                        relay_url: self.url(),
-                        subscription_id,
-                        event,
-                    },
-                    RelayNotification::Message { message } => RelayPoolNotification::Message {
-                        relay_url: self.url(),
-                        message,
-                    },
-                    RelayNotification::RelayStatus { status } => {
-                        RelayPoolNotification::RelayStatus {
-                            relay_url: self.url(),
-                            status,
-                        }
+            // Convert relay to notification to pool notification
+            let notification: RelayPoolNotification = match notification {
+                RelayNotification::Event {
+                    subscription_id,
+                    event,
+                } => RelayPoolNotification::Event {
+                    relay_url: self.url(),
+                    subscription_id,
+                    event,
+                },
+                RelayNotification::Message { message } => RelayPoolNotification::Message {
+                    relay_url: self.url(),
+                    message,
+                },
+                RelayNotification::RelayStatus { status } => RelayPoolNotification::RelayStatus {
+                    relay_url: self.url(),
+                    status,
+                },
+                RelayNotification::Shutdown => RelayPoolNotification::Shutdown,
+            };
+
+            // Send notification
+            self.send_external_notification(notification).await;
+        }
+    }
+
+    /// Send notification **only** to the external channel (if set)
+    async fn send_external_notification(&self, notification: RelayPoolNotification) {
+        // Clone the sender, to not hold the lock if the overflow policy blocks
+        let external_notification_sender: Option<broadcast::Sender<RelayPoolNotification>> =
+            self.external_notification_sender.read().await.clone();
+        if let Some(external_notification_sender) = external_notification_sender {
+            // Forward to the filtered listeners
+            let filtered_notifications = self.filtered_notifications.read().await;
+            if let Some(filtered_notifications) = filtered_notifications.as_ref() {
+                filtered_notifications.dispatch(&notification).await;
+            }
+            drop(filtered_notifications);
+
+            let is_event: bool = matches!(
+                notification,
+                RelayPoolNotification::Event { .. }
+                    | RelayPoolNotification::Message {
+                        message: RelayMessage::Event { .. },
+                        ..
                     }
-                    RelayNotification::Shutdown => RelayPoolNotification::Shutdown,
-                };
+            );
+            if is_event
+                && !self
+                    .apply_overflow_policy(&external_notification_sender)
+                    .await
+            {
+                return;
+            }
 
-                // Send notification
-                let _ = external_notification_sender.send(notification);
+            let _ = external_notification_sender.send(notification);
+        }
+    }
+
+    /// Apply the [`EventOverflowPolicy`] before sending an event to the external channel
+    ///
+    /// Return `false` if the event must be dropped.
+    async fn apply_overflow_policy(
+        &self,
+        sender: &broadcast::Sender<RelayPoolNotification>,
+    ) -> bool {
+        let overflow: EventOverflow = *self.event_overflow.read().await;
+        match overflow.policy {
+            EventOverflowPolicy::DropOldest => true,
+            EventOverflowPolicy::DropNewest => {
+                // Keep a free slot for the `EventDropped` notification
+                if sender.len() + 1 < overflow.capacity {
+                    self.overflowing.store(false, Ordering::SeqCst);
+                    return true;
+                }
+
+                // Notify only the first dropped event
+                if !self.overflowing.swap(true, Ordering::SeqCst) {
+                    tracing::warn!(
+                        "Dropping events from '{}': notification channel is full",
+                        self.url
+                    );
+                    let _ = sender.send(RelayPoolNotification::EventDropped {
+                        relay_url: self.url(),
+                    });
+                }
+
+                false
+            }
+            EventOverflowPolicy::Block => {
+                // The broadcast channel doesn't notify when a slot is freed
+                while sender.len() >= overflow.capacity && sender.receiver_count() > 0 {
+                    thread::sleep(OVERFLOW_CHECK_INTERVAL).await;
+                }
+                true
             }
         }
     }
 
     pub async fn connect(&self, connection_timeout: Option<Duration>) {
+        if self.is_banned() {
+            tracing::warn!("Can't connect to '{}': relay banned", self.url);
+            return;
+        }
+
         self.schedule_for_termination(false); // TODO: remove?
 
         if let RelayStatus::Initialized | RelayStatus::Terminated = self.status().await {
@@ -471,7 +1066,20 @@ impl InternalRelay {
                         // Sleep
                         let retry_sec: u64 = relay.calculate_retry_sec();
                         tracing::trace!("{} retry time set to {retry_sec} secs", relay.url);
-                        thread::sleep(Duration::from_secs(retry_sec)).await;
+
+                        // Save retry state
+                        let next_retry_at: Option<Timestamp> = match relay.status().await {
+                            RelayStatus::Connected | RelayStatus::Idle => None,
+                            _ => Some(Timestamp::now() + retry_sec),
+                        };
+                        relay.stats.save_retry(retry_sec, next_retry_at);
+
+                        // Sleep, unless woken up by a forced reconnection
+                        time::timeout(
+                            Some(Duration::from_secs(retry_sec)),
+                            relay.retry_now.notified(),
+                        )
+                        .await;
                     }
                 });
             } else if connection_timeout.is_some() {
@@ -486,15 +1094,13 @@ impl InternalRelay {
     /// Depending on attempts and success, use default or incremental retry time
     fn calculate_retry_sec(&self) -> u64 {
         if self.opts.get_adjust_retry_sec() {
-            // diff = attempts - success
-            let diff: u64 = self.stats.attempts().saturating_sub(self.stats.success()) as u64;
+            // diff = failed attempts since the last successful connection (or retry reset)
+            let diff: u64 = self.stats.retry_attempts() as u64;
 
             // Use incremental retry time if diff >= 3
-            if diff >= 3 {
-                let retry_interval: i64 =
-                    cmp::min(MIN_RETRY_SEC * (1 + diff), MAX_ADJ_RETRY_SEC) as i64;
+            if let Some(retry_interval) = adjusted_retry_sec(diff) {
                 let jitter: i64 = rand::thread_rng().gen_range(-1..=1);
-                return retry_interval.saturating_add(jitter) as u64;
+                return (retry_interval as i64).saturating_add(jitter) as u64;
             }
         }
 
@@ -522,6 +1128,8 @@ impl InternalRelay {
         thread::spawn(async move {
             tracing::debug!("Relay Message Handler started for '{}'", relay.url);
 
+            relay.update_last_activity();
+
             let sender = async {
                 // Lock receivers
                 let mut rx_nostr = relay.channels.rx_nostr().await;
@@ -538,6 +1146,15 @@ impl InternalRelay {
                                 .map(|msg| WsMessage::Text(msg.as_json()))
                                 .collect();
 
+                            relay.update_last_activity();
+
+                            // Trace messages
+                            for msg in msgs.iter() {
+                                if let WsMessage::Text(json) = msg {
+                                    relay.trace_message(MessageDirection::Outgoing, json).await;
+                                }
+                            }
+
                             // Calculate messages size
                             let size: usize = msgs.iter().map(|msg| msg.len()).sum();
                             let len: usize = msgs.len();
@@ -619,6 +1236,13 @@ impl InternalRelay {
                                         break;
                                     }
                                 }
+                                // Close connection
+                                RelayServiceEvent::Reconnect => break,
+                                // Close connection for inactivity
+                                RelayServiceEvent::Idle => {
+                                    relay.set_status(RelayStatus::Idle, true).await;
+                                    break;
+                                }
                             }
                         }
                         else => break
@@ -642,34 +1266,54 @@ impl InternalRelay {
                         match msg {
                             #[cfg(not(target_arch = "wasm32"))]
                             WsMessage::Pong(bytes) => {
-                                if relay.opts.flags.has_ping() {
-                                    match String::from_utf8(bytes) {
-                                        Ok(nonce) => match nonce.parse::<u64>() {
-                                            Ok(nonce) => {
-                                                if relay.stats.ping.last_nonce() == nonce {
-                                                    tracing::debug!(
-                                                        "Pong from '{}' match nonce: {}",
-                                                        relay.url,
-                                                        nonce
-                                                    );
-                                                    relay.stats.ping.set_replied(true);
-                                                    let sent_at = relay.stats.ping.sent_at().await;
-                                                    relay
-                                                        .stats
-                                                        .save_latency(sent_at.elapsed())
-                                                        .await;
-                                                } else {
-                                                    tracing::error!("Pong nonce not match: received={nonce}, expected={}", relay.stats.ping.last_nonce());
-                                                }
+                                // Pongs are handled also if the ping flag is disabled, to support on-demand pings
+                                match String::from_utf8(bytes) {
+                                    Ok(nonce) => match nonce.parse::<u64>() {
+                                        Ok(nonce) => {
+                                            let on_demand =
+                                                relay.on_demand_pings.lock().await.remove(&nonce);
+                                            if let Some((sent_at, tx)) = on_demand {
+                                                tracing::debug!(
+                                                    "Pong from '{}' match on-demand nonce: {}",
+                                                    relay.url,
+                                                    nonce
+                                                );
+                                                let latency: Duration = sent_at.elapsed();
+                                                relay.stats.save_latency(latency).await;
+                                                let _ = tx.send(latency);
+                                            } else if relay.stats.ping.last_nonce() == nonce {
+                                                tracing::debug!(
+                                                    "Pong from '{}' match nonce: {}",
+                                                    relay.url,
+                                                    nonce
+                                                );
+                                                let sent_at = relay.stats.ping.sent_at().await;
+                                                relay.stats.save_latency(sent_at.elapsed()).await;
+                                                relay.stats.ping.set_replied(true);
+                                            } else {
+                                                tracing::error!("Pong nonce not match: received={nonce}, expected={}", relay.stats.ping.last_nonce());
                                             }
-                                            Err(e) => tracing::error!("{e}"),
-                                        },
+                                        }
                                         Err(e) => tracing::error!("{e}"),
-                                    }
+                                    },
+                                    Err(e) => tracing::error!("{e}"),
                                 }
                             }
                             _ => {
+                                relay.update_last_activity();
                                 let data: Vec<u8> = msg.into_data();
+
+                                // Avoid to decode the message if tracer is not set
+                                if relay.has_tracer.load(Ordering::SeqCst) {
+                                    relay
+                                        .trace_message(
+                                            MessageDirection::Incoming,
+                                            &String::from_utf8_lossy(&data),
+                                        )
+                                        .await;
+                                }
+
+                                // Handled inline, to validate and dispatch the messages in receive order
                                 relay.handle_relay_message_infallible(&data).await;
                             }
                         }
@@ -681,24 +1325,30 @@ impl InternalRelay {
             let pinger = async {
                 if relay.opts.flags.has_ping() {
                     loop {
-                        // If last nonce is NOT 0, check if relay replied
-                        // Break loop if relay not replied
-                        if relay.stats.ping.last_nonce() != 0 && !relay.stats.ping.replied() {
-                            tracing::warn!("'{}' not replied to ping", relay.url);
-                            relay.stats.ping.reset();
-                            break;
-                        }
+                        if relay
+                            .opts
+                            .ping_strategy
+                            .should_ping(relay.has_subscriptions().await)
+                        {
+                            // If last nonce is NOT 0, check if relay replied
+                            // Break loop if relay not replied
+                            if relay.stats.ping.last_nonce() != 0 && !relay.stats.ping.replied() {
+                                tracing::warn!("'{}' not replied to ping", relay.url);
+                                relay.stats.ping.reset();
+                                break;
+                            }
 
-                        // Generate and save nonce
-                        let nonce: u64 = rand::random();
-                        relay.stats.ping.set_last_nonce(nonce);
-                        relay.stats.ping.set_replied(false);
+                            // Generate and save nonce
+                            let nonce: u64 = rand::random();
+                            relay.stats.ping.set_last_nonce(nonce);
+                            relay.stats.ping.set_replied(false);
 
-                        // Ping
-                        if let Err(e) = relay.channels.ping(nonce) {
-                            tracing::error!("Impossible to ping '{}': {e}", relay.url);
-                            break;
-                        };
+                            // Ping
+                            if let Err(e) = relay.channels.ping(nonce) {
+                                tracing::error!("Impossible to ping '{}': {e}", relay.url);
+                                break;
+                            };
+                        }
 
                         // Sleep
                         thread::sleep(PING_INTERVAL).await;
@@ -717,6 +1367,27 @@ impl InternalRelay {
                 }
             };
 
+            let idle = async {
+                if let Some(idle_timeout) = relay.opts.idle_timeout {
+                    loop {
+                        thread::sleep(cmp::min(IDLE_CHECK_INTERVAL, idle_timeout)).await;
+                        if relay.is_idle(idle_timeout).await {
+                            break;
+                        }
+                    }
+
+                    // Ask to the sender to close the connection
+                    if let Err(e) = relay.channels.send_service_msg(RelayServiceEvent::Idle) {
+                        tracing::error!(
+                            "Impossible to close idle connection for '{}': {e}",
+                            relay.url
+                        );
+                    }
+                }
+
+                futures_util::future::pending::<()>().await
+            };
+
             // Wait that one of the futures terminate/complete
             tokio::select! {
                 _ = receiver => {
@@ -728,6 +1399,7 @@ impl InternalRelay {
                 _ = pinger => {
                     tracing::trace!("Relay pinger exited for '{}'", relay.url);
                 }
+                _ = idle => {}
             }
 
             // Check if relay is marked as disconnected. If not, update status.
@@ -748,9 +1420,17 @@ impl InternalRelay {
         // Set RelayStatus to `Connecting`
         self.set_status(RelayStatus::Connecting, true).await;
 
-        // Request `RelayInformationDocument`
+        // Forget the negentropy support of the previous session (probed again when needed)
+        {
+            let mut negentropy_support = self.negentropy_support.write().await;
+            *negentropy_support = None;
+        }
+
+        // Request `RelayInformationDocument` (fetched by the warm-up, if enabled)
         #[cfg(feature = "nip11")]
-        self.request_nip11_document();
+        if !self.opts.warm_up {
+            self.request_nip11_document();
+        }
 
         // Compose timeout
         let timeout: Option<Duration> = if self.stats.attempts() > 1 {
@@ -773,7 +1453,13 @@ impl InternalRelay {
                 // Spawn message handler
                 match self.spawn_message_handler(ws_tx, ws_rx) {
                     Ok(()) => {
-                        // Subscribe to relay
+                        // Prefetch capabilities
+                        if self.opts.warm_up {
+                            let relay = self.clone();
+                            let _ = thread::spawn(async move { relay.warm_up().await });
+                        }
+
+                        // Subscribe to relay
                         if self.opts.flags.has_read() {
                             let opts: RelaySendOptions =
                                 RelaySendOptions::default().skip_send_confirmation(true);
@@ -783,20 +1469,34 @@ impl InternalRelay {
                         }
                     }
                     Err(e) => {
-                        self.set_status(RelayStatus::Disconnected, false).await;
+                        self.set_status_with_reason(
+                            RelayStatus::Disconnected,
+                            false,
+                            Some(e.to_string()),
+                        )
+                        .await;
                         tracing::error!("Impossible to spawn message handler for '{url}': {e}");
                     }
                 }
             }
             Err(e) => {
-                self.set_status(RelayStatus::Disconnected, false).await;
+                self.set_status_with_reason(RelayStatus::Disconnected, false, Some(e.to_string()))
+                    .await;
                 tracing::error!("Impossible to connect to '{url}': {e}");
             }
         };
     }
 
+    /// Handle events as if they were received from the relay
+    pub async fn inject_events(&self, subscription_id: &SubscriptionId, events: Vec<Event>) {
+        for event in events.into_iter() {
+            let msg: String = RelayMessage::event(subscription_id.clone(), event).as_json();
+            self.handle_relay_message_infallible(msg.as_bytes()).await;
+        }
+    }
+
     #[inline(always)]
-    async fn handle_relay_message_infallible(&self, msg: &[u8]) {
+    pub(crate) async fn handle_relay_message_infallible(&self, msg: &[u8]) {
         match self.handle_relay_message(msg).await {
             Ok(Some(message)) => {
                 match &message {
@@ -813,9 +1513,31 @@ impl InternalRelay {
                     _ => (),
                 }
 
+                // Extract subscription ID, if it's an EOSE message
+                let eose: Option<SubscriptionId> = match &message {
+                    RelayMessage::EndOfStoredEvents(subscription_id) => {
+                        Some(subscription_id.clone())
+                    }
+                    _ => None,
+                };
+
+                // Track EOSE, before notifying it
+                if let Some(subscription_id) = &eose {
+                    self.set_eose(subscription_id, true).await;
+                }
+
                 // Send notification
                 self.send_notification(RelayNotification::Message { message }, true)
                     .await;
+
+                // Send EOSE notification
+                if let Some(subscription_id) = eose {
+                    self.send_external_notification(RelayPoolNotification::Eose {
+                        relay_url: self.url(),
+                        subscription_id,
+                    })
+                    .await;
+                }
             }
             Ok(None) | Err(Error::MessageHandle(MessageHandleError::EmptyMsg)) => (),
             Err(e) => tracing::error!(
@@ -851,6 +1573,13 @@ impl InternalRelay {
                 subscription_id,
                 event,
             } => {
+                // Get logical subscription ID (if the subscription was split)
+                let subscription_id: SubscriptionId = self
+                    .splits
+                    .read()
+                    .await
+                    .logical_id(SubscriptionId::new(subscription_id));
+
                 let kind: Kind = Kind::from(event.kind);
 
                 // Check event size
@@ -887,6 +1616,21 @@ impl InternalRelay {
                     return Err(Error::PublicKeyBlacklisted(partial_event.pubkey));
                 }
 
+                // Check mute list (author public key)
+                {
+                    let mutes = self.mutes.read().await;
+                    if let Some(mutes) = mutes.as_ref() {
+                        if mutes.has_public_key(&partial_event.pubkey).await {
+                            tracing::debug!(
+                                "Event {} from '{}' dropped: muted author",
+                                partial_event.id,
+                                self.url
+                            );
+                            return Ok(None);
+                        }
+                    }
+                }
+
                 // Check min POW
                 let difficulty: u8 = self.opts.get_pow_difficulty();
                 if difficulty > 0 && !partial_event.id.check_pow(difficulty) {
@@ -910,7 +1654,49 @@ impl InternalRelay {
                 // Deserialize missing event fields
                 let missing: MissingPartialEvent = MissingPartialEvent::from_raw(event);
 
-                // TODO: check if word/hashtag is blacklisted
+                // Check max event age and future drift
+                if let Err(reason) = self
+                    .opts
+                    .check_created_at(missing.created_at, Timestamp::now())
+                {
+                    tracing::debug!(
+                        "Event {} from '{}' dropped: {reason}",
+                        partial_event.id,
+                        self.url
+                    );
+                    self.send_external_notification(RelayPoolNotification::Rejected {
+                        relay_url: self.url(),
+                        id: partial_event.id,
+                        reason,
+                    })
+                    .await;
+                    return Ok(None);
+                }
+
+                // Check blacklist (words)
+                if self.blacklist.has_word_in(&missing.content).await {
+                    tracing::debug!(
+                        "Event {} from '{}' dropped: blacklisted word",
+                        partial_event.id,
+                        self.url
+                    );
+                    return Ok(None);
+                }
+
+                // Check mute list (words)
+                {
+                    let mutes = self.mutes.read().await;
+                    if let Some(mutes) = mutes.as_ref() {
+                        if mutes.has_word_in(&missing.content).await {
+                            tracing::debug!(
+                                "Event {} from '{}' dropped: muted word",
+                                partial_event.id,
+                                self.url
+                            );
+                            return Ok(None);
+                        }
+                    }
+                }
 
                 // Check if event is replaceable and has coordinate
                 if missing.kind.is_replaceable() || missing.kind.is_parameterized_replaceable() {
@@ -964,24 +1750,65 @@ impl InternalRelay {
                     return Err(Error::EventExpired);
                 }
 
-                // Check if saved
+                // Verify event
                 if !saved {
-                    // Verify event
-                    event.verify()?;
+                    event.verify_id()?;
+
+                    if let Err(e) = event.verify_signature() {
+                        self.handle_invalid_signature(event.id()).await;
+                        return Err(e.into());
+                    }
+                }
+
+                // Check custom validator
+                if let Err(reason) = self.validate_event(&event).await {
+                    tracing::debug!(
+                        "Event {} from '{}' rejected by validator: {reason}",
+                        event.id(),
+                        self.url
+                    );
+                    self.send_external_notification(RelayPoolNotification::Rejected {
+                        relay_url: self.url(),
+                        id: event.id(),
+                        reason,
+                    })
+                    .await;
+                    return Ok(None);
+                }
 
-                    // Save event
+                // Update throughput and kind stats
+                self.stats.new_event(event.kind()).await;
+
+                // Save event
+                if !saved {
                     self.database.save_event(&event).await?;
                 }
 
+                // Track newest event, used to advance `since` on re-subscription
+                if self.opts.resubscribe_advance_since {
+                    self.update_last_event_at(&subscription_id, event.created_at)
+                        .await;
+                }
+
                 // Box event
                 let event: Box<Event> = Box::new(event);
 
                 // Check if seen
                 if !seen {
+                    // Check global limit of the subscription
+                    if !self.within_global_limit(&subscription_id).await {
+                        tracing::debug!(
+                            "Event {} from '{}' dropped: global limit of '{subscription_id}' reached",
+                            event.id(),
+                            self.url
+                        );
+                        return Ok(None);
+                    }
+
                     // Send notification
                     self.send_notification(
                         RelayNotification::Event {
-                            subscription_id: SubscriptionId::new(&subscription_id),
+                            subscription_id: subscription_id.clone(),
                             event: event.clone(),
                         },
                         true,
@@ -990,11 +1817,159 @@ impl InternalRelay {
                 }
 
                 Ok(Some(RelayMessage::Event {
-                    subscription_id: SubscriptionId::new(subscription_id),
+                    subscription_id,
                     event,
                 }))
             }
-            m => Ok(Some(RelayMessage::try_from(m)?)),
+            m => {
+                let message: RelayMessage = RelayMessage::try_from(m)?;
+                Ok(self.logical_message(message).await)
+            }
+        }
+    }
+
+    /// Send a WebSocket ping and wait for the pong
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn ping_now(&self) -> Result<Duration, Error> {
+        if !self.is_connected().await {
+            return Err(Error::NotConnected);
+        }
+
+        // Generate a dedicated nonce, to not interfere with the periodic ping
+        let nonce: u64 = rand::random();
+        let (tx, rx) = oneshot::channel::<Duration>();
+        let mut pings = self.on_demand_pings.lock().await;
+        pings.insert(nonce, (Instant::now(), tx));
+        drop(pings);
+
+        // Ping
+        if let Err(e) = self.channels.ping(nonce) {
+            self.on_demand_pings.lock().await.remove(&nonce);
+            return Err(e);
+        }
+
+        // Wait for pong (latency is saved by the message handler)
+        let res = time::timeout(Some(PING_TIMEOUT), rx).await;
+
+        // Remove the pending ping, if not replied
+        self.on_demand_pings.lock().await.remove(&nonce);
+
+        match res {
+            Some(Ok(latency)) => Ok(latency),
+            Some(Err(..)) | None => Err(Error::Timeout),
+        }
+    }
+
+    pub async fn reconnect(&self, connection_timeout: Duration) -> Result<(), Error> {
+        // Reset retry state
+        self.stats.reset_retry();
+
+        match self.status().await {
+            RelayStatus::Initialized | RelayStatus::Terminated => {
+                self.connect(Some(connection_timeout)).await;
+            }
+            status => {
+                let mut notifications = self.internal_notification_sender.subscribe();
+
+                // Close the current connection and wait for the message handler exit
+                if matches!(status, RelayStatus::Connected) {
+                    self.channels
+                        .send_service_msg(RelayServiceEvent::Reconnect)?;
+                    time::timeout(Some(connection_timeout), async {
+                        while let Ok(notification) = notifications.recv().await {
+                            if let RelayNotification::RelayStatus {
+                                status: RelayStatus::Disconnected,
+                            } = notification
+                            {
+                                break;
+                            }
+                        }
+                    })
+                    .await
+                    .ok_or(Error::Timeout)?;
+                }
+
+                match self.status().await {
+                    RelayStatus::Idle => self.wake_up().await,
+                    RelayStatus::Connecting => {
+                        self.wait_for_connection(&mut notifications, connection_timeout)
+                            .await
+                    }
+                    // Let the auto-reconnect loop connect, to avoid double connections
+                    _ if self.opts.get_reconnect() => {
+                        self.retry_now.notify_one();
+                        self.wait_for_connection(&mut notifications, connection_timeout)
+                            .await
+                    }
+                    _ => self.try_connect(Some(connection_timeout)).await,
+                }
+            }
+        }
+
+        if self.is_connected().await {
+            Ok(())
+        } else {
+            Err(Error::NotConnected)
+        }
+    }
+
+    /// Wait until the relay is connected or the connection attempt fails, at most for `timeout`
+    async fn wait_for_connection(
+        &self,
+        notifications: &mut broadcast::Receiver<RelayNotification>,
+        timeout: Duration,
+    ) {
+        if self.is_connected().await {
+            return;
+        }
+
+        time::timeout(Some(timeout), async {
+            let mut connecting: bool = false;
+            while let Ok(notification) = notifications.recv().await {
+                if let RelayNotification::RelayStatus { status } = notification {
+                    match status {
+                        RelayStatus::Connecting => connecting = true,
+                        RelayStatus::Connected => break,
+                        RelayStatus::Disconnected if connecting => break,
+                        RelayStatus::Terminated => break,
+                        _ => (),
+                    }
+                }
+            }
+        })
+        .await;
+    }
+
+    /// Immediately retry a dead connection, resetting the retry backoff
+    ///
+    /// Return `false` if the relay is not disconnected (i.e. healthy, connecting or terminated).
+    pub async fn notify_network_changed(&self) -> bool {
+        if self.is_banned() {
+            return false;
+        }
+
+        match self.status().await {
+            RelayStatus::Pending | RelayStatus::Disconnected => {
+                tracing::debug!("Network changed, reconnecting to '{}'", self.url);
+
+                self.stats.reset_retry();
+
+                self.send_external_notification(RelayPoolNotification::NetworkReconnect {
+                    relay_url: self.url(),
+                })
+                .await;
+
+                if self.opts.get_reconnect() {
+                    // Wake up the auto-reconnect loop, instead of connecting in parallel
+                    self.retry_now.notify_one();
+                } else {
+                    let relay = self.clone();
+                    let _ = thread::spawn(async move { relay.try_connect(None).await });
+                }
+
+                true
+            }
+            _ => false,
         }
     }
 
@@ -1028,6 +2003,9 @@ impl InternalRelay {
             return Err(Error::ReadDisabled);
         }
 
+        // Reconnect if disconnected for inactivity
+        self.wake_up().await;
+
         if opts.skip_disconnected
             && !self.is_connected().await
             && self.stats.attempts() > MIN_ATTEMPTS
@@ -1162,10 +2140,36 @@ impl InternalRelay {
             return Err(Error::ReadDisabled);
         }
 
-        let subscriptions = self.subscriptions().await;
-        for (id, filters) in subscriptions.into_iter() {
-            if !filters.is_empty() && self.should_resubscribe(&id).await {
-                self.send_msg(ClientMessage::req(id, filters), opts).await?;
+        // Notify the re-subscriptions only after a reconnection, not at the first connection
+        let reconnected: bool = self.stats.success() > 1;
+
+        let subscriptions = self.subscriptions.read().await.clone();
+        for (id, data) in subscriptions.into_iter() {
+            if !data.filters.is_empty() && self.should_resubscribe(&id).await {
+                let filters: Vec<Filter> = if self.opts.resubscribe_advance_since {
+                    let last_seen: Timestamp = self
+                        .last_seen
+                        .read()
+                        .await
+                        .get(&data.filters_hash)
+                        .copied()
+                        .unwrap_or_default();
+                    advance_since(data.filters, last_seen.max(data.last_event_at))
+                } else {
+                    data.filters
+                };
+
+                let msgs: Vec<ClientMessage> = self.req_msgs(id.clone(), filters, false).await?;
+                self.batch_msg(msgs, opts).await?;
+                self.set_eose(&id, false).await;
+
+                if reconnected {
+                    self.send_external_notification(RelayPoolNotification::Resubscribed {
+                        relay_url: self.url(),
+                        subscription_id: id,
+                    })
+                    .await;
+                }
             } else {
                 tracing::debug!("Skip re-subscription of '{id}'");
             }
@@ -1180,7 +2184,7 @@ impl InternalRelay {
         filters: Vec<Filter>,
         opts: SubscribeOptions,
     ) -> Result<SubscriptionId, Error> {
-        let id: SubscriptionId = SubscriptionId::generate();
+        let id: SubscriptionId = opts.id.clone().unwrap_or_else(SubscriptionId::generate);
         self.subscribe_with_id(id.clone(), filters, opts).await?;
         Ok(id)
     }
@@ -1201,9 +2205,9 @@ impl InternalRelay {
             return Err(Error::FiltersEmpty);
         }
 
-        // Compose and send REQ message
-        let msg: ClientMessage = ClientMessage::req(id.clone(), filters.clone());
-        self.send_msg(msg, opts.send_opts).await?;
+        // Compose and send REQ message/s (checking the subscription limits)
+        let msgs: Vec<ClientMessage> = self.req_msgs(id.clone(), filters.clone(), true).await?;
+        self.batch_msg(msgs, opts.send_opts).await?;
 
         // Check if auto-close condition is set
         match opts.auto_close {
@@ -1213,56 +2217,90 @@ impl InternalRelay {
                     let sub_id: SubscriptionId = id.clone();
                     let relay = this.clone();
                     let res: Option<bool> = time::timeout(opts.timeout, async move {
-                        let mut counter = 0;
-                        let mut received_eose: bool = false;
-
                         let mut notifications = relay.internal_notification_sender.subscribe();
-                        while let Ok(notification) = notifications.recv().await {
-                            match notification {
-                                RelayNotification::Message { message, .. } => match message {
-                                    RelayMessage::Event {
-                                        subscription_id, ..
-                                    } => {
-                                        if subscription_id.eq(&id) {
-                                            if let FilterOptions::WaitForEventsAfterEOSE(num) =
-                                                opts.filter
-                                            {
-                                                if received_eose {
-                                                    counter += 1;
-                                                    if counter >= num {
-                                                        break;
+
+                        let wait_for_eose = async {
+                            let mut counter = 0;
+                            let mut received_eose: bool = false;
+
+                            while let Ok(notification) = notifications.recv().await {
+                                match notification {
+                                    RelayNotification::Message { message, .. } => match message {
+                                        RelayMessage::Event {
+                                            subscription_id, ..
+                                        } => {
+                                            if subscription_id.eq(&id) {
+                                                if let FilterOptions::WaitForEventsAfterEOSE(num) =
+                                                    opts.filter
+                                                {
+                                                    if received_eose {
+                                                        counter += 1;
+                                                        if counter >= num {
+                                                            break;
+                                                        }
                                                     }
                                                 }
                                             }
                                         }
-                                    }
-                                    RelayMessage::EndOfStoredEvents(subscription_id) => {
-                                        if subscription_id.eq(&id) {
-                                            tracing::debug!(
-                                                "Received EOSE for subscription {id} from {}",
-                                                relay.url
-                                            );
-                                            received_eose = true;
-                                            if let FilterOptions::ExitOnEOSE
-                                            | FilterOptions::WaitDurationAfterEOSE(_) =
-                                                opts.filter
-                                            {
-                                                break;
+                                        RelayMessage::EndOfStoredEvents(subscription_id) => {
+                                            if subscription_id.eq(&id) {
+                                                tracing::debug!(
+                                                    "Received EOSE for subscription {id} from {}",
+                                                    relay.url
+                                                );
+                                                received_eose = true;
+                                                if let FilterOptions::ExitOnEOSE
+                                                | FilterOptions::WaitDurationAfterEOSE(_) =
+                                                    opts.filter
+                                                {
+                                                    break;
+                                                }
                                             }
                                         }
+                                        _ => (),
+                                    },
+                                    RelayNotification::RelayStatus { status } => {
+                                        if status.is_disconnected() {
+                                            return false; // No need to send CLOSE msg
+                                        }
                                     }
-                                    _ => (),
-                                },
-                                RelayNotification::RelayStatus { status } => {
-                                    if status.is_disconnected() {
+                                    RelayNotification::Shutdown => {
                                         return false; // No need to send CLOSE msg
                                     }
+                                    _ => (),
                                 }
-                                RelayNotification::Shutdown => {
-                                    return false; // No need to send CLOSE msg
+                            }
+
+                            true
+                        };
+
+                        let proceed: bool = match opts.eose_timeout {
+                            Some(eose_timeout) => {
+                                let res: Option<bool> =
+                                    time::timeout(Some(eose_timeout), wait_for_eose).await;
+                                match res {
+                                    Some(proceed) => proceed,
+                                    None => {
+                                        tracing::warn!(
+                                            "EOSE not received within {eose_timeout:?} for REQ {id} from {}",
+                                            relay.url
+                                        );
+
+                                        // Keep collecting events until the overall timeout (if any)
+                                        if opts.timeout.is_some() {
+                                            wait_for_disconnection(&mut notifications).await;
+                                            return false; // No need to send CLOSE msg
+                                        }
+
+                                        return true; // Need to send CLOSE msg
+                                    }
                                 }
-                                _ => (),
                             }
+                            None => wait_for_eose.await,
+                        };
+
+                        if !proceed {
+                            return false; // No need to send CLOSE msg
                         }
 
                         if let FilterOptions::WaitDurationAfterEOSE(duration) = opts.filter {
@@ -1296,13 +2334,11 @@ impl InternalRelay {
                         true
                     });
 
+                    let msgs: Vec<ClientMessage> = this.close_msgs(&sub_id).await;
+
                     if to_close {
                         // Unsubscribe
-                        this.send_msg(
-                            ClientMessage::close(sub_id.clone()),
-                            RelaySendOptions::default(),
-                        )
-                        .await?;
+                        this.batch_msg(msgs, RelaySendOptions::default()).await?;
 
                         tracing::debug!("Subscription {sub_id} auto-closed");
                     }
@@ -1312,13 +2348,63 @@ impl InternalRelay {
             }
             None => {
                 // No auto-close subscription: update subscription filters
-                self.update_subscription(id.clone(), filters, true).await;
+                self.store_subscription(id.clone(), filters, true).await;
             }
         };
 
         Ok(())
     }
 
+    pub async fn update_subscription(
+        &self,
+        id: SubscriptionId,
+        filters: Vec<Filter>,
+        opts: RelaySendOptions,
+    ) -> Result<(), Error> {
+        if !self.opts.flags.has_read() {
+            return Err(Error::ReadDisabled);
+        }
+
+        if filters.is_empty() {
+            return Err(Error::FiltersEmpty);
+        }
+
+        if self.subscription(&id).await.is_none() {
+            return Err(Error::SubscriptionNotFound(id));
+        }
+
+        // Get the REQ currently open for the subscription
+        let previous: Vec<SubscriptionId> = {
+            let splits = self.splits.read().await;
+            splits.reqs(&id)
+        };
+
+        // Compose new REQ message/s
+        let reqs: Vec<ClientMessage> = self.req_msgs(id.clone(), filters.clone(), true).await?;
+
+        // Close the REQ not overwritten by the new ones (all of them, if the relay doesn't overwrite subscriptions)
+        let mut msgs: Vec<ClientMessage> = previous
+            .into_iter()
+            .filter(|prev| {
+                !self.opts.overwrite_subscriptions
+                    || !reqs.iter().any(|req| match req {
+                        ClientMessage::Req {
+                            subscription_id, ..
+                        } => subscription_id == prev,
+                        _ => false,
+                    })
+            })
+            .map(ClientMessage::close)
+            .collect();
+        msgs.extend(reqs);
+        self.batch_msg(msgs, opts).await?;
+
+        // Update subscription filters
+        self.store_subscription(id, filters, true).await;
+
+        Ok(())
+    }
+
     pub async fn unsubscribe(
         &self,
         id: SubscriptionId,
@@ -1331,9 +2417,9 @@ impl InternalRelay {
         // Remove subscription
         self.remove_subscription(&id).await;
 
-        // Send CLOSE message
-        let msg: ClientMessage = ClientMessage::close(id);
-        self.send_msg(msg, opts).await
+        // Send CLOSE message/s
+        let msgs: Vec<ClientMessage> = self.close_msgs(&id).await;
+        self.batch_msg(msgs, opts).await
     }
 
     pub async fn unsubscribe_all(&self, opts: RelaySendOptions) -> Result<(), Error> {
@@ -1347,9 +2433,9 @@ impl InternalRelay {
             // Remove subscription
             self.remove_subscription(&id).await;
 
-            // Send CLOSE message
-            let msg: ClientMessage = ClientMessage::close(id);
-            self.send_msg(msg, opts).await?;
+            // Send CLOSE message/s
+            let msgs: Vec<ClientMessage> = self.close_msgs(&id).await;
+            self.batch_msg(msgs, opts).await?;
         }
 
         Ok(())
@@ -1536,9 +2622,15 @@ impl InternalRelay {
         Ok(count)
     }
 
-    pub async fn reconcile(&self, filter: Filter, opts: NegentropyOptions) -> Result<(), Error> {
+    pub async fn reconcile(
+        &self,
+        filter: Filter,
+        opts: NegentropyOptions,
+    ) -> Result<Reconciliation, Error> {
+        let filter: Filter = opts.constrain_filter(filter);
         let items = self.database.negentropy_items(filter.clone()).await?;
-        self.reconcile_with_items(filter, items, opts).await
+        self.reconcile_with_items_and_callback(filter, items, opts, |_| (), None)
+            .await
     }
 
     pub async fn reconcile_with_items(
@@ -1546,12 +2638,78 @@ impl InternalRelay {
         filter: Filter,
         items: Vec<(EventId, Timestamp)>,
         opts: NegentropyOptions,
-    ) -> Result<(), Error> {
+    ) -> Result<Reconciliation, Error> {
+        let filter: Filter = opts.constrain_filter(filter);
+        self.reconcile_with_items_and_callback(filter, items, opts, |_| (), None)
+            .await
+    }
+
+    pub fn reconcile_cancellable(
+        &self,
+        filter: Filter,
+        opts: NegentropyOptions,
+    ) -> Result<SyncHandle, Error> {
+        let (abort, abort_rx) = watch::channel(false);
+        let relay = self.clone();
+        let handle = thread::spawn(async move {
+            let filter: Filter = opts.constrain_filter(filter);
+            let items = relay.database.negentropy_items(filter.clone()).await?;
+            relay
+                .reconcile_with_items_and_callback(filter, items, opts, |_| (), Some(abort_rx))
+                .await
+        })?;
+        Ok(SyncHandle::new(abort, handle))
+    }
+
+    pub async fn reconcile_with_callback<F>(
+        &self,
+        filter: Filter,
+        opts: NegentropyOptions,
+        on_item: F,
+    ) -> Result<Reconciliation, Error>
+    where
+        F: Fn(ReconcileItem),
+    {
+        let filter: Filter = opts.constrain_filter(filter);
+        let items = self.database.negentropy_items(filter.clone()).await?;
+        self.reconcile_with_items_and_callback(filter, items, opts, on_item, None)
+            .await
+    }
+
+    /// Reconcile the items with the relay
+    ///
+    /// The filter must be already constrained (see [`NegentropyOptions::metadata_only`]).
+    pub async fn reconcile_with_items_and_callback<F>(
+        &self,
+        filter: Filter,
+        items: Vec<(EventId, Timestamp)>,
+        opts: NegentropyOptions,
+        on_item: F,
+        mut abort: Option<watch::Receiver<bool>>,
+    ) -> Result<Reconciliation, Error>
+    where
+        F: Fn(ReconcileItem),
+    {
         // Check if read option is disabled
         if !self.opts.flags.has_read() {
             return Err(Error::ReadDisabled);
         }
 
+        // Check if the filter has no metadata kinds left (nothing to reconcile)
+        if opts.metadata_only
+            && filter
+                .kinds
+                .as_ref()
+                .map_or(false, |kinds| kinds.is_empty())
+        {
+            return Ok(Reconciliation::default());
+        }
+
+        // Check if negentropy is known to be not supported
+        if *self.negentropy_support.read().await == Some(false) {
+            return Err(Error::NegentropyNotSupported);
+        }
+
         // Check if relay is connected
         if !self.is_connected().await
             && self.stats.attempts() > MIN_ATTEMPTS
@@ -1568,18 +2726,20 @@ impl InternalRelay {
         }
         negentropy.seal()?;
 
+        let mut notifications = self.internal_notification_sender.subscribe();
+        let mut temp_notifications = self.internal_notification_sender.subscribe();
+
         // Send initial negentropy message
         let sub_id = SubscriptionId::generate();
         let send_opts = RelaySendOptions::default().skip_send_confirmation(true);
         let open_msg = ClientMessage::neg_open(&mut negentropy, &sub_id, filter)?;
         self.send_msg(open_msg, send_opts).await?;
 
-        let mut notifications = self.internal_notification_sender.subscribe();
-        let mut temp_notifications = self.internal_notification_sender.subscribe();
-
         // Check if negentropy it's supported
         time::timeout(Some(opts.initial_timeout), async {
-            while let Ok(notification) = temp_notifications.recv().await {
+            while let Some(notification) =
+                next_notification(&mut temp_notifications, &mut abort).await
+            {
                 if let RelayNotification::Message { message } = notification {
                     match message {
                         RelayMessage::NegMsg {
@@ -1628,9 +2788,10 @@ impl InternalRelay {
         let mut have_ids: Vec<Bytes> = Vec::new();
         let mut need_ids: Vec<Bytes> = Vec::new();
         let down_sub_id: SubscriptionId = SubscriptionId::generate();
+        let mut output: Reconciliation = Reconciliation::default();
 
         // Start reconciliation
-        while let Ok(notification) = notifications.recv().await {
+        while let Some(notification) = next_notification(&mut notifications, &mut abort).await {
             match notification {
                 RelayNotification::Message { message } => {
                     match message {
@@ -1646,11 +2807,14 @@ impl InternalRelay {
                                     &mut need_ids,
                                 )?;
 
-                                if !do_up {
+                                // Save diff and emit the new items
+                                save_diff(&mut output, &have_ids, &need_ids, &on_item);
+
+                                if !do_up || opts.dry_run {
                                     have_ids.clear();
                                 }
 
-                                if !do_down {
+                                if !do_down || opts.dry_run {
                                     need_ids.clear();
                                 }
 
@@ -1686,11 +2850,23 @@ impl InternalRelay {
                             status,
                             message,
                         } => {
-                            if in_flight_up.remove(&event_id) && !status {
-                                tracing::error!(
-                                    "Unable to upload event {event_id} to {}: {message}",
-                                    self.url
-                                );
+                            if in_flight_up.remove(&event_id) {
+                                if status {
+                                    output.sent.insert(event_id);
+                                } else {
+                                    tracing::error!(
+                                        "Unable to upload event {event_id} to {}: {message}",
+                                        self.url
+                                    );
+                                }
+                            }
+                        }
+                        RelayMessage::Event {
+                            subscription_id,
+                            event,
+                        } => {
+                            if subscription_id == down_sub_id {
+                                output.received.insert(event.id());
                             }
                         }
                         RelayMessage::EndOfStoredEvents(id) => {
@@ -1785,7 +2961,19 @@ impl InternalRelay {
             }
         }
 
-        tracing::info!("Negentropy reconciliation terminated for {}", self.url);
+        let aborted: bool = abort.as_ref().map_or(false, |abort| *abort.borrow());
+
+        if aborted {
+            tracing::info!("Negentropy reconciliation aborted for {}", self.url);
+
+            // Stop the download of the requested events
+            if in_flight_down {
+                self.send_msg(ClientMessage::close(down_sub_id), send_opts)
+                    .await?;
+            }
+        } else {
+            tracing::info!("Negentropy reconciliation terminated for {}", self.url);
+        }
 
         // Close negentropy
         let close_msg = ClientMessage::NegClose {
@@ -1793,12 +2981,62 @@ impl InternalRelay {
         };
         self.send_msg(close_msg, send_opts).await?;
 
-        Ok(())
+        Ok(output)
     }
 
     pub async fn support_negentropy(&self) -> Result<bool, Error> {
-        let pk = Keys::generate();
-        let filter = Filter::new().author(pk.public_key());
+        let cached: Option<bool> = *self.negentropy_support.read().await;
+        match cached {
+            Some(support) => Ok(support),
+            None => self.probe_negentropy().await,
+        }
+    }
+
+    /// Check if relay support negentropy, caching the result
+    async fn probe_negentropy(&self) -> Result<bool, Error> {
+        let support: bool = self.request_negentropy_support().await?;
+        let mut negentropy_support = self.negentropy_support.write().await;
+        *negentropy_support = Some(support);
+        Ok(support)
+    }
+
+    /// Fetch the relay capabilities (NIP-11 document and negentropy support)
+    async fn warm_up(&self) {
+        #[cfg(feature = "nip11")]
+        let information = async {
+            if let Err(e) = self.refresh_information().await {
+                tracing::warn!(
+                    "Impossible to get information document from '{}': {e}",
+                    self.url
+                );
+            }
+        };
+
+        #[cfg(not(feature = "nip11"))]
+        let information = async {};
+
+        let negentropy = async {
+            if let Err(e) = self.probe_negentropy().await {
+                tracing::warn!(
+                    "Impossible to check negentropy support of '{}': {e}",
+                    self.url
+                );
+            }
+        };
+
+        futures_util::future::join(information, negentropy).await;
+
+        tracing::debug!("Warm-up completed for '{}'", self.url);
+
+        self.send_external_notification(RelayPoolNotification::RelayReady {
+            relay_url: self.url(),
+        })
+        .await;
+    }
+
+    async fn request_negentropy_support(&self) -> Result<bool, Error> {
+        let pk = Keys::generate();
+        let filter = Filter::new().author(pk.public_key());
         match self
             .reconcile_with_items(
                 filter,
@@ -1814,6 +3052,21 @@ impl InternalRelay {
     }
 }
 
+/// Wait until the relay disconnects or shuts down
+async fn wait_for_disconnection(notifications: &mut broadcast::Receiver<RelayNotification>) {
+    while let Ok(notification) = notifications.recv().await {
+        match notification {
+            RelayNotification::RelayStatus { status } => {
+                if status.is_disconnected() {
+                    break;
+                }
+            }
+            RelayNotification::Shutdown => break,
+            _ => (),
+        }
+    }
+}
+
 /// Send WebSocket messages with timeout set to [WEBSOCKET_TX_TIMEOUT].
 async fn send_ws_msgs<I>(tx: &mut Sink, msgs: I) -> Result<(), Error>
 where
@@ -1833,3 +3086,1031 @@ async fn close_ws(tx: &mut Sink) -> Result<(), Error> {
         None => Err(Error::WebSocketTimeout),
     }
 }
+
+/// Incremental retry time: `None` if less than 3 failed attempts (`diff`)
+fn adjusted_retry_sec(diff: u64) -> Option<u64> {
+    if diff >= 3 {
+        Some(cmp::min(MIN_RETRY_SEC * (1 + diff), MAX_ADJ_RETRY_SEC))
+    } else {
+        None
+    }
+}
+
+/// Check if relay is idle: no subscriptions and no activity for `idle_timeout`
+fn is_idle(last_activity: u64, now: u64, idle_timeout: Duration, has_subscriptions: bool) -> bool {
+    !has_subscriptions && now.saturating_sub(last_activity) >= idle_timeout.as_secs()
+}
+
+/// Advance `since` of the filters to the newest seen event (if any), to avoid to re-download events
+fn advance_since(filters: Vec<Filter>, last_event_at: Timestamp) -> Vec<Filter> {
+    if last_event_at.is_zero() {
+        return filters;
+    }
+
+    filters
+        .into_iter()
+        .map(|mut filter| {
+            match filter.since {
+                Some(since) if since >= last_event_at => (),
+                _ => filter.since = Some(last_event_at),
+            }
+            filter
+        })
+        .collect()
+}
+
+/// Receive the next notification
+///
+/// Return `None` if the channel is closed or if the sync is aborted.
+async fn next_notification(
+    notifications: &mut broadcast::Receiver<RelayNotification>,
+    abort: &mut Option<watch::Receiver<bool>>,
+) -> Option<RelayNotification> {
+    match abort {
+        Some(abort) => {
+            tokio::select! {
+                res = notifications.recv() => res.ok(),
+                _ = handle::aborted(abort) => None,
+            }
+        }
+        None => notifications.recv().await.ok(),
+    }
+}
+
+/// Save the reconciliation diff, emitting the items not already discovered
+fn save_diff<F>(output: &mut Reconciliation, have_ids: &[Bytes], need_ids: &[Bytes], on_item: &F)
+where
+    F: Fn(ReconcileItem),
+{
+    for id in have_ids
+        .iter()
+        .filter_map(|id| EventId::from_slice(id).ok())
+    {
+        if output.remote_missing.insert(id) {
+            on_item(ReconcileItem::NeedUpload(id));
+        }
+    }
+
+    for id in need_ids
+        .iter()
+        .filter_map(|id| EventId::from_slice(id).ok())
+    {
+        if output.local_missing.insert(id) {
+            on_item(ReconcileItem::NeedDownload(id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "nip11")]
+    use nostr::nips::nip11::Limitation;
+    use nostr::{EventBuilder, Metadata};
+    use nostr_database::MemoryDatabase;
+
+    use super::*;
+    use crate::mock::{MockRelay, MockRelayOptions};
+    use crate::relay::limits::RelayLimits;
+    use crate::relay::options::PingStrategy;
+
+    fn new_relay(url: Url, opts: RelayOptions) -> InternalRelay {
+        InternalRelay::new(
+            url,
+            Arc::new(MemoryDatabase::default()),
+            RelayBlacklist::empty(),
+            opts,
+        )
+    }
+
+    /// Relay never connected
+    fn offline_relay(opts: RelayOptions) -> InternalRelay {
+        new_relay(Url::parse("wss://relay.example.com").unwrap(), opts)
+    }
+
+    fn setup(mock: &MockRelay) -> InternalRelay {
+        new_relay(mock.url(), RelayOptions::default())
+    }
+
+    #[test]
+    fn test_is_idle() {
+        let timeout = Duration::from_secs(60);
+
+        assert!(is_idle(1_000, 1_060, timeout, false));
+        assert!(is_idle(1_000, 2_000, timeout, false));
+
+        // Recent activity
+        assert!(!is_idle(1_000, 1_059, timeout, false));
+        assert!(!is_idle(1_000, 900, timeout, false));
+
+        // Active subscriptions
+        assert!(!is_idle(1_000, 2_000, timeout, true));
+    }
+
+    #[test]
+    fn test_advance_since() {
+        let filters = vec![
+            Filter::new().kind(Kind::TextNote),
+            Filter::new()
+                .kind(Kind::Metadata)
+                .since(Timestamp::from(200)),
+        ];
+
+        // Nothing received
+        assert_eq!(
+            advance_since(filters.clone(), Timestamp::from(0)),
+            filters.clone()
+        );
+
+        let advanced = advance_since(filters, Timestamp::from(100));
+        assert_eq!(advanced[0].since, Some(Timestamp::from(100)));
+        assert_eq!(advanced[1].since, Some(Timestamp::from(200)));
+    }
+
+    #[test]
+    fn test_adjusted_retry_sec() {
+        assert_eq!(adjusted_retry_sec(0), None);
+        assert_eq!(adjusted_retry_sec(2), None);
+        assert_eq!(adjusted_retry_sec(3), Some(MIN_RETRY_SEC * 4));
+        assert!(adjusted_retry_sec(4) > adjusted_retry_sec(3));
+        assert_eq!(adjusted_retry_sec(1_000), Some(MAX_ADJ_RETRY_SEC));
+    }
+
+    async fn wait_for_ready(notifications: &mut broadcast::Receiver<RelayPoolNotification>) {
+        let ready = async {
+            while let Ok(notification) = notifications.recv().await {
+                if let RelayPoolNotification::RelayReady { .. } = notification {
+                    break;
+                }
+            }
+        };
+        time::timeout(Some(Duration::from_secs(10)), ready)
+            .await
+            .expect("Relay not ready");
+    }
+
+    async fn neg_opens(mock: &MockRelay) -> usize {
+        mock.received()
+            .await
+            .into_iter()
+            .filter(|msg| matches!(msg, ClientMessage::NegOpen { .. }))
+            .count()
+    }
+
+    #[tokio::test]
+    async fn test_warm_up() {
+        let mock = MockRelay::run().await;
+        let relay = new_relay(mock.url(), RelayOptions::default().warm_up(true));
+
+        let (tx, mut notifications) = broadcast::channel(1024);
+        relay.set_notification_sender(Some(tx)).await;
+
+        // Probed after connect
+        relay.connect(Some(Duration::from_secs(5))).await;
+        wait_for_ready(&mut notifications).await;
+        assert_eq!(*relay.negentropy_support.read().await, Some(true));
+        assert_eq!(neg_opens(&mock).await, 1);
+
+        // Cached
+        assert!(relay.support_negentropy().await.unwrap());
+        assert_eq!(neg_opens(&mock).await, 1);
+
+        // Probed again after reconnect
+        {
+            let mut support = relay.negentropy_support.write().await;
+            *support = Some(false);
+        }
+        relay.reconnect(Duration::from_secs(5)).await.unwrap();
+        wait_for_ready(&mut notifications).await;
+        assert_eq!(*relay.negentropy_support.read().await, Some(true));
+        assert_eq!(neg_opens(&mock).await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_reconnect() {
+        let mock = MockRelay::run().await;
+        let relay = setup(&mock);
+
+        relay.connect(Some(Duration::from_secs(5))).await;
+        assert!(relay.is_connected().await);
+
+        // Forced reconnection of a healthy connection
+        relay.reconnect(Duration::from_secs(5)).await.unwrap();
+        assert!(relay.is_connected().await);
+
+        // Dead connection
+        mock.disconnect_all();
+        time::timeout(Some(Duration::from_secs(2)), async {
+            while relay.is_connected().await {
+                thread::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        relay.reconnect(Duration::from_secs(5)).await.unwrap();
+        assert!(relay.is_connected().await);
+
+        // Connected only by the auto-reconnect loop: no double connections
+        thread::sleep(Duration::from_millis(500)).await;
+        assert_eq!(mock.connections(), 3);
+        assert_eq!(relay.stats.attempts(), 3);
+        assert_eq!(relay.stats.success(), 3);
+        assert_eq!(relay.stats.retry_state().attempts, 0);
+    }
+
+    #[tokio::test]
+    async fn test_notify_network_changed() {
+        let mock = MockRelay::run().await;
+        let relay = new_relay(mock.url(), RelayOptions::default().retry_sec(60));
+
+        // Healthy connection
+        relay.connect(Some(Duration::from_secs(5))).await;
+        assert!(relay.is_connected().await);
+        assert!(!relay.notify_network_changed().await);
+
+        // Dead connection, waiting the next retry
+        mock.disconnect_all();
+        time::timeout(Some(Duration::from_secs(2)), async {
+            while relay.is_connected().await {
+                thread::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        for _ in 0..5 {
+            relay.stats.new_attempt();
+        }
+
+        let mut notifications = relay.internal_notification_sender.subscribe();
+        assert!(relay.notify_network_changed().await);
+
+        // Retried immediately by the auto-reconnect loop
+        time::timeout(Some(Duration::from_secs(2)), async {
+            while let Ok(notification) = notifications.recv().await {
+                if let RelayNotification::RelayStatus {
+                    status: RelayStatus::Connected,
+                } = notification
+                {
+                    break;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(relay.stats.retry_state().attempts, 0);
+
+        // No parallel connections
+        thread::sleep(Duration::from_millis(500)).await;
+        assert_eq!(mock.connections(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_user_notification_channel_size() {
+        let relay = offline_relay(RelayOptions::default().notification_channel_size(4));
+
+        let id = SubscriptionId::new("feed");
+        relay
+            .store_subscription(id.clone(), vec![Filter::new()], false)
+            .await;
+
+        let mut internal = relay.internal_notification_sender.subscribe();
+        let mut user = relay.user_notification_sender.subscribe();
+
+        let keys = Keys::generate();
+        let events: Vec<Event> = (0..10)
+            .map(|i| {
+                EventBuilder::text_note(format!("{i}"), [])
+                    .to_event(&keys)
+                    .unwrap()
+            })
+            .collect();
+        relay.inject_events(&id, events).await;
+
+        // The user channel lags, the internal one not
+        assert!(matches!(
+            user.try_recv(),
+            Err(broadcast::error::TryRecvError::Lagged(..))
+        ));
+        let mut received: usize = 0;
+        while let Ok(notification) = internal.try_recv() {
+            if let RelayNotification::Event { .. } = notification {
+                received += 1;
+            }
+        }
+        assert_eq!(received, 10);
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        let mock = MockRelay::run().await;
+        let relay = new_relay(
+            mock.url(),
+            RelayOptions::default().idle_timeout(Some(Duration::from_secs(1))),
+        );
+
+        relay.connect(Some(Duration::from_secs(5))).await;
+        assert!(relay.is_connected().await);
+
+        // No subscriptions and no traffic
+        time::timeout(Some(Duration::from_secs(10)), async {
+            while relay.status().await != RelayStatus::Idle {
+                thread::sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .expect("Relay not idle");
+
+        // Reconnect on send
+        let keys = Keys::generate();
+        let event = EventBuilder::text_note("wake up", [])
+            .to_event(&keys)
+            .unwrap();
+        relay
+            .send_event(event.clone(), RelaySendOptions::default())
+            .await
+            .unwrap();
+        assert!(relay.is_connected().await);
+        assert_eq!(mock.connections(), 2);
+        assert_eq!(mock.events().await, vec![event]);
+    }
+
+    #[tokio::test]
+    async fn test_ping_strategy_active_only() {
+        let mock = MockRelay::run().await;
+        let opts = RelayOptions::default()
+            .ping(true)
+            .ping_strategy(PingStrategy::ActiveOnly);
+        let idle = new_relay(mock.url_with_path("/idle"), opts.clone());
+        let active = new_relay(mock.url_with_path("/active"), opts);
+        active
+            .store_subscription(SubscriptionId::new("active"), vec![Filter::new()], false)
+            .await;
+
+        idle.connect(Some(Duration::from_secs(5))).await;
+        active.connect(Some(Duration::from_secs(5))).await;
+
+        time::timeout(Some(Duration::from_secs(5)), async {
+            while active.stats.ping.last_nonce() == 0 {
+                thread::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("Subscribed relay not pinged");
+        assert_eq!(idle.stats.ping.last_nonce(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_message_tracer() {
+        let keys = Keys::generate();
+        let event = EventBuilder::text_note("traced", [])
+            .to_event(&keys)
+            .unwrap();
+
+        let mock = MockRelay::run().await;
+        mock.add_events([event.clone()]).await;
+
+        let relay = setup(&mock);
+
+        let traced: Arc<std::sync::Mutex<Vec<(MessageDirection, String)>>> =
+            Arc::new(std::sync::Mutex::new(Vec::new()));
+        let t = traced.clone();
+        relay
+            .set_message_tracer(Some(MessageTracer::new(move |_, direction, msg| {
+                t.lock().unwrap().push((direction, msg.to_string()));
+            })))
+            .await;
+
+        relay.connect(Some(Duration::from_secs(5))).await;
+        let id = relay
+            .subscribe(
+                vec![Filter::new().author(keys.public_key())],
+                SubscribeOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        let req: String =
+            ClientMessage::req(id.clone(), vec![Filter::new().author(keys.public_key())]).as_json();
+        let received: String = RelayMessage::event(id, event).as_json();
+        time::timeout(Some(Duration::from_secs(5)), async {
+            loop {
+                {
+                    let traced = traced.lock().unwrap();
+                    if traced.contains(&(MessageDirection::Incoming, received.clone())) {
+                        break;
+                    }
+                }
+                thread::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("EVENT not traced");
+
+        let traced = traced.lock().unwrap();
+        assert!(traced.contains(&(MessageDirection::Outgoing, req)));
+    }
+
+    #[tokio::test]
+    async fn test_resubscribed_notification() {
+        let mock = MockRelay::run().await;
+        let relay = setup(&mock);
+
+        let (tx, mut notifications) = broadcast::channel(1024);
+        relay.set_notification_sender(Some(tx)).await;
+
+        // Stored before connecting: the first `REQ` is not a re-subscription
+        let id = SubscriptionId::new("resubscribed");
+        relay
+            .store_subscription(id.clone(), vec![Filter::new().limit(1)], false)
+            .await;
+        relay.connect(Some(Duration::from_secs(5))).await;
+        assert!(relay.is_connected().await);
+
+        time::timeout(Some(Duration::from_secs(5)), async {
+            while !mock.received().await.iter().any(|msg| msg.is_req()) {
+                thread::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("REQ not sent");
+
+        while let Ok(notification) = notifications.try_recv() {
+            if let RelayPoolNotification::Resubscribed { .. } = notification {
+                panic!("Resubscribed on first connection");
+            }
+        }
+
+        // Reconnection
+        relay.reconnect(Duration::from_secs(5)).await.unwrap();
+        let resubscribed = time::timeout(Some(Duration::from_secs(5)), async {
+            while let Ok(notification) = notifications.recv().await {
+                if let RelayPoolNotification::Resubscribed {
+                    subscription_id, ..
+                } = notification
+                {
+                    return subscription_id;
+                }
+            }
+            panic!("Notification channel closed");
+        })
+        .await
+        .expect("Not resubscribed");
+        assert_eq!(resubscribed, id);
+    }
+
+    #[tokio::test]
+    async fn test_resubscribe_since_last_seen() {
+        let relay = offline_relay(RelayOptions::default().resubscribe_advance_since(true));
+
+        let id = SubscriptionId::new("feed");
+        let filters = vec![Filter::new().kind(Kind::TextNote)];
+        relay
+            .store_subscription(id.clone(), filters.clone(), false)
+            .await;
+        assert_eq!(relay.last_seen(&filters).await, None);
+
+        // Events received in the previous connection
+        relay.update_last_event_at(&id, Timestamp::from(100)).await;
+        relay.update_last_event_at(&id, Timestamp::from(50)).await;
+        assert_eq!(relay.last_seen(&filters).await, Some(Timestamp::from(100)));
+
+        // Re-subscribe after the reconnection
+        relay
+            .resubscribe_all(RelaySendOptions::default().skip_send_confirmation(true))
+            .await
+            .unwrap();
+
+        let mut rx = relay.channels.rx_nostr().await;
+        let msg = rx.recv().await.unwrap();
+        match msg.msgs.into_iter().next() {
+            Some(ClientMessage::Req { filters, .. }) => {
+                assert_eq!(filters[0].since, Some(Timestamp::from(100)));
+            }
+            _ => panic!("expected REQ"),
+        }
+        drop(rx);
+
+        // Pruned with the subscription
+        relay.remove_subscription(&id).await;
+        assert_eq!(relay.last_seen(&filters).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_max_subscriptions_with_splits() {
+        let mut limits = RelayLimits::default();
+        limits.subscriptions.max_subscriptions = Some(3);
+        limits.subscriptions.max_filters = Some(1);
+
+        let relay = offline_relay(RelayOptions::default().limits(limits));
+        let opts = SubscribeOptions::default()
+            .send_opts(RelaySendOptions::default().skip_send_confirmation(true));
+        let filters =
+            |n: usize| -> Vec<Filter> { (0..n).map(|i| Filter::new().limit(i)).collect() };
+
+        relay
+            .subscribe_with_id(SubscriptionId::new("a"), filters(2), opts.clone())
+            .await
+            .unwrap();
+
+        // 2 REQ open, the new subscription needs 2 REQ
+        assert!(matches!(
+            relay
+                .subscribe_with_id(SubscriptionId::new("b"), filters(2), opts.clone())
+                .await,
+            Err(Error::TooManySubscriptions { max: 3 })
+        ));
+        relay
+            .subscribe_with_id(SubscriptionId::new("b"), filters(1), opts)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_drain() {
+        let mock = MockRelay::run().await;
+        let relay = setup(&mock);
+
+        // No subscriptions
+        assert!(relay.drain(Duration::from_millis(100)).await);
+
+        let id = SubscriptionId::new("feed");
+        relay
+            .store_subscription(id.clone(), vec![Filter::new()], true)
+            .await;
+        assert_eq!(relay.pending_eose().await, vec![id.clone()]);
+
+        // Not connected: return immediately
+        let now = Instant::now();
+        assert!(!relay.drain(Duration::from_secs(5)).await);
+        assert!(now.elapsed() < Duration::from_secs(1));
+
+        relay.connect(Some(Duration::from_secs(5))).await;
+        assert!(relay.is_connected().await);
+
+        // EOSE never received
+        assert!(!relay.drain(Duration::from_millis(100)).await);
+
+        // EOSE received during the drain
+        let r = relay.clone();
+        let eose_id = id.clone();
+        let _ = thread::spawn(async move {
+            thread::sleep(Duration::from_millis(100)).await;
+            let msg: String = RelayMessage::eose(eose_id).as_json();
+            r.handle_relay_message_infallible(msg.as_bytes()).await;
+        });
+        assert!(relay.drain(Duration::from_secs(2)).await);
+        assert!(relay.pending_eose().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_update_subscription() {
+        let relay = offline_relay(RelayOptions::default());
+        let opts = RelaySendOptions::default().skip_send_confirmation(true);
+
+        let id = SubscriptionId::new("feed");
+        let filter = Filter::new().kind(Kind::TextNote);
+        relay
+            .store_subscription(
+                id.clone(),
+                vec![filter.clone().since(Timestamp::from(1_000))],
+                false,
+            )
+            .await;
+
+        // Widen the window
+        let filters = vec![filter.since(Timestamp::from(100))];
+        relay
+            .update_subscription(id.clone(), filters.clone(), opts)
+            .await
+            .unwrap();
+        assert_eq!(relay.subscription(&id).await, Some(filters.clone()));
+
+        // Same subscription ID reused, without closing it
+        {
+            let mut rx = relay.channels.rx_nostr().await;
+            let msg = rx.recv().await.unwrap();
+            assert_eq!(msg.msgs, vec![ClientMessage::req(id.clone(), filters)]);
+        }
+
+        // Events of the new window are delivered to the same subscription
+        let mut notifications = relay.internal_notification_sender.subscribe();
+        let event = EventBuilder::text_note("old", [])
+            .custom_created_at(Timestamp::from(500))
+            .to_event(&Keys::generate())
+            .unwrap();
+        relay.inject_events(&id, vec![event.clone()]).await;
+
+        let received = time::timeout(Some(Duration::from_secs(2)), async {
+            while let Ok(notification) = notifications.recv().await {
+                if let RelayNotification::Event {
+                    subscription_id,
+                    event,
+                } = notification
+                {
+                    return Some((subscription_id, event.id()));
+                }
+            }
+            None
+        })
+        .await
+        .flatten();
+        assert_eq!(received, Some((id.clone(), event.id())));
+
+        // Relay that doesn't overwrite subscriptions
+        let relay = offline_relay(RelayOptions::default().overwrite_subscriptions(false));
+        relay
+            .store_subscription(id.clone(), vec![Filter::new().kind(Kind::TextNote)], false)
+            .await;
+        let filters = vec![Filter::new().kind(Kind::Repost)];
+        relay
+            .update_subscription(id.clone(), filters.clone(), opts)
+            .await
+            .unwrap();
+
+        let mut rx = relay.channels.rx_nostr().await;
+        let msg = rx.recv().await.unwrap();
+        assert_eq!(
+            msg.msgs,
+            vec![
+                ClientMessage::close(id.clone()),
+                ClientMessage::req(id, filters)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_abort_reconciliation() {
+        let relay = offline_relay(RelayOptions::default());
+
+        let handle = relay
+            .reconcile_cancellable(Filter::new(), NegentropyOptions::new())
+            .unwrap();
+
+        // Slow relay: reply to `NEG-OPEN` but never send the requested events
+        let id = EventId::from_slice(&[1; 32]).unwrap();
+        let sub_id: SubscriptionId = {
+            let mut rx = relay.channels.rx_nostr().await;
+            let msg = rx.recv().await.unwrap();
+            let (subscription_id, initial_message) = match msg.msgs.into_iter().next() {
+                Some(ClientMessage::NegOpen {
+                    subscription_id,
+                    initial_message,
+                    ..
+                }) => (subscription_id, initial_message),
+                _ => panic!("expected NEG-OPEN"),
+            };
+
+            let mut negentropy = Negentropy::new(32, None).unwrap();
+            negentropy
+                .add_item(0, Bytes::from_slice(id.as_bytes()))
+                .unwrap();
+            negentropy.seal().unwrap();
+            let reply: Bytes = negentropy
+                .reconcile(&Bytes::from_hex(initial_message).unwrap())
+                .unwrap();
+
+            let _ = relay
+                .internal_notification_sender
+                .send(RelayNotification::Message {
+                    message: RelayMessage::NegMsg {
+                        subscription_id: subscription_id.clone(),
+                        message: reply.to_hex(),
+                    },
+                });
+
+            // Wait the download request
+            while let Some(msg) = rx.recv().await {
+                if msg.msgs[0].is_req() {
+                    break;
+                }
+            }
+
+            subscription_id
+        };
+
+        handle.abort();
+        assert!(handle.is_aborted());
+
+        let output = time::timeout(Some(Duration::from_secs(2)), handle.output())
+            .await
+            .expect("sync not aborted")
+            .unwrap();
+        assert!(output.local_missing.contains(&id));
+        assert!(output.received.is_empty());
+
+        // Download stopped and negentropy session closed
+        let mut rx = relay.channels.rx_nostr().await;
+        let msg = rx.recv().await.unwrap();
+        assert!(msg.msgs[0].is_close());
+        let msg = rx.recv().await.unwrap();
+        assert_eq!(
+            msg.msgs[0],
+            ClientMessage::NegClose {
+                subscription_id: sub_id
+            }
+        );
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "nip11")]
+    async fn test_set_document_updates_cache() {
+        let opts = RelayOptions::default().auto_limits_from_nip11(true);
+        let relay = offline_relay(opts);
+
+        assert!(relay.information_age().is_none());
+
+        let mut document = RelayInformationDocument::new();
+        document.name = Some(String::from("Example"));
+        document.limitation = Some(Limitation {
+            max_subscriptions: Some(1),
+            ..Default::default()
+        });
+        relay.set_document(document).await;
+
+        assert_eq!(relay.document().await.name, Some(String::from("Example")));
+        assert!(relay.information_age().unwrap() < Duration::from_secs(2));
+
+        let limits = relay.subscription_limits().await;
+        assert_eq!(limits.max_subscriptions, Some(1));
+    }
+
+    #[test]
+    fn test_save_diff() {
+        let a = EventId::all_zeros();
+        let b = EventId::from_slice(&[1; 32]).unwrap();
+        let have_ids = vec![Bytes::from_slice(a.as_bytes())];
+        let need_ids = vec![Bytes::from_slice(b.as_bytes())];
+
+        let items: std::sync::Mutex<Vec<ReconcileItem>> = std::sync::Mutex::new(Vec::new());
+        let on_item = |item: ReconcileItem| items.lock().unwrap().push(item);
+
+        let mut output = Reconciliation::default();
+        save_diff(&mut output, &have_ids, &need_ids, &on_item);
+        assert_eq!(
+            *items.lock().unwrap(),
+            vec![ReconcileItem::NeedUpload(a), ReconcileItem::NeedDownload(b)]
+        );
+        assert!(output.remote_missing.contains(&a));
+        assert!(output.local_missing.contains(&b));
+
+        // Already discovered items are not emitted again
+        save_diff(&mut output, &have_ids, &need_ids, &on_item);
+        assert_eq!(items.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_metadata_only() {
+        let keys = Keys::generate();
+        let metadata = EventBuilder::metadata(&Metadata::new().name("alice"))
+            .to_event(&keys)
+            .unwrap();
+        let note = EventBuilder::text_note("content", [])
+            .to_event(&keys)
+            .unwrap();
+        let mock = MockRelay::run().await;
+        mock.add_events([metadata.clone(), note]).await;
+
+        let relay = setup(&mock);
+        relay.connect(Some(Duration::from_secs(5))).await;
+        assert!(relay.is_connected().await);
+
+        let opts = NegentropyOptions::new().metadata_only(true);
+
+        // No metadata kinds in the filter: nothing to reconcile
+        let output = relay
+            .reconcile(Filter::new().kind(Kind::TextNote), opts)
+            .await
+            .unwrap();
+        assert_eq!(output, Reconciliation::default());
+        assert_eq!(neg_opens(&mock).await, 0);
+
+        // Constrained once
+        let output = relay
+            .reconcile(Filter::new().kinds([Kind::Metadata, Kind::TextNote]), opts)
+            .await
+            .unwrap();
+        assert_eq!(output.received, HashSet::from([metadata.id()]));
+        let filters: Vec<Filter> = mock
+            .received()
+            .await
+            .into_iter()
+            .filter_map(|msg| match msg {
+                ClientMessage::NegOpen { filter, .. } => Some(*filter),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(filters, vec![Filter::new().kind(Kind::Metadata)]);
+    }
+
+    async fn wait_for_closes(mock: &MockRelay, count: usize) {
+        time::timeout(Some(Duration::from_secs(5)), async {
+            while mock
+                .received()
+                .await
+                .iter()
+                .filter(|msg| msg.is_close())
+                .count()
+                < count
+            {
+                thread::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("CLOSE not sent");
+    }
+
+    #[tokio::test]
+    async fn test_eose_timeout() {
+        let keys = Keys::generate();
+        let event = EventBuilder::text_note("no eose", [])
+            .to_event(&keys)
+            .unwrap();
+        let mock = MockRelay::run_with_opts(MockRelayOptions::new().no_eose()).await;
+        mock.add_events([event.clone()]).await;
+
+        let relay = setup(&mock);
+        let (tx, mut notifications) = broadcast::channel(1024);
+        relay.set_notification_sender(Some(tx)).await;
+        relay.connect(Some(Duration::from_secs(5))).await;
+        assert!(relay.is_connected().await);
+
+        // EOSE never received: keep collecting events until the overall timeout
+        let opts = SubscribeAutoCloseOptions::default()
+            .eose_timeout(Some(Duration::from_millis(200)))
+            .timeout(Some(Duration::from_secs(2)));
+        let started = Instant::now();
+        relay
+            .subscribe(
+                vec![Filter::new()],
+                SubscribeOptions::default().close_on(Some(opts)),
+            )
+            .await
+            .unwrap();
+        wait_for_closes(&mock, 1).await;
+        assert!(started.elapsed() >= Duration::from_secs(2));
+
+        let mut received: bool = false;
+        while let Ok(notification) = notifications.try_recv() {
+            if let RelayPoolNotification::Event { event: e, .. } = notification {
+                received |= e.id() == event.id();
+            }
+        }
+        assert!(received);
+
+        // Without the overall timeout: closed as soon as the EOSE timeout expires
+        let opts =
+            SubscribeAutoCloseOptions::default().eose_timeout(Some(Duration::from_millis(200)));
+        let started = Instant::now();
+        relay
+            .subscribe(
+                vec![Filter::new()],
+                SubscribeOptions::default().close_on(Some(opts)),
+            )
+            .await
+            .unwrap();
+        wait_for_closes(&mock, 2).await;
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    /// Subscribe to a relay serving 10 events, with a stalled consumer of the external notifications
+    async fn stalled_consumer(
+        policy: EventOverflowPolicy,
+    ) -> (
+        MockRelay,
+        broadcast::Receiver<RelayNotification>,
+        broadcast::Receiver<RelayPoolNotification>,
+    ) {
+        let keys = Keys::generate();
+        let mock = MockRelay::run_with_opts(MockRelayOptions::new().no_eose()).await;
+        let events = (0..10).map(|i| {
+            EventBuilder::text_note(i.to_string(), [])
+                .to_event(&keys)
+                .unwrap()
+        });
+        mock.add_events(events).await;
+
+        let relay = setup(&mock);
+        let (tx, ..) = broadcast::channel(4);
+        relay.set_notification_sender(Some(tx.clone())).await;
+        relay.set_event_overflow_policy(policy, 4).await;
+        relay.connect(Some(Duration::from_secs(5))).await;
+        assert!(relay.is_connected().await);
+
+        let internal = relay.internal_notification_sender.subscribe();
+        let stalled = tx.subscribe();
+        relay
+            .subscribe(vec![Filter::new()], SubscribeOptions::default())
+            .await
+            .unwrap();
+
+        (mock, internal, stalled)
+    }
+
+    async fn wait_for_events(
+        notifications: &mut broadcast::Receiver<RelayNotification>,
+        count: usize,
+    ) {
+        let mut handled: usize = 0;
+        time::timeout(Some(Duration::from_secs(5)), async {
+            while handled < count {
+                if let Ok(RelayNotification::Event { .. }) = notifications.recv().await {
+                    handled += 1;
+                }
+            }
+        })
+        .await
+        .expect("Events not handled");
+    }
+
+    #[tokio::test]
+    async fn test_event_overflow_drop_oldest() {
+        let (_mock, mut internal, mut stalled) =
+            stalled_consumer(EventOverflowPolicy::DropOldest).await;
+        wait_for_events(&mut internal, 10).await;
+
+        assert!(matches!(
+            stalled.try_recv(),
+            Err(broadcast::error::TryRecvError::Lagged(..))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_event_overflow_drop_newest() {
+        let (mock, mut internal, mut stalled) =
+            stalled_consumer(EventOverflowPolicy::DropNewest).await;
+        wait_for_events(&mut internal, 10).await;
+
+        let mut events: usize = 0;
+        let mut dropped: Vec<Url> = Vec::new();
+        loop {
+            match stalled.try_recv() {
+                Ok(RelayPoolNotification::Event { .. }) => events += 1,
+                Ok(RelayPoolNotification::EventDropped { relay_url }) => dropped.push(relay_url),
+                Ok(..) => (),
+                Err(broadcast::error::TryRecvError::Empty) => break,
+                Err(e) => panic!("Unexpected error: {e}"),
+            }
+        }
+
+        // New events dropped, notified once
+        assert!(events > 0 && events < 10);
+        assert_eq!(dropped, vec![mock.url()]);
+    }
+
+    #[tokio::test]
+    async fn test_event_overflow_block() {
+        let (_mock, mut internal, mut stalled) = stalled_consumer(EventOverflowPolicy::Block).await;
+        wait_for_events(&mut internal, 2).await;
+
+        // Relay blocked by the stalled consumer
+        thread::sleep(Duration::from_millis(500)).await;
+        let mut handled: usize = 2;
+        while let Ok(notification) = internal.try_recv() {
+            if let RelayNotification::Event { .. } = notification {
+                handled += 1;
+            }
+        }
+        assert!(handled < 10);
+
+        // Consumer catches up: no events lost
+        let mut events: usize = 0;
+        time::timeout(Some(Duration::from_secs(5)), async {
+            while events < 10 {
+                if let RelayPoolNotification::Event { .. } = stalled.recv().await.unwrap() {
+                    events += 1;
+                }
+            }
+        })
+        .await
+        .expect("Events not received");
+    }
+
+    #[tokio::test]
+    async fn test_ping_now() {
+        let mock = MockRelay::run().await;
+        let relay = setup(&mock);
+
+        // Not connected
+        assert!(matches!(
+            relay.ping_now().await.unwrap_err(),
+            Error::NotConnected
+        ));
+
+        relay.connect(Some(Duration::from_secs(5))).await;
+        assert!(relay.is_connected().await);
+
+        // Simulate a periodic ping waiting for the pong
+        relay.stats.ping.set_last_nonce(42);
+        relay.stats.ping.set_replied(false);
+
+        relay.ping_now().await.unwrap();
+        relay.ping_now().await.unwrap();
+
+        // Periodic ping state untouched
+        assert_eq!(relay.stats.ping.last_nonce(), 42);
+        assert!(!relay.stats.ping.replied());
+
+        // Latency saved and pending pings cleaned
+        assert_eq!(relay.stats.latency_reads().await, 2);
+        assert!(relay.on_demand_pings.lock().await.is_empty());
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright (c) 2022-2023 Yuki Kishimoto
+// Copyright (c) 2023-2024 Rust Nostr Developers
+// Distributed under the MIT software license
+
+//! Last seen events
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+use nostr::{Filter, SingleLetterTag};
+
+/// Compute the signature of the filters of a subscription
+///
+/// Used as in-memory key of the newest event seen from a relay (see [`Relay::last_seen`](super::Relay::last_seen)).
+/// The `since` field is ignored, since it's advanced on re-subscription.
+///
+/// **Not stable** across Rust versions (uses [`DefaultHasher`]): never persist it.
+pub(super) fn filters_hash(filters: &[Filter]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    for filter in filters.iter() {
+        sorted(&filter.ids).hash(&mut hasher);
+        sorted(&filter.authors).hash(&mut hasher);
+        sorted(&filter.kinds).hash(&mut hasher);
+
+        let mut tags: Vec<(&SingleLetterTag, Vec<&String>)> = filter
+            .generic_tags
+            .iter()
+            .map(|(tag, values)| {
+                let mut values: Vec<&String> = values.iter().collect();
+                values.sort();
+                (tag, values)
+            })
+            .collect();
+        tags.sort();
+        tags.hash(&mut hasher);
+
+        filter.search.hash(&mut hasher);
+        filter.until.hash(&mut hasher);
+        filter.limit.hash(&mut hasher);
+    }
+
+    hasher.finish()
+}
+
+fn sorted<T>(set: &Option<HashSet<T>>) -> Option<Vec<&T>>
+where
+    T: Ord,
+{
+    set.as_ref().map(|set| {
+        let mut values: Vec<&T> = set.iter().collect();
+        values.sort();
+        values
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr::{Keys, Kind, Timestamp};
+
+    use super::*;
+
+    #[test]
+    fn test_filters_hash() {
+        let authors: Vec<_> = (0..10).map(|_| Keys::generate().public_key()).collect();
+        let a = Filter::new()
+            .authors(authors.clone())
+            .kinds([Kind::TextNote, Kind::Repost]);
+        let b = Filter::new()
+            .authors(authors.into_iter().rev())
+            .kinds([Kind::Repost, Kind::TextNote])
+            .since(Timestamp::from(1_000));
+
+        // Order of values and `since` are ignored
+        assert_eq!(filters_hash(&[a.clone()]), filters_hash(&[b]));
+        assert_ne!(filters_hash(&[a.clone()]), filters_hash(&[a.limit(10)]));
+    }
+}
@@ -4,9 +4,15 @@
 
 //! Relay limits
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
 
-use nostr::Kind;
+#[cfg(feature = "nip11")]
+use nostr::nips::nip11::Limitation;
+use nostr::{Filter, Kind, SingleLetterTag};
+
+use super::Error;
 
 const MAX_EVENT_SIZE: u32 = 70_000; // bytes
 const MAX_CONTACT_LIST_EVENT_SIZE: u32 = 840_000; // bytes
@@ -18,6 +24,8 @@ pub struct RelayLimits {
     pub messages: RelayMessageLimits,
     /// Event limits
     pub events: RelayEventLimits,
+    /// Subscription limits
+    pub subscriptions: RelaySubscriptionLimits,
 }
 
 impl RelayLimits {
@@ -27,6 +35,7 @@ impl RelayLimits {
         Self {
             messages: RelayMessageLimits::disable(),
             events: RelayEventLimits::disable(),
+            subscriptions: RelaySubscriptionLimits::disable(),
         }
     }
 }
@@ -129,8 +138,237 @@ impl RelayEventLimits {
     }
 }
 
+/// Policy for filters exceeding the [`RelaySubscriptionLimits`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OversizedFilterPolicy {
+    /// Split the filter in many filters within the limits (the `limit` is lowered to `max_limit`)
+    #[default]
+    Split,
+    /// Reject the subscription, with an error identifying the offending field
+    Reject,
+}
+
+/// Subscription limits
+///
+/// Unset limits can be filled with the ones advertised by the relay (see `RelayOptions::auto_limits_from_nip11`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RelaySubscriptionLimits {
+    /// Maximum number of active subscriptions (default: none)
+    pub max_subscriptions: Option<u16>,
+    /// Maximum number of filters per `REQ` (default: none)
+    ///
+    /// Subscriptions with more filters are split into many `REQ`, transparently.
+    pub max_filters: Option<u16>,
+    /// Maximum `limit` of a filter (default: none)
+    pub max_limit: Option<usize>,
+    /// Maximum number of values of a filter field (`ids`, `authors`, `kinds` or a tag) (default: none)
+    pub max_filter_values: Option<usize>,
+    /// What to do with the filters that exceed `max_limit` or `max_filter_values` (default: split)
+    pub oversized_filters: OversizedFilterPolicy,
+}
+
+impl RelaySubscriptionLimits {
+    /// Disable all limits
+    #[inline]
+    pub fn disable() -> Self {
+        Self::default()
+    }
+
+    /// Check if `new` `REQ` can be opened, with `active` `REQ` already open
+    pub(crate) fn check(&self, active: usize, new: usize) -> Result<(), Error> {
+        if let Some(max) = self.max_subscriptions {
+            if active + new > max as usize {
+                return Err(Error::TooManySubscriptions { max });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check filters against `max_limit` and `max_filter_values`
+    ///
+    /// Return the filters to send, split according to the [`OversizedFilterPolicy`].
+    pub(crate) fn check_filters(&self, filters: Vec<Filter>) -> Result<Vec<Filter>, Error> {
+        if self.max_limit.is_none() && self.max_filter_values.is_none() {
+            return Ok(filters);
+        }
+
+        let mut checked: Vec<Filter> = Vec::with_capacity(filters.len());
+        for filter in filters.into_iter() {
+            self.check_filter(filter, &mut checked)?;
+        }
+        Ok(checked)
+    }
+
+    fn check_filter(&self, mut filter: Filter, checked: &mut Vec<Filter>) -> Result<(), Error> {
+        if let (Some(max), Some(limit)) = (self.max_limit, filter.limit) {
+            if limit > max {
+                match self.oversized_filters {
+                    OversizedFilterPolicy::Split => filter.limit = Some(max),
+                    OversizedFilterPolicy::Reject => {
+                        return Err(Error::FilterTooLarge {
+                            field: String::from("limit"),
+                            len: limit,
+                            max,
+                        })
+                    }
+                }
+            }
+        }
+
+        let max: usize = match self.max_filter_values {
+            Some(max) => max.max(1),
+            None => {
+                checked.push(filter);
+                return Ok(());
+            }
+        };
+
+        match FilterField::oversized(&filter, max) {
+            Some((field, len)) => match self.oversized_filters {
+                OversizedFilterPolicy::Split => {
+                    // Other fields may exceed the limit too
+                    for chunk in field.split(filter, max).into_iter() {
+                        self.check_filter(chunk, checked)?;
+                    }
+                    Ok(())
+                }
+                OversizedFilterPolicy::Reject => Err(Error::FilterTooLarge {
+                    field: field.to_string(),
+                    len,
+                    max,
+                }),
+            },
+            None => {
+                checked.push(filter);
+                Ok(())
+            }
+        }
+    }
+
+    /// Fill unset limits with the ones advertised in the NIP-11 document
+    ///
+    /// Explicit limits are always preferred over the advertised ones.
+    #[cfg(feature = "nip11")]
+    pub(crate) fn merge_advertised(self, limitation: &Limitation) -> Self {
+        Self {
+            max_subscriptions: self
+                .max_subscriptions
+                .or_else(|| advertised(limitation.max_subscriptions)),
+            max_filters: self
+                .max_filters
+                .or_else(|| advertised(limitation.max_filters)),
+            max_limit: self.max_limit.or_else(|| advertised(limitation.max_limit)),
+            ..self
+        }
+    }
+}
+
+#[cfg(feature = "nip11")]
+fn advertised<T>(value: Option<i32>) -> Option<T>
+where
+    T: TryFrom<i32>,
+{
+    value.and_then(|v| T::try_from(v).ok())
+}
+
+/// Filter field with many values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FilterField {
+    Ids,
+    Authors,
+    Kinds,
+    Tag(SingleLetterTag),
+}
+
+impl fmt::Display for FilterField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ids => write!(f, "ids"),
+            Self::Authors => write!(f, "authors"),
+            Self::Kinds => write!(f, "kinds"),
+            Self::Tag(tag) => write!(f, "#{tag}"),
+        }
+    }
+}
+
+impl FilterField {
+    /// Find the first field with more than `max` values
+    fn oversized(filter: &Filter, max: usize) -> Option<(Self, usize)> {
+        if len(&filter.ids) > max {
+            return Some((Self::Ids, len(&filter.ids)));
+        }
+
+        if len(&filter.authors) > max {
+            return Some((Self::Authors, len(&filter.authors)));
+        }
+
+        if len(&filter.kinds) > max {
+            return Some((Self::Kinds, len(&filter.kinds)));
+        }
+
+        filter
+            .generic_tags
+            .iter()
+            .find(|(.., values)| values.len() > max)
+            .map(|(tag, values)| (Self::Tag(*tag), values.len()))
+    }
+
+    /// Split the filter in chunks of `max` values of this field
+    fn split(self, filter: Filter, max: usize) -> Vec<Filter> {
+        match self {
+            Self::Ids => chunks(filter.ids.clone(), max)
+                .into_iter()
+                .map(|ids| Filter {
+                    ids: Some(ids),
+                    ..filter.clone()
+                })
+                .collect(),
+            Self::Authors => chunks(filter.authors.clone(), max)
+                .into_iter()
+                .map(|authors| Filter {
+                    authors: Some(authors),
+                    ..filter.clone()
+                })
+                .collect(),
+            Self::Kinds => chunks(filter.kinds.clone(), max)
+                .into_iter()
+                .map(|kinds| Filter {
+                    kinds: Some(kinds),
+                    ..filter.clone()
+                })
+                .collect(),
+            Self::Tag(tag) => chunks(filter.generic_tags.get(&tag).cloned(), max)
+                .into_iter()
+                .map(|values| {
+                    let mut filter: Filter = filter.clone();
+                    filter.generic_tags.insert(tag, values);
+                    filter
+                })
+                .collect(),
+        }
+    }
+}
+
+fn len<T>(set: &Option<HashSet<T>>) -> usize {
+    set.as_ref().map_or(0, |set| set.len())
+}
+
+fn chunks<T>(set: Option<HashSet<T>>, max: usize) -> Vec<HashSet<T>>
+where
+    T: Clone + Eq + Hash,
+{
+    let values: Vec<T> = set.unwrap_or_default().into_iter().collect();
+    values
+        .chunks(max)
+        .map(|chunk| chunk.iter().cloned().collect())
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
+    use nostr::{Keys, PublicKey};
+
     use super::*;
 
     #[test]
@@ -146,4 +384,79 @@ mod tests {
             Some(MAX_CONTACT_LIST_EVENT_SIZE)
         );
     }
+
+    #[test]
+    #[cfg(feature = "nip11")]
+    fn test_subscription_limits_merge_advertised() {
+        let limitation = Limitation {
+            max_subscriptions: Some(1),
+            max_filters: Some(-1),
+            ..Default::default()
+        };
+
+        let limits = RelaySubscriptionLimits::default().merge_advertised(&limitation);
+        assert_eq!(limits.max_subscriptions, Some(1));
+        assert_eq!(limits.max_filters, None);
+        assert!(limits.check(0, 1).is_ok());
+        assert!(matches!(
+            limits.check(1, 1),
+            Err(Error::TooManySubscriptions { max: 1 })
+        ));
+        assert!(matches!(
+            limits.check(0, 2),
+            Err(Error::TooManySubscriptions { max: 1 })
+        ));
+
+        // Explicit limits win
+        let limits = RelaySubscriptionLimits {
+            max_subscriptions: Some(20),
+            ..Default::default()
+        }
+        .merge_advertised(&limitation);
+        assert_eq!(limits.max_subscriptions, Some(20));
+    }
+
+    #[test]
+    fn test_check_filters() {
+        let authors: Vec<PublicKey> = (0..5).map(|_| Keys::generate().public_key()).collect();
+        let filter = Filter::new().authors(authors.clone()).limit(1_000);
+
+        // Split
+        let limits = RelaySubscriptionLimits {
+            max_limit: Some(500),
+            max_filter_values: Some(2),
+            ..Default::default()
+        };
+        let filters = limits.check_filters(vec![filter.clone()]).unwrap();
+        assert_eq!(filters.len(), 3);
+        assert!(filters.iter().all(|f| f.limit == Some(500)));
+
+        let split: HashSet<PublicKey> = filters
+            .into_iter()
+            .flat_map(|f| f.authors.unwrap_or_default())
+            .collect();
+        assert_eq!(split, authors.into_iter().collect());
+
+        // Reject
+        let limits = RelaySubscriptionLimits {
+            max_filter_values: Some(2),
+            oversized_filters: OversizedFilterPolicy::Reject,
+            ..Default::default()
+        };
+        match limits.check_filters(vec![filter]) {
+            Err(Error::FilterTooLarge { field, len, max }) => {
+                assert_eq!(field, "authors");
+                assert_eq!(len, 5);
+                assert_eq!(max, 2);
+            }
+            res => panic!("unexpected result: {res:?}"),
+        }
+
+        // Within the limits
+        let small = Filter::new().kind(Kind::TextNote).limit(10);
+        assert_eq!(
+            limits.check_filters(vec![small.clone()]).unwrap(),
+            vec![small]
+        );
+    }
 }
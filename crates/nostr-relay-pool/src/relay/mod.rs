@@ -16,7 +16,8 @@ use atomic_destructor::AtomicDestructor;
 #[cfg(feature = "nip11")]
 use nostr::nips::nip11::RelayInformationDocument;
 use nostr::{
-    ClientMessage, Event, EventId, Filter, RelayMessage, Result, SubscriptionId, Timestamp, Url,
+    ClientMessage, Event, EventId, Filter, Kind, RelayMessage, Result, SubscriptionId, Timestamp,
+    Url,
 };
 use nostr_database::{DynNostrDatabase, MemoryDatabase};
 use tokio::sync::broadcast;
@@ -25,24 +26,34 @@ mod blacklist;
 mod constants;
 mod error;
 pub mod flags;
+mod handle;
+mod history;
 mod internal;
+mod last_seen;
 pub mod limits;
 pub mod options;
+mod split;
 pub mod stats;
 mod status;
+mod tracer;
+mod validator;
 
 pub use self::blacklist::RelayBlacklist;
 pub use self::error::Error;
 pub use self::flags::{AtomicRelayServiceFlags, RelayServiceFlags};
+pub use self::handle::SyncHandle;
+pub use self::history::ConnectionEvent;
 use self::internal::InternalRelay;
 pub use self::limits::RelayLimits;
 pub use self::options::{
-    FilterOptions, NegentropyDirection, NegentropyOptions, RelayOptions, RelaySendOptions,
-    SubscribeAutoCloseOptions, SubscribeOptions,
+    FilterOptions, NegentropyDirection, NegentropyOptions, PingStrategy, RelayOptions,
+    RelaySendOptions, SubscribeAutoCloseOptions, SubscribeOptions,
 };
-pub use self::stats::RelayConnectionStats;
+pub use self::stats::{RelayConnectionStats, RelayStats, RetryState};
 pub use self::status::RelayStatus;
-use crate::pool::RelayPoolNotification;
+pub use self::tracer::{MessageDirection, MessageTracer};
+pub use self::validator::EventValidator;
+use crate::pool::{ReconcileItem, Reconciliation, RelayPoolNotification};
 
 /// Relay Notification
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -159,6 +170,14 @@ impl Relay {
         self.inner.is_connected().await
     }
 
+    /// Check if [`Relay`] has been banned for too many invalid signatures
+    ///
+    /// See [`InvalidSigPolicy::DropAndBanRelay`](crate::pool::InvalidSigPolicy::DropAndBanRelay).
+    #[inline]
+    pub fn is_banned(&self) -> bool {
+        self.inner.is_banned()
+    }
+
     /// Get [`RelayInformationDocument`]
     #[inline]
     #[cfg(feature = "nip11")]
@@ -166,6 +185,24 @@ impl Relay {
         self.inner.document().await
     }
 
+    /// Re-fetch the [`RelayInformationDocument`]
+    ///
+    /// Advertised limits are re-applied if [`RelayOptions::auto_limits_from_nip11`] is enabled.
+    #[inline]
+    #[cfg(feature = "nip11")]
+    pub async fn refresh_information(&self) -> Result<(), Error> {
+        self.inner.refresh_information().await
+    }
+
+    /// Get time elapsed since the last [`RelayInformationDocument`] update
+    ///
+    /// Return `None` if the document has never been fetched.
+    #[inline]
+    #[cfg(feature = "nip11")]
+    pub fn information_age(&self) -> Option<Duration> {
+        self.inner.information_age()
+    }
+
     /// Get subscriptions
     #[inline]
     pub async fn subscriptions(&self) -> HashMap<SubscriptionId, Vec<Filter>> {
@@ -190,6 +227,49 @@ impl Relay {
         self.inner.stats()
     }
 
+    /// Get reconnection [`RetryState`]
+    #[inline]
+    pub fn retry_state(&self) -> RetryState {
+        self.inner.stats().retry_state()
+    }
+
+    /// Take a snapshot of the relay stats
+    ///
+    /// Counters are read without waiting for the relay event loop (only short read locks are taken).
+    #[inline]
+    pub async fn stats_snapshot(&self) -> RelayStats {
+        self.inner.stats_snapshot().await
+    }
+
+    /// Get the number of received events per kind
+    #[inline]
+    pub async fn kind_histogram(&self) -> HashMap<Kind, u64> {
+        self.inner.stats().kind_histogram().await
+    }
+
+    /// Reset the number of received events per kind
+    #[inline]
+    pub async fn reset_kind_histogram(&self) {
+        self.inner.stats().reset_kind_histogram().await
+    }
+
+    /// Get the timestamp of the newest event seen for the filters (the `since` field and the order of the values are ignored)
+    ///
+    /// Tracked only if [`RelayOptions::resubscribe_advance_since`] is enabled.
+    /// Kept across reconnections, in memory only, and forgotten when the subscription is closed.
+    #[inline]
+    pub async fn last_seen(&self, filters: &[Filter]) -> Option<Timestamp> {
+        self.inner.last_seen(filters).await
+    }
+
+    /// Get recent connection lifecycle events (oldest first)
+    ///
+    /// The number of saved events can be configured with [`RelayOptions::history_size`].
+    #[inline]
+    pub async fn history(&self) -> Vec<ConnectionEvent> {
+        self.inner.history().await
+    }
+
     /// Get queue len
     #[inline]
     pub fn queue(&self) -> usize {
@@ -198,10 +278,17 @@ impl Relay {
 
     /// Get new **relay** notification listener
     ///
+    /// Carry only the notifications of this relay (events, messages and status changes),
+    /// that are also aggregated in the [`RelayPool`](crate::RelayPool) notifications if the relay belongs to a pool.
+    /// Dropping the receiver doesn't affect the pool notifications.
+    ///
+    /// Events are notified in the same order they are received from the relay.
+    /// The capacity of the channel can be changed with [`RelayOptions::notification_channel_size`].
+    ///
     /// <div class="warning">When you call this method, you subscribe to the notifications channel from that precise moment. Anything received by relay/s before that moment is not included in the channel!</div>
     #[inline]
     pub fn notifications(&self) -> broadcast::Receiver<RelayNotification> {
-        self.inner.internal_notification_sender.subscribe()
+        self.inner.user_notification_sender.subscribe()
     }
 
     /// Set external notification sender
@@ -221,6 +308,27 @@ impl Relay {
         self.inner.connect(connection_timeout).await
     }
 
+    /// Force reconnection to relay
+    ///
+    /// Close the current connection (if any), reset the [`RetryState`] and immediately try to re-establish the connection,
+    /// without waiting for the retry interval of the auto-reconnect loop.
+    /// The connection/uptime stats are kept.
+    #[inline]
+    pub async fn reconnect(&self, connection_timeout: Duration) -> Result<(), Error> {
+        self.inner.reconnect(connection_timeout).await
+    }
+
+    /// Send a WebSocket ping and measure the round-trip time
+    ///
+    /// The sample is included in the latency average (see [`RelayConnectionStats::latency`]).
+    ///
+    /// Return [`Error::NotConnected`] if the relay is not connected.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[inline]
+    pub async fn ping_now(&self) -> Result<Duration, Error> {
+        self.inner.ping_now().await
+    }
+
     /// Disconnect from relay and set status to 'Terminated'
     #[inline]
     pub async fn disconnect(&self) -> Result<(), Error> {
@@ -294,6 +402,21 @@ impl Relay {
         self.inner.subscribe_with_id(id, filters, opts).await
     }
 
+    /// Update the filters of a subscription
+    ///
+    /// The `REQ` is sent again with the same [`SubscriptionId`], so the relay replaces the filters (NIP-01)
+    /// and the events are still delivered to the same subscription.
+    /// If [`RelayOptions::overwrite_subscriptions`] is disabled, the subscription is closed and then reopened.
+    #[inline]
+    pub async fn update_subscription(
+        &self,
+        id: SubscriptionId,
+        filters: Vec<Filter>,
+        opts: RelaySendOptions,
+    ) -> Result<(), Error> {
+        self.inner.update_subscription(id, filters, opts).await
+    }
+
     /// Unsubscribe
     #[inline]
     pub async fn unsubscribe(
@@ -354,7 +477,11 @@ impl Relay {
     ///
     /// Use events stored in database
     #[inline]
-    pub async fn reconcile(&self, filter: Filter, opts: NegentropyOptions) -> Result<(), Error> {
+    pub async fn reconcile(
+        &self,
+        filter: Filter,
+        opts: NegentropyOptions,
+    ) -> Result<Reconciliation, Error> {
         self.inner.reconcile(filter, opts).await
     }
 
@@ -365,11 +492,47 @@ impl Relay {
         filter: Filter,
         items: Vec<(EventId, Timestamp)>,
         opts: NegentropyOptions,
-    ) -> Result<(), Error> {
+    ) -> Result<Reconciliation, Error> {
         self.inner.reconcile_with_items(filter, items, opts).await
     }
 
+    /// Negentropy reconciliation that can be aborted
+    ///
+    /// The sync runs in background: use [`SyncHandle::abort`] to stop it and [`SyncHandle::output`] to get the result.
+    ///
+    /// Use events stored in database
+    #[inline]
+    pub fn reconcile_cancellable(
+        &self,
+        filter: Filter,
+        opts: NegentropyOptions,
+    ) -> Result<SyncHandle, Error> {
+        self.inner.reconcile_cancellable(filter, opts)
+    }
+
+    /// Negentropy reconciliation, calling `on_item` for every [`ReconcileItem`] as soon as it's discovered
+    ///
+    /// The items are emitted during the negentropy exchange, before the events transfer (also in dry run).
+    ///
+    /// Use events stored in database
+    #[inline]
+    pub async fn reconcile_with_callback<F>(
+        &self,
+        filter: Filter,
+        opts: NegentropyOptions,
+        on_item: F,
+    ) -> Result<Reconciliation, Error>
+    where
+        F: Fn(ReconcileItem),
+    {
+        self.inner
+            .reconcile_with_callback(filter, opts, on_item)
+            .await
+    }
+
     /// Check if relay support negentropy protocol
+    ///
+    /// The result is cached: the relay is probed only the first time (or at the warm-up, if enabled).
     #[inline]
     pub async fn support_negentropy(&self) -> Result<bool, Error> {
         self.inner.support_negentropy().await
@@ -10,6 +10,8 @@ use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
+use nostr::{Filter, Kind, SubscriptionId, Timestamp};
+
 use super::flags::{AtomicRelayServiceFlags, RelayServiceFlags};
 use crate::RelayLimits;
 
@@ -19,9 +21,18 @@ pub const DEFAULT_SEND_TIMEOUT: Duration = Duration::from_secs(20);
 pub(super) const DEFAULT_RETRY_SEC: u64 = 10;
 pub(super) const MIN_RETRY_SEC: u64 = 5;
 pub(super) const MAX_ADJ_RETRY_SEC: u64 = 60;
+pub(super) const DEFAULT_HISTORY_SIZE: usize = 10;
+pub(super) const DEFAULT_NOTIFICATION_CHANNEL_SIZE: usize = 2048;
 pub(super) const NEGENTROPY_HIGH_WATER_UP: usize = 100;
 pub(super) const NEGENTROPY_LOW_WATER_UP: usize = 50;
 pub(super) const NEGENTROPY_BATCH_SIZE_DOWN: usize = 50;
+/// Profile and relay metadata kinds (used by [`NegentropyOptions::metadata_only`])
+const METADATA_KINDS: [Kind; 4] = [
+    Kind::Metadata,
+    Kind::ContactList,
+    Kind::RelayList,
+    Kind::Replaceable(10050),
+];
 
 /// [`Relay`](super::Relay) options
 #[derive(Debug, Clone)]
@@ -34,6 +45,18 @@ pub struct RelayOptions {
     retry_sec: Arc<AtomicU64>,
     adjust_retry_sec: Arc<AtomicBool>,
     pub(super) limits: RelayLimits,
+    #[cfg(feature = "nip11")]
+    pub(super) auto_limits_from_nip11: bool,
+    group: Option<String>,
+    pub(super) history_size: usize,
+    pub(super) notification_channel_size: usize,
+    pub(super) resubscribe_advance_since: bool,
+    pub(super) overwrite_subscriptions: bool,
+    max_event_age: Option<Duration>,
+    max_future_drift: Option<Duration>,
+    pub(super) idle_timeout: Option<Duration>,
+    pub(super) ping_strategy: PingStrategy,
+    pub(super) warm_up: bool,
 }
 
 impl Default for RelayOptions {
@@ -47,6 +70,18 @@ impl Default for RelayOptions {
             retry_sec: Arc::new(AtomicU64::new(DEFAULT_RETRY_SEC)),
             adjust_retry_sec: Arc::new(AtomicBool::new(true)),
             limits: RelayLimits::default(),
+            #[cfg(feature = "nip11")]
+            auto_limits_from_nip11: false,
+            group: None,
+            history_size: DEFAULT_HISTORY_SIZE,
+            notification_channel_size: DEFAULT_NOTIFICATION_CHANNEL_SIZE,
+            resubscribe_advance_since: false,
+            overwrite_subscriptions: true,
+            max_event_age: None,
+            max_future_drift: None,
+            idle_timeout: None,
+            ping_strategy: PingStrategy::default(),
+            warm_up: false,
         }
     }
 }
@@ -100,6 +135,19 @@ impl RelayOptions {
         self
     }
 
+    /// Set archive flag
+    ///
+    /// Archive relays are used only for historical queries: routine subscriptions and queries skip them
+    /// (see [`RelayServiceFlags::ARCHIVE`]).
+    pub fn archive(self, archive: bool) -> Self {
+        if archive {
+            self.flags.add(RelayServiceFlags::ARCHIVE);
+        } else {
+            self.flags.remove(RelayServiceFlags::ARCHIVE);
+        }
+        self
+    }
+
     /// Minimum POW for received events (default: 0)
     pub fn pow(mut self, diffculty: u8) -> Self {
         self.pow = Arc::new(AtomicU8::new(diffculty));
@@ -172,6 +220,16 @@ impl RelayOptions {
         self.adjust_retry_sec.load(Ordering::SeqCst)
     }
 
+    /// Update the shared options (flags, POW difficulty and reconnection options) with the ones of `other`
+    pub(crate) fn update(&self, other: &Self) {
+        self.flags.replace(&other.flags);
+        self.update_pow_difficulty(other.get_pow_difficulty());
+        self.update_reconnect(other.get_reconnect());
+        self.retry_sec
+            .store(other.get_retry_sec(), Ordering::SeqCst);
+        self.update_adjust_retry_sec(other.get_adjust_retry_sec());
+    }
+
     /// Set adjust_retry_sec option
     pub fn update_adjust_retry_sec(&self, adjust_retry_sec: bool) {
         self.adjust_retry_sec
@@ -183,6 +241,143 @@ impl RelayOptions {
         self.limits = limits;
         self
     }
+
+    /// Apply the subscription limits advertised in the relay NIP-11 document (default: false)
+    ///
+    /// Limits explicitly set in [`RelayLimits::subscriptions`] take precedence over the advertised ones.
+    ///
+    /// The advertised `max_message_length`, `max_event_tags` and `max_content_length` are not applied:
+    /// they limit what the relay accepts from clients, while [`RelayLimits::messages`] and [`RelayLimits::events`]
+    /// limit what is received from the relay.
+    #[cfg(feature = "nip11")]
+    pub fn auto_limits_from_nip11(mut self, enable: bool) -> Self {
+        self.auto_limits_from_nip11 = enable;
+        self
+    }
+
+    /// Set group label (default: none)
+    ///
+    /// Groups are only metadata used to filter the relays of the pool (i.e. `personal`, `public`, `paid`).
+    /// For now, a relay can belong to only one group.
+    pub fn group<S>(mut self, group: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.group = Some(group.into());
+        self
+    }
+
+    pub(crate) fn get_group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
+
+    /// Max number of connection lifecycle events to keep in [`Relay::history`](super::Relay::history) (default: 10)
+    ///
+    /// Set to `0` to disable it.
+    pub fn history_size(mut self, size: usize) -> Self {
+        self.history_size = size;
+        self
+    }
+
+    /// Capacity of the **relay** notifications channel (default: 2048)
+    ///
+    /// [`Relay::notifications`](super::Relay::notifications) receivers that are slower than the relay
+    /// lose the oldest notifications (`RecvError::Lagged`): increase it to absorb bursts of events.
+    /// The relay internals (queries, reconciliation, ...) and the pool notifications are not affected,
+    /// since they use their own channels (see [`RelayPoolOptions::notification_channel_size`](crate::RelayPoolOptions::notification_channel_size)).
+    pub fn notification_channel_size(mut self, size: usize) -> Self {
+        self.notification_channel_size = size;
+        self
+    }
+
+    /// On re-subscription after a reconnection, advance the `since` field of the filters
+    /// to the timestamp of the newest received event (default: false)
+    ///
+    /// Avoid to re-download events already received in the previous connection.
+    /// The newest event seen for the same filters is used, also if received by a previous subscription
+    /// (see [`Relay::last_seen`](super::Relay::last_seen)).
+    pub fn resubscribe_advance_since(mut self, enable: bool) -> Self {
+        self.resubscribe_advance_since = enable;
+        self
+    }
+
+    /// Update subscriptions in place, sending a new `REQ` with the same subscription ID (default: true)
+    ///
+    /// NIP-01 requires relays to replace the filters of an existing subscription.
+    /// Disable it for relays that don't do it: the subscription is closed and then reopened
+    /// (see [`Relay::update_subscription`](super::Relay::update_subscription)).
+    pub fn overwrite_subscriptions(mut self, enable: bool) -> Self {
+        self.overwrite_subscriptions = enable;
+        self
+    }
+
+    /// Drop received events with a `created_at` older than `max_age` (default: None)
+    ///
+    /// Dropped events are notified with [`RelayPoolNotification::Rejected`](crate::RelayPoolNotification::Rejected).
+    pub fn max_event_age(mut self, max_age: Option<Duration>) -> Self {
+        self.max_event_age = max_age;
+        self
+    }
+
+    /// Drop received events with a `created_at` more than `max_drift` in the future (default: None)
+    ///
+    /// Dropped events are notified with [`RelayPoolNotification::Rejected`](crate::RelayPoolNotification::Rejected).
+    pub fn max_future_drift(mut self, max_drift: Option<Duration>) -> Self {
+        self.max_future_drift = max_drift;
+        self
+    }
+
+    /// Disconnect the relay if there are no subscriptions and no messages sent or received for `timeout` (default: None)
+    ///
+    /// The relay status is set to [`RelayStatus::Idle`](super::RelayStatus::Idle)
+    /// and the connection is automatically re-established when a new message is sent (i.e. a new subscription or event).
+    /// Pings don't count as activity.
+    pub fn idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Prefetch the relay capabilities after every successful connection (default: false)
+    ///
+    /// The NIP-11 document (if the `nip11` feature is enabled) and the negentropy support are fetched concurrently and cached,
+    /// then [`RelayPoolNotification::RelayReady`](crate::RelayPoolNotification::RelayReady) is sent.
+    pub fn warm_up(mut self, enable: bool) -> Self {
+        self.warm_up = enable;
+        self
+    }
+
+    /// Choose when to send the keepalive pings, if the ping flag is set (default: [`PingStrategy::All`])
+    pub fn ping_strategy(mut self, strategy: PingStrategy) -> Self {
+        self.ping_strategy = strategy;
+        self
+    }
+
+    /// Check if `created_at` is within the [`RelayOptions::max_event_age`] and [`RelayOptions::max_future_drift`] bounds
+    pub(crate) fn check_created_at(
+        &self,
+        created_at: Timestamp,
+        now: Timestamp,
+    ) -> Result<(), String> {
+        if let Some(max_age) = self.max_event_age {
+            if created_at < now - max_age {
+                return Err(format!(
+                    "event older than max age ({} secs)",
+                    max_age.as_secs()
+                ));
+            }
+        }
+
+        if let Some(max_drift) = self.max_future_drift {
+            if created_at > now + max_drift {
+                return Err(format!(
+                    "event too far in the future (max drift: {} secs)",
+                    max_drift.as_secs()
+                ));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 /// [`Relay`](super::Relay) send options
@@ -235,6 +430,7 @@ impl RelaySendOptions {
 pub struct SubscribeAutoCloseOptions {
     pub(super) filter: FilterOptions,
     pub(super) timeout: Option<Duration>,
+    pub(super) eose_timeout: Option<Duration>,
 }
 
 impl SubscribeAutoCloseOptions {
@@ -249,16 +445,38 @@ impl SubscribeAutoCloseOptions {
         self.timeout = timeout;
         self
     }
+
+    /// Max time to wait for `EOSE` (default: `None`)
+    ///
+    /// If `EOSE` is not received within this [Duration] (i.e. the relay never sends it),
+    /// the subscription keeps collecting events until the overall [`SubscribeAutoCloseOptions::timeout`] and then is closed.
+    /// If the overall timeout is not set, the subscription is immediately closed.
+    pub fn eose_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.eose_timeout = timeout;
+        self
+    }
 }
 
 /// Subscribe options
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct SubscribeOptions {
     pub(super) auto_close: Option<SubscribeAutoCloseOptions>,
-    pub(super) send_opts: RelaySendOptions,
+    pub(crate) send_opts: RelaySendOptions,
+    pub(super) max_concurrent_relays: Option<usize>,
+    pub(crate) id: Option<SubscriptionId>,
+    pub(crate) global_limit: Option<usize>,
 }
 
 impl SubscribeOptions {
+    /// Use a custom [`SubscriptionId`] (default: auto-generated)
+    ///
+    /// The ID is used verbatim in `REQ` and `CLOSE` messages and must be unique within the pool.
+    /// Ignored by the `subscribe_with_id` methods.
+    pub fn id(mut self, id: SubscriptionId) -> Self {
+        self.id = Some(id);
+        self
+    }
+
     /// Set auto-close conditions
     pub fn close_on(mut self, opts: Option<SubscribeAutoCloseOptions>) -> Self {
         self.auto_close = opts;
@@ -271,8 +489,64 @@ impl SubscribeOptions {
         self
     }
 
+    /// Set max number of relays to which the `REQ` is dispatched at the same time (default: unbounded)
+    ///
+    /// The remaining relays are queued and a new `REQ` is sent as soon as one of the active relays sends `EOSE`
+    /// (or after the send timeout, if `EOSE` is never received).
+    ///
+    /// Used only when subscribing to more than one relay.
+    pub fn max_concurrent_relays(mut self, max: Option<usize>) -> Self {
+        self.max_concurrent_relays = max;
+        self
+    }
+
+    /// Max number of unique events delivered across all relays (default: none)
+    ///
+    /// `limit` of filters is per-relay: once `limit` unique events have been delivered (after the deduplication),
+    /// the subscription is closed on all relays and the events still in flight are dropped.
+    /// Can be combined with [`SubscribeAutoCloseOptions`]: the subscription is closed by the first satisfied condition.
+    /// A limit of `0` is rejected.
+    ///
+    /// Used only by the pool.
+    pub fn global_limit(mut self, limit: Option<usize>) -> Self {
+        self.global_limit = limit;
+        self
+    }
+
     pub(crate) fn is_auto_closing(&self) -> bool {
-        self.auto_close.is_some()
+        self.auto_close.is_some() || self.global_limit.is_some()
+    }
+
+    pub(crate) fn concurrency_limit(&self) -> Option<usize> {
+        self.max_concurrent_relays
+    }
+
+    pub(crate) fn send_timeout(&self) -> Duration {
+        self.send_opts.timeout
+    }
+}
+
+/// Keepalive ping strategy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PingStrategy {
+    /// Ping the relay while connected
+    #[default]
+    All,
+    /// Ping the relay only while there are active subscriptions
+    ///
+    /// Idle connections are not kept alive: use [`RelayOptions::idle_timeout`] to close them.
+    ActiveOnly,
+    /// Never ping the relay
+    None,
+}
+
+impl PingStrategy {
+    pub(super) fn should_ping(&self, has_subscriptions: bool) -> bool {
+        match self {
+            Self::All => true,
+            Self::ActiveOnly => has_subscriptions,
+            Self::None => false,
+        }
     }
 }
 
@@ -315,6 +589,8 @@ impl NegentropyDirection {
 pub struct NegentropyOptions {
     pub(super) initial_timeout: Duration,
     pub(super) direction: NegentropyDirection,
+    pub(super) dry_run: bool,
+    pub(super) metadata_only: bool,
 }
 
 impl Default for NegentropyOptions {
@@ -322,6 +598,8 @@ impl Default for NegentropyOptions {
         Self {
             initial_timeout: Duration::from_secs(10),
             direction: NegentropyDirection::default(),
+            dry_run: false,
+            metadata_only: false,
         }
     }
 }
@@ -345,4 +623,112 @@ impl NegentropyOptions {
         self.direction = direction;
         self
     }
+
+    /// Dry run (default: false)
+    ///
+    /// Only compute the differences, without sending or receiving events.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Reconcile only profile and relay metadata kinds (`0`, `3`, `10002` and `10050`) (default: false)
+    ///
+    /// Useful to quickly bootstrap the social graph before syncing the content.
+    /// The kinds of the filter are restricted to the metadata ones (or set to all of them, if the filter has no kinds).
+    /// The [`NegentropyDirection`] is honored as usual.
+    pub fn metadata_only(mut self, metadata_only: bool) -> Self {
+        self.metadata_only = metadata_only;
+        self
+    }
+
+    /// Constrain the reconciliation filter, according to the options
+    pub(crate) fn constrain_filter(&self, mut filter: Filter) -> Filter {
+        if !self.metadata_only {
+            return filter;
+        }
+
+        let kinds: Vec<Kind> = match filter.kinds.take() {
+            Some(kinds) if !kinds.is_empty() => METADATA_KINDS
+                .into_iter()
+                .filter(|k| kinds.contains(k))
+                .collect(),
+            _ => METADATA_KINDS.to_vec(),
+        };
+
+        filter.kinds(kinds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_created_at() {
+        let now = Timestamp::from(1_000_000);
+        let opts = RelayOptions::new()
+            .max_event_age(Some(Duration::from_secs(3600)))
+            .max_future_drift(Some(Duration::from_secs(60)));
+
+        // Old event
+        assert!(opts
+            .check_created_at(Timestamp::from(1_000_000 - 3601), now)
+            .is_err());
+
+        // Future-dated event
+        assert!(opts
+            .check_created_at(Timestamp::from(1_000_000 + 61), now)
+            .is_err());
+
+        // In window
+        assert!(opts.check_created_at(now, now).is_ok());
+        assert!(opts
+            .check_created_at(Timestamp::from(1_000_000 - 3600), now)
+            .is_ok());
+        assert!(opts
+            .check_created_at(Timestamp::from(1_000_000 + 60), now)
+            .is_ok());
+
+        // Disabled
+        let opts = RelayOptions::new();
+        assert!(opts.check_created_at(Timestamp::from(0), now).is_ok());
+        assert!(opts
+            .check_created_at(Timestamp::from(u64::MAX), now)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_ping_strategy() {
+        assert!(PingStrategy::All.should_ping(false));
+        assert!(PingStrategy::All.should_ping(true));
+
+        // Idle relays not pinged
+        assert!(!PingStrategy::ActiveOnly.should_ping(false));
+        assert!(PingStrategy::ActiveOnly.should_ping(true));
+
+        assert!(!PingStrategy::None.should_ping(true));
+    }
+
+    #[test]
+    fn test_negentropy_metadata_only() {
+        let filter = Filter::new().kinds([Kind::TextNote, Kind::Metadata]);
+
+        // Disabled
+        let opts = NegentropyOptions::new();
+        assert_eq!(opts.constrain_filter(filter.clone()), filter);
+
+        // Enabled: intersection
+        let opts = NegentropyOptions::new().metadata_only(true);
+        assert_eq!(
+            opts.constrain_filter(filter),
+            Filter::new().kind(Kind::Metadata)
+        );
+
+        // Enabled: no kinds
+        assert_eq!(
+            opts.constrain_filter(Filter::new()),
+            Filter::new().kinds(METADATA_KINDS)
+        );
+    }
 }
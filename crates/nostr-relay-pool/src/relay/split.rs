@@ -0,0 +1,202 @@
+// Copyright (c) 2022-2023 Yuki Kishimoto
+// Copyright (c) 2023-2024 Rust Nostr Developers
+// Distributed under the MIT software license
+
+//! Split subscriptions
+//!
+//! Subscriptions with more filters than the relay `max_filters` limit are split into many `REQ`,
+//! aggregated under the logical subscription ID.
+
+use std::collections::{HashMap, HashSet};
+
+use nostr::{Filter, SubscriptionId};
+
+/// Max length of the subscription IDs accepted by most relays
+const MAX_SUBSCRIPTION_ID_LEN: usize = 64;
+
+#[derive(Debug, Clone, Default)]
+struct SplitSubscription {
+    children: Vec<SubscriptionId>,
+    eose: HashSet<SubscriptionId>,
+    /// Aggregated EOSE already emitted
+    done: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(super) struct SplitSubscriptions {
+    splits: HashMap<SubscriptionId, SplitSubscription>,
+    /// Child ID -> logical ID
+    parents: HashMap<SubscriptionId, SubscriptionId>,
+}
+
+impl SplitSubscriptions {
+    /// Split filters in chunks of `max_filters`
+    ///
+    /// Return the `(ID, filters)` pair of every `REQ` to send.
+    /// Any previous split of the same subscription is replaced.
+    pub fn split(
+        &mut self,
+        id: SubscriptionId,
+        filters: Vec<Filter>,
+        max_filters: Option<u16>,
+    ) -> Vec<(SubscriptionId, Vec<Filter>)> {
+        self.remove(&id);
+
+        let max_filters: usize = match max_filters {
+            Some(max) if filters.len() > max as usize => (max as usize).max(1),
+            _ => return vec![(id, filters)],
+        };
+
+        // Child IDs are `<id>:<index>`, using a random prefix if the ID is too long
+        let chunks = filters.chunks(max_filters);
+        let suffix_len: usize = chunks.len().to_string().len() + 1;
+        let mut prefix: String = id.to_string();
+        if prefix.len() + suffix_len > MAX_SUBSCRIPTION_ID_LEN {
+            prefix = SubscriptionId::generate().to_string();
+        }
+
+        let reqs: Vec<(SubscriptionId, Vec<Filter>)> = chunks
+            .enumerate()
+            .map(|(i, chunk)| (SubscriptionId::new(format!("{prefix}:{i}")), chunk.to_vec()))
+            .collect();
+
+        let children: Vec<SubscriptionId> = reqs.iter().map(|(child, ..)| child.clone()).collect();
+        for child in children.iter() {
+            self.parents.insert(child.clone(), id.clone());
+        }
+        self.splits.insert(
+            id,
+            SplitSubscription {
+                children,
+                ..Default::default()
+            },
+        );
+
+        reqs
+    }
+
+    /// Get the logical subscription ID
+    pub fn logical_id(&self, id: SubscriptionId) -> SubscriptionId {
+        match self.parents.get(&id) {
+            Some(parent) => parent.clone(),
+            None => id,
+        }
+    }
+
+    /// Handle `EOSE`
+    ///
+    /// Return the logical subscription ID only when every `REQ` sent `EOSE`.
+    pub fn eose(&mut self, id: SubscriptionId) -> Option<SubscriptionId> {
+        let parent: SubscriptionId = match self.parents.get(&id) {
+            Some(parent) => parent.clone(),
+            None => return Some(id),
+        };
+
+        let split: &mut SplitSubscription = self.splits.get_mut(&parent)?;
+        split.eose.insert(id);
+
+        if !split.done && split.eose.len() >= split.children.len() {
+            split.done = true;
+            Some(parent)
+        } else {
+            None
+        }
+    }
+
+    /// Get the IDs of the `REQ` sent for the subscription
+    pub fn reqs(&self, id: &SubscriptionId) -> Vec<SubscriptionId> {
+        match self.splits.get(id) {
+            Some(split) => split.children.clone(),
+            None => vec![id.clone()],
+        }
+    }
+
+    /// Remove split subscription
+    ///
+    /// Return the IDs to close.
+    pub fn remove(&mut self, id: &SubscriptionId) -> Vec<SubscriptionId> {
+        match self.splits.remove(id) {
+            Some(split) => {
+                for child in split.children.iter() {
+                    self.parents.remove(child);
+                }
+                split.children
+            }
+            None => vec![id.clone()],
+        }
+    }
+
+    /// Number of `REQ` needed to send the filters
+    pub fn reqs_count(filters: usize, max_filters: Option<u16>) -> usize {
+        match max_filters {
+            Some(max) if filters > max as usize => {
+                let max: usize = (max as usize).max(1);
+                (filters + max - 1) / max
+            }
+            _ => 1,
+        }
+    }
+
+    /// Number of extra `REQ` sent to the relay, because of the splits
+    pub fn extra_reqs(&self, exclude: &SubscriptionId) -> usize {
+        self.splits
+            .iter()
+            .filter(|(id, ..)| *id != exclude)
+            .map(|(.., split)| split.children.len().saturating_sub(1))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_subscriptions() {
+        let mut splits = SplitSubscriptions::default();
+        let id = SubscriptionId::new("feed");
+        let filters: Vec<Filter> = (0..5).map(|i| Filter::new().limit(i)).collect();
+
+        // Not exceeding the limit
+        let reqs = splits.split(id.clone(), filters[..2].to_vec(), Some(2));
+        assert_eq!(reqs, vec![(id.clone(), filters[..2].to_vec())]);
+
+        // 5 filters, max 2 per REQ
+        let reqs = splits.split(id.clone(), filters.clone(), Some(2));
+        assert_eq!(reqs.len(), 3);
+        assert_eq!(reqs[2].1, vec![filters[4].clone()]);
+        assert_eq!(splits.extra_reqs(&SubscriptionId::new("other")), 2);
+
+        let children: Vec<SubscriptionId> = reqs.into_iter().map(|(child, ..)| child).collect();
+        assert_eq!(splits.logical_id(children[1].clone()), id);
+
+        // Aggregated EOSE
+        assert_eq!(splits.eose(children[0].clone()), None);
+        assert_eq!(splits.eose(children[1].clone()), None);
+        assert_eq!(splits.eose(children[2].clone()), Some(id.clone()));
+        assert_eq!(splits.eose(children[2].clone()), None);
+
+        // Close
+        assert_eq!(splits.remove(&id), children);
+        assert_eq!(splits.logical_id(children[0].clone()), children[0]);
+        assert_eq!(splits.remove(&id), vec![id]);
+
+        assert_eq!(SplitSubscriptions::reqs_count(5, Some(2)), 3);
+        assert_eq!(SplitSubscriptions::reqs_count(2, Some(2)), 1);
+        assert_eq!(SplitSubscriptions::reqs_count(5, None), 1);
+    }
+
+    #[test]
+    fn test_split_long_subscription_id() {
+        let mut splits = SplitSubscriptions::default();
+        let id = SubscriptionId::new("a".repeat(MAX_SUBSCRIPTION_ID_LEN));
+        let filters: Vec<Filter> = (0..12).map(|i| Filter::new().limit(i)).collect();
+
+        let reqs = splits.split(id.clone(), filters, Some(1));
+        assert_eq!(reqs.len(), 12);
+        for (child, ..) in reqs.into_iter() {
+            assert!(child.to_string().len() <= MAX_SUBSCRIPTION_ID_LEN);
+            assert_eq!(splits.logical_id(child), id);
+        }
+    }
+}
@@ -4,21 +4,24 @@
 
 //! Relay Stats
 
-#[cfg(not(target_arch = "wasm32"))]
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 #[cfg(not(target_arch = "wasm32"))]
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 #[cfg(not(target_arch = "wasm32"))]
-use std::time::{Duration, Instant};
+use std::time::Instant;
 
-use nostr::Timestamp;
-#[cfg(not(target_arch = "wasm32"))]
+use nostr::{Kind, Timestamp};
+#[cfg(feature = "serde")]
+use serde::Serialize;
 use tokio::sync::RwLock;
 
 #[cfg(not(target_arch = "wasm32"))]
 use super::constants::LATENCY_MAX_VALUES;
+use super::constants::THROUGHPUT_MAX_WINDOW;
+use super::RelayStatus;
 
 /// Ping Stats
 #[cfg(not(target_arch = "wasm32"))]
@@ -81,15 +84,89 @@ impl PingStats {
     }
 }
 
+/// Number of received events, bucketed per second
+#[derive(Debug, Clone, Default)]
+struct ThroughputCounter {
+    /// `(unix timestamp, events)`, oldest first
+    buckets: VecDeque<(u64, u64)>,
+}
+
+impl ThroughputCounter {
+    fn record(&mut self, now: u64) {
+        match self.buckets.back_mut() {
+            Some((second, count)) if *second == now => *count += 1,
+            _ => self.buckets.push_back((now, 1)),
+        }
+
+        // Remove buckets out of the max window
+        let oldest: u64 = now.saturating_sub(THROUGHPUT_MAX_WINDOW.as_secs());
+        while let Some((second, ..)) = self.buckets.front() {
+            if *second > oldest {
+                break;
+            }
+            self.buckets.pop_front();
+        }
+    }
+
+    fn events_per_second(&self, now: u64, window: Duration) -> f64 {
+        let window: u64 = window.as_secs().clamp(1, THROUGHPUT_MAX_WINDOW.as_secs());
+        let since: u64 = now.saturating_sub(window);
+        let events: u64 = self
+            .buckets
+            .iter()
+            .filter(|(second, ..)| *second > since)
+            .map(|(.., count)| count)
+            .sum();
+        events as f64 / window as f64
+    }
+}
+
+/// Reconnection retry state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryState {
+    /// Number of failed connection attempts since the last successful connection (or forced reconnection)
+    pub attempts: u32,
+    /// When the next reconnection attempt is scheduled (`None` if connected or not scheduled yet)
+    pub next_retry_at: Option<Timestamp>,
+    /// Interval used by the reconnection loop
+    pub current_interval: Duration,
+}
+
+/// Snapshot of the relay stats
+///
+/// Returned by [`Relay::stats_snapshot`](super::Relay::stats_snapshot).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct RelayStats {
+    /// Connection status
+    pub status: RelayStatus,
+    /// Average latency (always `None` for WASM targets)
+    pub latency: Option<Duration>,
+    /// Bytes sent
+    pub bytes_sent: usize,
+    /// Bytes received
+    pub bytes_received: usize,
+    /// Number of active subscriptions
+    pub subscriptions: usize,
+    /// Number of reconnections (successful connections after the first one)
+    pub reconnections: usize,
+}
+
 /// Relay connection stats
 #[derive(Debug, Clone)]
 pub struct RelayConnectionStats {
     attempts: Arc<AtomicUsize>,
     success: Arc<AtomicUsize>,
+    /// Failed attempts considered by the retry backoff
+    retry_attempts: Arc<AtomicUsize>,
     bytes_sent: Arc<AtomicUsize>,
     bytes_received: Arc<AtomicUsize>,
     connected_at: Arc<AtomicU64>,
     first_connection_timestamp: Arc<AtomicU64>,
+    retry_interval: Arc<AtomicU64>,
+    next_retry_at: Arc<AtomicU64>,
+    throughput: Arc<RwLock<ThroughputCounter>>,
+    kinds: Arc<RwLock<HashMap<Kind, u64>>>,
     #[cfg(not(target_arch = "wasm32"))]
     latencies: Arc<RwLock<VecDeque<Duration>>>,
     #[cfg(not(target_arch = "wasm32"))]
@@ -108,10 +185,15 @@ impl RelayConnectionStats {
         Self {
             attempts: Arc::new(AtomicUsize::new(0)),
             success: Arc::new(AtomicUsize::new(0)),
+            retry_attempts: Arc::new(AtomicUsize::new(0)),
             bytes_sent: Arc::new(AtomicUsize::new(0)),
             bytes_received: Arc::new(AtomicUsize::new(0)),
             connected_at: Arc::new(AtomicU64::new(0)),
             first_connection_timestamp: Arc::new(AtomicU64::new(0)),
+            retry_interval: Arc::new(AtomicU64::new(0)),
+            next_retry_at: Arc::new(AtomicU64::new(0)),
+            throughput: Arc::new(RwLock::new(ThroughputCounter::default())),
+            kinds: Arc::new(RwLock::new(HashMap::new())),
             #[cfg(not(target_arch = "wasm32"))]
             latencies: Arc::new(RwLock::new(VecDeque::new())),
             #[cfg(not(target_arch = "wasm32"))]
@@ -160,6 +242,38 @@ impl RelayConnectionStats {
         Timestamp::from(self.first_connection_timestamp.load(Ordering::SeqCst))
     }
 
+    /// Get reconnection retry state
+    pub fn retry_state(&self) -> RetryState {
+        let attempts: usize = self.retry_attempts();
+        let next_retry_at: u64 = self.next_retry_at.load(Ordering::SeqCst);
+        RetryState {
+            attempts: u32::try_from(attempts).unwrap_or(u32::MAX),
+            next_retry_at: if next_retry_at > 0 {
+                Some(Timestamp::from(next_retry_at))
+            } else {
+                None
+            },
+            current_interval: Duration::from_secs(self.retry_interval.load(Ordering::SeqCst)),
+        }
+    }
+
+    /// Average number of events per second received in the last `window` (max 10 min)
+    pub async fn events_per_second(&self, window: Duration) -> f64 {
+        let throughput = self.throughput.read().await;
+        throughput.events_per_second(Timestamp::now().as_u64(), window)
+    }
+
+    /// Number of received events per kind
+    pub async fn kind_histogram(&self) -> HashMap<Kind, u64> {
+        self.kinds.read().await.clone()
+    }
+
+    /// Reset the received events per kind
+    pub async fn reset_kind_histogram(&self) {
+        let mut kinds = self.kinds.write().await;
+        kinds.clear();
+    }
+
     /// Calculate latency
     #[cfg(not(target_arch = "wasm32"))]
     pub async fn latency(&self) -> Option<Duration> {
@@ -177,10 +291,12 @@ impl RelayConnectionStats {
 
     pub(crate) fn new_attempt(&self) {
         self.attempts.fetch_add(1, Ordering::SeqCst);
+        self.retry_attempts.fetch_add(1, Ordering::SeqCst);
     }
 
     pub(crate) fn new_success(&self) {
         self.success.fetch_add(1, Ordering::SeqCst);
+        self.retry_attempts.store(0, Ordering::SeqCst);
 
         let now: u64 = Timestamp::now().as_u64();
 
@@ -189,6 +305,35 @@ impl RelayConnectionStats {
         if self.first_connection_timestamp() == Timestamp::from(0) {
             self.first_connection_timestamp.store(now, Ordering::SeqCst);
         }
+
+        self.next_retry_at.store(0, Ordering::SeqCst);
+    }
+
+    /// Failed attempts considered by the retry backoff
+    pub(crate) fn retry_attempts(&self) -> usize {
+        self.retry_attempts.load(Ordering::SeqCst)
+    }
+
+    /// Reset retry state: failed attempts are forgotten by the backoff (`attempts` and `success` are kept)
+    pub(crate) fn reset_retry(&self) {
+        self.retry_attempts.store(0, Ordering::SeqCst);
+        self.next_retry_at.store(0, Ordering::SeqCst);
+    }
+
+    pub(crate) fn save_retry(&self, interval: u64, next_retry_at: Option<Timestamp>) {
+        self.retry_interval.store(interval, Ordering::SeqCst);
+        self.next_retry_at.store(
+            next_retry_at.map(|t| t.as_u64()).unwrap_or_default(),
+            Ordering::SeqCst,
+        );
+    }
+
+    pub(crate) async fn new_event(&self, kind: Kind) {
+        let mut throughput = self.throughput.write().await;
+        throughput.record(Timestamp::now().as_u64());
+
+        let mut kinds = self.kinds.write().await;
+        *kinds.entry(kind).or_default() += 1;
     }
 
     pub(crate) fn add_bytes_sent(&self, size: usize) {
@@ -210,3 +355,98 @@ impl RelayConnectionStats {
         latencies.push_front(latency)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_state() {
+        let stats = RelayConnectionStats::new();
+        assert_eq!(stats.retry_state().attempts, 0);
+        assert_eq!(stats.retry_state().next_retry_at, None);
+
+        // Simulate failures
+        for _ in 0..3 {
+            stats.new_attempt();
+        }
+        let next_retry_at = Timestamp::from(1_000);
+        stats.save_retry(20, Some(next_retry_at));
+
+        let state: RetryState = stats.retry_state();
+        assert_eq!(state.attempts, 3);
+        assert_eq!(state.next_retry_at, Some(next_retry_at));
+        assert_eq!(state.current_interval, Duration::from_secs(20));
+
+        // Connection established
+        stats.new_attempt();
+        stats.new_success();
+
+        let state: RetryState = stats.retry_state();
+        assert_eq!(state.attempts, 0);
+        assert_eq!(state.next_retry_at, None);
+
+        // Forced reconnection
+        stats.new_attempt();
+        stats.save_retry(20, Some(next_retry_at));
+        stats.reset_retry();
+
+        let state: RetryState = stats.retry_state();
+        assert_eq!(state.attempts, 0);
+        assert_eq!(state.next_retry_at, None);
+
+        // Connection stats untouched
+        assert_eq!(stats.attempts(), 5);
+        assert_eq!(stats.success(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_kind_histogram() {
+        let stats = RelayConnectionStats::new();
+
+        for kind in [
+            Kind::TextNote,
+            Kind::Metadata,
+            Kind::TextNote,
+            Kind::Reaction,
+        ] {
+            stats.new_event(kind).await;
+        }
+
+        let histogram: HashMap<Kind, u64> = stats.kind_histogram().await;
+        assert_eq!(histogram.get(&Kind::TextNote), Some(&2));
+        assert_eq!(histogram.get(&Kind::Metadata), Some(&1));
+        assert_eq!(histogram.get(&Kind::Reaction), Some(&1));
+        assert_eq!(histogram.get(&Kind::EncryptedDirectMessage), None);
+
+        stats.reset_kind_histogram().await;
+        assert!(stats.kind_histogram().await.is_empty());
+    }
+
+    #[test]
+    fn test_throughput() {
+        let mut fast = ThroughputCounter::default();
+        let mut slow = ThroughputCounter::default();
+        let window = Duration::from_secs(10);
+
+        // 10 events/sec vs 1 event every 5 secs
+        for second in 1_000..1_010 {
+            for _ in 0..10 {
+                fast.record(second);
+            }
+            if second % 5 == 0 {
+                slow.record(second);
+            }
+        }
+
+        assert_eq!(fast.events_per_second(1_009, window), 10.0);
+        assert_eq!(slow.events_per_second(1_009, window), 0.2);
+
+        // Outside of the window
+        assert_eq!(fast.events_per_second(1_100, window), 0.0);
+
+        // Old buckets are removed
+        fast.record(1_000 + THROUGHPUT_MAX_WINDOW.as_secs() + 20);
+        assert_eq!(fast.buckets.len(), 1);
+    }
+}
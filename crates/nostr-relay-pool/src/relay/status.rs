@@ -6,8 +6,12 @@
 
 use core::fmt;
 
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
 /// Relay connection status
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum RelayStatus {
     /// Relay initialized
     Initialized,
@@ -19,6 +23,8 @@ pub enum RelayStatus {
     Connected,
     /// Relay disconnected, will retry to connect again
     Disconnected,
+    /// Relay disconnected for inactivity, will reconnect on the next message
+    Idle,
     /// Relay completely disconnected
     Terminated,
 }
@@ -31,14 +37,15 @@ impl fmt::Display for RelayStatus {
             Self::Connecting => write!(f, "Connecting"),
             Self::Connected => write!(f, "Connected"),
             Self::Disconnected => write!(f, "Disconnected"),
+            Self::Idle => write!(f, "Idle"),
             Self::Terminated => write!(f, "Terminated"),
         }
     }
 }
 
 impl RelayStatus {
-    /// Check if is `disconnected` or `terminated`
+    /// Check if is `disconnected`, `idle` or `terminated`
     pub(crate) fn is_disconnected(&self) -> bool {
-        matches!(self, Self::Disconnected | Self::Terminated)
+        matches!(self, Self::Disconnected | Self::Idle | Self::Terminated)
     }
 }
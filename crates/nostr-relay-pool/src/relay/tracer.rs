@@ -0,0 +1,51 @@
+// Copyright (c) 2022-2023 Yuki Kishimoto
+// Copyright (c) 2023-2024 Rust Nostr Developers
+// Distributed under the MIT software license
+
+//! Message tracer
+
+use std::fmt;
+use std::sync::Arc;
+
+use nostr::Url;
+
+type TracerFn = dyn Fn(&Url, MessageDirection, &str) + Send + Sync;
+
+/// Direction of a relay message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageDirection {
+    /// Message received from relay
+    Incoming,
+    /// Message sent to relay
+    Outgoing,
+}
+
+/// Raw relay message tracer
+#[derive(Clone)]
+pub struct MessageTracer {
+    func: Arc<TracerFn>,
+}
+
+impl fmt::Debug for MessageTracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MessageTracer").finish()
+    }
+}
+
+impl MessageTracer {
+    /// New message tracer
+    pub fn new<F>(func: F) -> Self
+    where
+        F: Fn(&Url, MessageDirection, &str) + Send + Sync + 'static,
+    {
+        Self {
+            func: Arc::new(func),
+        }
+    }
+
+    /// Trace message
+    #[inline]
+    pub fn trace(&self, url: &Url, direction: MessageDirection, msg: &str) {
+        (self.func)(url, direction, msg)
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright (c) 2022-2023 Yuki Kishimoto
+// Copyright (c) 2023-2024 Rust Nostr Developers
+// Distributed under the MIT software license
+
+//! Event validator
+
+use std::fmt;
+use std::sync::Arc;
+
+use nostr::Event;
+
+type ValidatorFn = dyn Fn(&Event) -> Result<(), String> + Send + Sync;
+
+/// Custom event validator
+///
+/// Return `Err` with the reason to reject the event.
+#[derive(Clone)]
+pub struct EventValidator {
+    func: Arc<ValidatorFn>,
+}
+
+impl fmt::Debug for EventValidator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventValidator").finish()
+    }
+}
+
+impl EventValidator {
+    /// New event validator
+    pub fn new<F>(func: F) -> Self
+    where
+        F: Fn(&Event) -> Result<(), String> + Send + Sync + 'static,
+    {
+        Self {
+            func: Arc::new(func),
+        }
+    }
+
+    /// Validate event
+    #[inline]
+    pub fn validate(&self, event: &Event) -> Result<(), String> {
+        (self.func)(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr::{EventBuilder, Keys, Timestamp};
+
+    use super::*;
+
+    #[test]
+    fn test_validator() {
+        let min: Timestamp = Timestamp::from(1_000);
+        let validator = EventValidator::new(move |event| {
+            if event.created_at() < min {
+                return Err(String::from("too old"));
+            }
+            Ok(())
+        });
+
+        let keys = Keys::generate();
+        let old = EventBuilder::text_note("old", [])
+            .custom_created_at(Timestamp::from(10))
+            .to_event(&keys)
+            .unwrap();
+        let valid = EventBuilder::text_note("valid", [])
+            .to_event(&keys)
+            .unwrap();
+
+        assert_eq!(validator.validate(&old), Err(String::from("too old")));
+        assert!(validator.validate(&valid).is_ok());
+    }
+}
@@ -0,0 +1,65 @@
+// Copyright (c) 2022-2023 Yuki Kishimoto
+// Copyright (c) 2023-2024 Rust Nostr Developers
+// Distributed under the MIT software license
+
+//! Debounce
+
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_utility::thread;
+
+/// Run only the last of the calls made within the delay
+#[derive(Debug, Clone, Default)]
+pub(super) struct Debouncer {
+    generation: Arc<AtomicU64>,
+}
+
+impl Debouncer {
+    /// Schedule the future after `delay`, discarding the ones scheduled before and not run yet
+    pub fn call<Fut>(&self, delay: Duration, fut: Fut)
+    where
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let generation: u64 = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let current: Arc<AtomicU64> = self.generation.clone();
+        let _ = thread::spawn(async move {
+            thread::sleep(delay).await;
+
+            // Run only if no other call was made meanwhile
+            if current.load(Ordering::SeqCst) == generation {
+                fut.await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_debouncer() {
+        let debouncer = Debouncer::default();
+        let calls: Arc<AtomicUsize> = Arc::new(AtomicUsize::new(0));
+        let delay = Duration::from_millis(100);
+
+        // Bulk calls
+        for _ in 0..3 {
+            let calls = calls.clone();
+            debouncer.call(delay, async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        // Not run before the debounce window
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        thread::sleep(delay * 3).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -6,6 +6,7 @@ use async_utility::thread;
 use atomic_destructor::StealthClone;
 use nostr::RelayMessage;
 use nostr_relay_pool::RelayPoolNotification;
+use tokio::sync::broadcast::error::RecvError;
 
 use super::Client;
 
@@ -19,7 +20,18 @@ impl Client {
             tracing::info!("Spawned client notification handler");
 
             let mut notifications = client.pool.notifications();
-            while let Ok(notification) = notifications.recv().await {
+            loop {
+                let notification: RelayPoolNotification = match notifications.recv().await {
+                    Ok(notification) => notification,
+                    Err(RecvError::Lagged(count)) => {
+                        tracing::warn!(
+                            "Client notification handler lagged: {count} notifications dropped"
+                        );
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+
                 match notification {
                     RelayPoolNotification::Message { relay_url, message } => {
                         // Check if auto authentication (NIP42) is enabled
@@ -4,11 +4,15 @@
 
 //! Client
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
+#[cfg(feature = "nip05")]
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 
+use async_utility::futures_util::future;
+use async_utility::time;
 use atomic_destructor::StealthClone;
 use nostr::prelude::*;
 use nostr_database::DynNostrDatabase;
@@ -17,21 +21,29 @@ use nostr_signer::prelude::*;
 #[cfg(feature = "nip57")]
 use nostr_zapper::{DynNostrZapper, IntoNostrZapper, ZapperError};
 use thiserror::Error;
-use tokio::sync::{broadcast, RwLock};
+use tokio::sync::{broadcast, mpsc, RwLock};
 
 pub mod builder;
+mod debounce;
 mod handler;
 pub mod options;
+mod pagination;
+mod retry;
 #[cfg(feature = "nip57")]
 mod zapper;
 
 pub use self::builder::ClientBuilder;
-pub use self::options::Options;
+use self::debounce::Debouncer;
+pub use self::options::{Options, RetryPolicy, SendEventOptions};
 #[cfg(not(target_arch = "wasm32"))]
 pub use self::options::{Proxy, ProxyTarget};
+pub use self::pagination::EventsPaginator;
+pub use self::retry::{SendEventOutput, SendStatus};
 #[cfg(feature = "nip57")]
 pub use self::zapper::{ZapDetails, ZapEntity};
 
+const RELAY_LIST_DEBOUNCE: Duration = Duration::from_secs(2);
+
 /// [`Client`] error
 #[derive(Debug, Error)]
 pub enum Error {
@@ -75,9 +87,16 @@ pub enum Error {
     /// Impossible to zap
     #[error("impossible to send zap: {0}")]
     ImpossibleToZap(String),
+    /// NIP05 error
+    #[cfg(feature = "nip05")]
+    #[error(transparent)]
+    NIP05(#[from] nip05::Error),
     /// Metadata not found
     #[error("metadata not found")]
     MetadataNotFound,
+    /// Timeout
+    #[error("timeout")]
+    Timeout,
 }
 
 /// Nostr client
@@ -87,6 +106,7 @@ pub struct Client {
     signer: Arc<RwLock<Option<NostrSigner>>>,
     #[cfg(feature = "nip57")]
     zapper: Arc<RwLock<Option<Arc<DynNostrZapper>>>>,
+    relay_list_debouncer: Debouncer,
     opts: Options,
 }
 
@@ -104,6 +124,7 @@ impl StealthClone for Client {
             signer: self.signer.clone(),
             #[cfg(feature = "nip57")]
             zapper: self.zapper.clone(),
+            relay_list_debouncer: self.relay_list_debouncer.clone(),
             opts: self.opts.clone(),
         }
     }
@@ -169,10 +190,11 @@ impl Client {
     /// Compose [`Client`] from [`ClientBuilder`]
     pub fn from_builder(builder: ClientBuilder) -> Self {
         let client = Self {
-            pool: RelayPool::with_database(builder.opts.pool, builder.database),
+            pool: RelayPool::with_database(builder.opts.pool.clone(), builder.database),
             signer: Arc::new(RwLock::new(builder.signer)),
             #[cfg(feature = "nip57")]
             zapper: Arc::new(RwLock::new(builder.zapper)),
+            relay_list_debouncer: Debouncer::default(),
             opts: builder.opts,
         };
 
@@ -262,6 +284,19 @@ impl Client {
         self.pool.database()
     }
 
+    /// Get the relays that delivered an event
+    ///
+    /// All the source relays are recorded by the database, also for the duplicated events.
+    /// The number of tracked events is bounded by the database (i.e. for [`MemoryDatabase`] see [`MemoryDatabaseOptions::max_events`]).
+    pub async fn event_sources(&self, id: &EventId) -> Result<HashSet<Url>, Error> {
+        let database = self.database();
+        let sources: Option<HashSet<Url>> = database
+            .event_seen_on_relays(*id)
+            .await
+            .map_err(pool::Error::from)?;
+        Ok(sources.unwrap_or_default())
+    }
+
     /// Get blacklist
     #[inline]
     pub fn blacklist(&self) -> RelayBlacklist {
@@ -342,6 +377,17 @@ impl Client {
         self.pool.notifications()
     }
 
+    /// Get new notification listener, receiving only the notifications matching the `predicate`
+    ///
+    /// Check [`RelayPool::subscribe_filtered`] to learn more.
+    #[inline]
+    pub async fn subscribe_filtered<F>(&self, predicate: F) -> mpsc::Receiver<RelayPoolNotification>
+    where
+        F: Fn(&RelayPoolNotification) -> bool + Send + Sync + 'static,
+    {
+        self.pool.subscribe_filtered(predicate).await
+    }
+
     /// Get relays
     #[inline]
     pub async fn relays(&self) -> HashMap<Url, Relay> {
@@ -358,6 +404,15 @@ impl Client {
         Ok(self.pool.relay(url).await?)
     }
 
+    /// Get the stats of all relays
+    ///
+    /// Snapshot of status, latency, traffic, active subscriptions and reconnections of every relay.
+    /// Serializable with the `serde` feature.
+    #[inline]
+    pub async fn stats(&self) -> HashMap<Url, RelayStats> {
+        self.pool.stats_snapshot().await
+    }
+
     /// Add new relay
     ///
     /// Return `false` if the relay already exists.
@@ -390,6 +445,14 @@ impl Client {
         pool::Error: From<<U as TryIntoUrl>::Err>,
     {
         let url: Url = url.try_into_url().map_err(pool::Error::from)?;
+        let opts: RelayOptions = self.relay_opts(&url);
+
+        // Add relay
+        self.add_relay_with_opts::<Url>(url, opts).await
+    }
+
+    /// Compose the [`RelayOptions`] from the [`Options`]
+    fn relay_opts(&self, url: &Url) -> RelayOptions {
         let opts: RelayOptions = RelayOptions::new();
 
         // Set proxy
@@ -411,12 +474,8 @@ impl Client {
         };
 
         // Set min POW difficulty and limits
-        let opts: RelayOptions = opts
-            .pow(self.opts.get_min_pow_difficulty())
-            .limits(self.opts.relay_limits.clone());
-
-        // Add relay
-        self.add_relay_with_opts::<Url>(url, opts).await
+        opts.pow(self.opts.get_min_pow_difficulty())
+            .limits(self.opts.relay_limits.clone())
     }
 
     /// Add new relay with custom [`RelayOptions`]
@@ -456,7 +515,11 @@ impl Client {
         U: TryIntoUrl,
         pool::Error: From<<U as TryIntoUrl>::Err>,
     {
-        Ok(self.pool.add_relay(url, opts).await?)
+        let added: bool = self.pool.add_relay(url, opts).await?;
+        if added {
+            self.relays_changed();
+        }
+        Ok(added)
     }
 
     /// Add multiple relays
@@ -480,6 +543,8 @@ impl Client {
 
     /// Disconnect and remove relay
     ///
+    /// Do nothing if the relay doesn't exist.
+    ///
     /// # Example
     /// ```rust,no_run
     /// use nostr_sdk::prelude::*;
@@ -491,19 +556,86 @@ impl Client {
     /// client.remove_relay("wss://relay.nostr.info").await.unwrap();
     /// # }
     /// ```
-    #[inline]
     pub async fn remove_relay<U>(&self, url: U) -> Result<(), Error>
     where
         U: TryIntoUrl,
         pool::Error: From<<U as TryIntoUrl>::Err>,
     {
-        Ok(self.pool.remove_relay(url).await?)
+        let url: Url = url.try_into_url().map_err(pool::Error::from)?;
+        let exists: bool = self.pool.relay(&url).await.is_ok();
+        self.pool.remove_relay(url).await?;
+        if exists {
+            self.relays_changed();
+        }
+        Ok(())
+    }
+
+    /// Remove relay, letting the active subscriptions receive the `EOSE` first
+    ///
+    /// Wait at most `timeout`, then force-close the connection.
+    /// Return `true` if the drain completed, `false` if timed out (see [`RelayPool::remove_relay_graceful`]).
+    pub async fn remove_relay_graceful<U>(&self, url: U, timeout: Duration) -> Result<bool, Error>
+    where
+        U: TryIntoUrl,
+        pool::Error: From<<U as TryIntoUrl>::Err>,
+    {
+        let drained: bool = self.pool.remove_relay_graceful(url, timeout).await?;
+        self.relays_changed();
+        Ok(drained)
     }
 
     /// Disconnect and remove all relays
     #[inline]
     pub async fn remove_all_relays(&self) -> Result<(), Error> {
-        Ok(self.pool.remove_all_relays().await?)
+        let has_relays: bool = !self.pool.relays().await.is_empty();
+        self.pool.remove_all_relays().await?;
+        if has_relays {
+            self.relays_changed();
+        }
+        Ok(())
+    }
+
+    /// Schedule the relay list publishing, if [`Options::auto_publish_relay_list`] is enabled
+    ///
+    /// Call it only for the relay changes made by the user: the relays added internally
+    /// (i.e. the hinted ones) must never end up in the relay list.
+    fn relays_changed(&self) {
+        if !self.opts.auto_publish_relay_list {
+            return;
+        }
+
+        // Stealth clone the client (not increment atomic destructor counter)
+        let client: Client = self.stealth_clone();
+        self.relay_list_debouncer
+            .call(RELAY_LIST_DEBOUNCE, async move {
+                if let Err(e) = client.publish_relay_list().await {
+                    tracing::error!("Impossible to publish relay list: {e}");
+                }
+            });
+    }
+
+    /// Publish the relay list composed from the `READ` and `WRITE` relays of the pool
+    async fn publish_relay_list(&self) -> Result<(), Error> {
+        let has_signer: bool = self.signer.read().await.is_some();
+        if !has_signer {
+            tracing::debug!("Signer not configured: skipping relay list publishing");
+            return Ok(());
+        }
+
+        let relays: HashMap<Url, Relay> = self.pool.relays().await;
+        let list = relays.into_iter().filter_map(|(url, relay)| {
+            let flags = relay.flags();
+            match (flags.has_read(), flags.has_write()) {
+                (true, true) => Some((url, None)),
+                (true, false) => Some((url, Some(RelayMetadata::Read))),
+                (false, true) => Some((url, Some(RelayMetadata::Write))),
+                (false, false) => None,
+            }
+        });
+
+        self.set_relay_list(list).await?;
+
+        Ok(())
     }
 
     /// Connect to a previously added relay
@@ -666,6 +798,36 @@ impl Client {
         Ok(self.pool.subscribe(filters, opts).await?)
     }
 
+    /// Subscribe to filters to all connected relays, returning a [`SubscriptionHandle`] to cancel it
+    ///
+    /// Same as [`Client::subscribe`], but the subscription can be closed with [`SubscriptionHandle::cancel`].
+    pub async fn subscribe_cancellable(
+        &self,
+        filters: Vec<Filter>,
+        opts: Option<SubscribeAutoCloseOptions>,
+    ) -> Result<Output<SubscriptionHandle>, Error> {
+        let send_opts: RelaySendOptions = self.opts.get_wait_for_subscription();
+        let opts: SubscribeOptions = SubscribeOptions::default()
+            .close_on(opts)
+            .send_opts(send_opts);
+        Ok(self.pool.subscribe_cancellable(filters, opts).await?)
+    }
+
+    /// Subscribe to filters to all connected relays, returning a [`SubscriptionStream`] of the received events
+    ///
+    /// The subscription is closed when the stream is dropped or cancelled with [`SubscriptionStream::handle`].
+    pub async fn subscribe_stream(
+        &self,
+        filters: Vec<Filter>,
+        opts: Option<SubscribeAutoCloseOptions>,
+    ) -> Result<Output<SubscriptionStream>, Error> {
+        let send_opts: RelaySendOptions = self.opts.get_wait_for_subscription();
+        let opts: SubscribeOptions = SubscribeOptions::default()
+            .close_on(opts)
+            .send_opts(send_opts);
+        Ok(self.pool.subscribe_stream(filters, opts).await?)
+    }
+
     /// Subscribe to filters with custom [SubscriptionId] to all connected relays
     ///
     /// # Auto-closing subscription
@@ -771,6 +933,20 @@ impl Client {
             .await?)
     }
 
+    /// Update the filters of a subscription (i.e. to widen the `since`/`until` window of a feed)
+    ///
+    /// The subscription isn't closed: the same [`SubscriptionId`] is reused,
+    /// so the events of the new filters are received as part of the existing subscription.
+    #[inline]
+    pub async fn update_subscription(
+        &self,
+        id: SubscriptionId,
+        filters: Vec<Filter>,
+    ) -> Result<Output<()>, Error> {
+        let opts: RelaySendOptions = self.opts.get_wait_for_subscription();
+        Ok(self.pool.update_subscription(id, filters, opts).await?)
+    }
+
     /// Unsubscribe
     #[inline]
     pub async fn unsubscribe(&self, id: SubscriptionId) {
@@ -785,6 +961,21 @@ impl Client {
         self.pool.unsubscribe_all(opts).await;
     }
 
+    /// Wait for `EOSE` from all the relays that are participating in the subscription
+    ///
+    /// Return the relays that sent the `EOSE` (`success`) and the relays that didn't send it before the timeout (`failed`).
+    ///
+    /// If timeout is set to `None`, the default from [`Options`] will be used.
+    #[inline]
+    pub async fn wait_for_eose(
+        &self,
+        id: &SubscriptionId,
+        timeout: Option<Duration>,
+    ) -> Result<Output<()>, Error> {
+        let timeout: Duration = timeout.unwrap_or(self.opts.timeout);
+        Ok(self.pool.wait_for_eose(id, timeout).await?)
+    }
+
     /// Get events of filters
     ///
     /// If timeout is set to `None`, the default from [`Options`] will be used.
@@ -834,6 +1025,90 @@ impl Client {
         Ok(self.pool.get_events_of(filters, timeout, opts).await?)
     }
 
+    /// Fetch events of filters
+    ///
+    /// Like [`Client::get_events_of`], but return also the relays that replied (`success`) and the ones that failed
+    /// (i.e. to check if [`Output::all_failed`]).
+    ///
+    /// If timeout is set to `None`, the default from [`Options`] will be used.
+    #[inline]
+    pub async fn fetch_events(
+        &self,
+        filters: Vec<Filter>,
+        timeout: Option<Duration>,
+    ) -> Result<Output<Vec<Event>>, Error> {
+        let timeout: Duration = timeout.unwrap_or(self.opts.timeout);
+        Ok(self
+            .pool
+            .fetch_events(filters, timeout, FilterOptions::ExitOnEOSE)
+            .await?)
+    }
+
+    /// Fetch events of filter in pages
+    ///
+    /// Each page request at most `page_size` new events, moving the `until` to the oldest event of the previous page
+    /// (inclusive, the events are deduplicated by ID), until there are no new events.
+    /// The `limit` of the filter, if set, is the max number of events returned in total.
+    /// Use [`EventsPaginator::into_stream`] to get a stream of events.
+    /// If `until` is `None`, the `until` of the filter will be used.
+    ///
+    /// If timeout is set to `None`, the default from [`Options`] will be used (for each page).
+    ///
+    /// Archive relays are skipped, unless enabled with [`EventsPaginator::include_archive`].
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use nostr_sdk::prelude::*;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<()> {
+    /// #   let client = Client::default();
+    /// #   let public_key = Keys::generate().public_key();
+    /// let filter = Filter::new().author(public_key).kind(Kind::TextNote);
+    /// let mut paginator = client.fetch_events_paginated(filter, 500, None, None);
+    /// while let Some(events) = paginator.next_page().await? {
+    ///     println!("Received {} events", events.len());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn fetch_events_paginated(
+        &self,
+        filter: Filter,
+        page_size: u16,
+        until: Option<Timestamp>,
+        timeout: Option<Duration>,
+    ) -> EventsPaginator {
+        EventsPaginator::new(self.clone(), filter, page_size, until, timeout)
+    }
+
+    /// Count events of filter (NIP-45)
+    ///
+    /// Send `COUNT` only to the relays that advertise NIP-45 support in their NIP-11 document.
+    /// Use [`Output::total`] to get the aggregated count.
+    ///
+    /// If timeout is set to `None`, the default from [`Options`] will be used.
+    ///
+    /// <https://github.com/nostr-protocol/nips/blob/master/45.md>
+    #[inline]
+    pub async fn count_events(
+        &self,
+        filter: Filter,
+        timeout: Option<Duration>,
+    ) -> Result<Output<HashMap<Url, usize>>, Error> {
+        let timeout: Duration = timeout.unwrap_or(self.opts.timeout);
+        Ok(self.pool.count_events(vec![filter], timeout).await?)
+    }
+
+    /// Get the relay that delivered the newest version of a replaceable event
+    ///
+    /// Available only if [`RelayPoolOptions::sticky_relays`] is enabled.
+    #[inline]
+    pub async fn preferred_relay_for(&self, public_key: &PublicKey, kind: &Kind) -> Option<Url> {
+        self.pool.preferred_relay_for(public_key, kind).await
+    }
+
     /// Get events of filters from specific relays
     ///
     /// Get events both from **local database** and **relays**
@@ -856,6 +1131,314 @@ impl Client {
             .await?)
     }
 
+    /// Fetch events of filter from relays not added to the pool (i.e. to follow a `naddr` hint)
+    ///
+    /// Short-lived connections are established with the relays and closed at `EOSE`,
+    /// without affecting the relays of the pool (and their stats).
+    /// The relays that can't be reached within the `timeout` are skipped.
+    ///
+    /// The received events are saved into the database.
+    pub async fn fetch_events_ephemeral<I, U>(
+        &self,
+        urls: I,
+        filter: Filter,
+        timeout: Duration,
+    ) -> Result<Vec<Event>, Error>
+    where
+        I: IntoIterator<Item = U>,
+        U: TryIntoUrl,
+        pool::Error: From<<U as TryIntoUrl>::Err>,
+    {
+        let urls: HashSet<Url> = urls
+            .into_iter()
+            .map(|u| u.try_into_url())
+            .collect::<Result<_, _>>()
+            .map_err(pool::Error::from)?;
+
+        if urls.is_empty() {
+            return Err(Error::RelayPool(pool::Error::NoRelaysSpecified));
+        }
+
+        let results = future::join_all(urls.into_iter().map(|url| {
+            let opts: RelayOptions = self.relay_opts(&url).reconnect(false);
+            let relay: Relay = Relay::custom(url, self.database(), self.blacklist(), opts);
+            let filter: Filter = filter.clone();
+            async move {
+                // Connection and query share the same timeout
+                let events: Vec<Event> = time::timeout(Some(timeout), async {
+                    relay.connect(Some(timeout)).await;
+
+                    if !relay.is_connected().await {
+                        tracing::warn!("Impossible to connect to ephemeral relay {}", relay.url());
+                        return Vec::new();
+                    }
+
+                    match relay
+                        .get_events_of(vec![filter], timeout, FilterOptions::ExitOnEOSE)
+                        .await
+                    {
+                        Ok(events) => events,
+                        Err(e) => {
+                            tracing::warn!("Impossible to get events from {}: {e}", relay.url());
+                            Vec::new()
+                        }
+                    }
+                })
+                .await
+                .unwrap_or_default();
+
+                if let Err(e) = relay.disconnect().await {
+                    tracing::warn!("Impossible to disconnect from {}: {e}", relay.url());
+                }
+
+                events
+            }
+        }))
+        .await;
+
+        // Merge events
+        let mut ids: HashSet<EventId> = HashSet::new();
+        let mut events: Vec<Event> = results
+            .into_iter()
+            .flatten()
+            .filter(|event| ids.insert(event.id()))
+            .collect();
+        events.sort_by(|a, b| b.created_at().cmp(&a.created_at()));
+
+        Ok(events)
+    }
+
+    /// Fetch event by ID (i.e. to resolve a `nevent`)
+    ///
+    /// Query the hinted relays first and then fall back to the other read relays,
+    /// returning as soon as the event is received. The remaining subscriptions are closed.
+    ///
+    /// The hinted relays not already in the pool are added only for the duration of the fetch.
+    /// If there are relays to fall back to, the hinted relays are queried at most for half of the `timeout`.
+    ///
+    /// Return `None` if the event is not found before the `timeout`.
+    /// Use [`Client::fetch_event_by_nevent`] to also query the outbox relays of the author.
+    #[inline]
+    pub async fn fetch_event_by_id(
+        &self,
+        id: EventId,
+        hints: Vec<Url>,
+        timeout: Duration,
+    ) -> Result<Option<Event>, Error> {
+        self.fetch_event_with_hints(id, None, hints, timeout).await
+    }
+
+    /// Fetch the event of a `nevent`
+    ///
+    /// Like [`Client::fetch_event_by_id`], but, if the author is known, fall back to its outbox relays
+    /// (the `WRITE` relays of the relay list in the database) before the other read relays.
+    pub async fn fetch_event_by_nevent(
+        &self,
+        nevent: &Nip19Event,
+        timeout: Duration,
+    ) -> Result<Option<Event>, Error> {
+        let hints: Vec<Url> = nevent
+            .relays
+            .iter()
+            .filter_map(|url| Url::parse(url).ok())
+            .collect();
+        self.fetch_event_with_hints(nevent.event_id, nevent.author, hints, timeout)
+            .await
+    }
+
+    async fn fetch_event_with_hints(
+        &self,
+        id: EventId,
+        author: Option<PublicKey>,
+        hints: Vec<Url>,
+        timeout: Duration,
+    ) -> Result<Option<Event>, Error> {
+        // Check local database
+        if let Ok(event) = self.database().event_by_id(id).await {
+            return Ok(Some(event));
+        }
+
+        let relays: HashMap<Url, Relay> = self.pool.relays().await;
+        let hints: HashSet<Url> = hints.into_iter().collect();
+
+        // Outbox relays of the author
+        let outbox: HashSet<Url> = match author {
+            Some(author) => self
+                .outbox_relays(author)
+                .await
+                .into_iter()
+                .filter(|url| !hints.contains(url))
+                .collect(),
+            None => HashSet::new(),
+        };
+
+        // Select READ relays to fall back to
+        let fallback: HashSet<Url> = relays
+            .iter()
+            .filter(|(url, relay)| {
+                !hints.contains(*url) && !outbox.contains(*url) && relay.flags().has_read()
+            })
+            .map(|(url, ..)| url.clone())
+            .collect();
+
+        // Add the hinted and outbox relays not already in the pool
+        // Added directly to the pool, since they must not change the relay list of the user.
+        let mut added: Vec<Url> = Vec::new();
+        for url in hints.iter().chain(outbox.iter()) {
+            if relays.contains_key(url) {
+                continue;
+            }
+
+            match self.pool.add_relay(url.clone(), self.relay_opts(url)).await {
+                Ok(true) => {
+                    added.push(url.clone());
+                    if let Err(e) = self.pool.connect_relay(url.clone(), None).await {
+                        tracing::warn!("Impossible to connect to {url}: {e}");
+                    }
+                }
+                Ok(false) => (),
+                Err(e) => tracing::warn!("Impossible to add relay {url}: {e}"),
+            }
+        }
+
+        // Query the relays step by step: each step (except the last one) for half of the remaining time
+        let steps: Vec<HashSet<Url>> = [hints, outbox, fallback]
+            .into_iter()
+            .filter(|urls| !urls.is_empty())
+            .collect();
+        let len: usize = steps.len();
+        let mut remaining: Duration = timeout;
+        let mut event: Option<Event> = None;
+        for (i, urls) in steps.into_iter().enumerate() {
+            let step_timeout: Duration = if i + 1 < len {
+                remaining / 2
+            } else {
+                remaining
+            };
+            remaining = remaining.saturating_sub(step_timeout);
+
+            event = self.fetch_event_from(id, urls, step_timeout).await;
+            if event.is_some() {
+                break;
+            }
+        }
+
+        // Remove the relays added for the fetch
+        for url in added.into_iter() {
+            if let Err(e) = self.pool.remove_relay(url.clone()).await {
+                tracing::warn!("Impossible to remove hinted relay {url}: {e}");
+            }
+        }
+
+        Ok(event)
+    }
+
+    /// Get the outbox relays of the public key, from the relay list in the database
+    async fn outbox_relays(&self, public_key: PublicKey) -> HashSet<Url> {
+        let filter: Filter = Filter::new()
+            .author(public_key)
+            .kind(Kind::RelayList)
+            .limit(1);
+        match self.database().query(vec![filter], Order::Desc).await {
+            Ok(events) => events
+                .first()
+                .map(|event| {
+                    nip65::extract_relay_list(event)
+                        .filter(|(.., metadata)| !matches!(metadata, Some(RelayMetadata::Read)))
+                        .map(|(url, ..)| url.clone())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            Err(e) => {
+                tracing::warn!("Impossible to get relay list of {public_key}: {e}");
+                HashSet::new()
+            }
+        }
+    }
+
+    /// Subscribe to the event ID and wait for the first relay that send it
+    ///
+    /// Return as soon as all the relays sent `EOSE` or the `timeout` is reached.
+    async fn fetch_event_from(
+        &self,
+        id: EventId,
+        urls: HashSet<Url>,
+        timeout: Duration,
+    ) -> Option<Event> {
+        if urls.is_empty() || timeout.is_zero() {
+            return None;
+        }
+
+        let sub_id: SubscriptionId = SubscriptionId::generate();
+        let filter: Filter = Filter::new().id(id).limit(1);
+        let send_opts: RelaySendOptions = RelaySendOptions::new().skip_send_confirmation(true);
+        let opts: SubscribeOptions = SubscribeOptions::default().send_opts(send_opts);
+
+        // Subscribe to notifications before sending the REQ, to avoid to miss the event
+        let mut notifications = self.pool.notifications();
+
+        let mut subscribed: Vec<Relay> = Vec::with_capacity(urls.len());
+        let mut pending: HashSet<Url> = HashSet::with_capacity(urls.len());
+        for url in urls.into_iter() {
+            let relay: Relay = match self.pool.relay(&url).await {
+                Ok(relay) => relay,
+                Err(e) => {
+                    tracing::warn!("Impossible to fetch event {id} from {url}: {e}");
+                    continue;
+                }
+            };
+
+            match relay
+                .subscribe_with_id(sub_id.clone(), vec![filter.clone()], opts.clone())
+                .await
+            {
+                Ok(()) => {
+                    subscribed.push(relay);
+                    pending.insert(url);
+                }
+                Err(e) => tracing::warn!("Impossible to fetch event {id} from {url}: {e}"),
+            }
+        }
+
+        let event: Option<Event> = if pending.is_empty() {
+            None
+        } else {
+            time::timeout(Some(timeout), async {
+                while let Ok(notification) = notifications.recv().await {
+                    match notification {
+                        RelayPoolNotification::Event { event, .. } if event.id() == id => {
+                            return Some(*event);
+                        }
+                        RelayPoolNotification::Eose {
+                            relay_url,
+                            subscription_id,
+                        } if subscription_id == sub_id => {
+                            pending.remove(&relay_url);
+                            if pending.is_empty() {
+                                break;
+                            }
+                        }
+                        RelayPoolNotification::Shutdown => break,
+                        _ => (),
+                    }
+                }
+
+                None
+            })
+            .await
+            .flatten()
+        };
+
+        // Close the subscriptions
+        for relay in subscribed.into_iter() {
+            if let Err(e) = relay.unsubscribe(sub_id.clone(), send_opts).await {
+                tracing::warn!("Impossible to close subscription with {}: {e}", relay.url());
+            }
+        }
+
+        event
+    }
+
     /// Send client message to **all relays**
     #[inline]
     pub async fn send_msg(&self, msg: ClientMessage) -> Result<Output<()>, Error> {
@@ -911,6 +1494,85 @@ impl Client {
         Ok(self.pool.send_event(event, opts).await?)
     }
 
+    /// Send event to the `WRITE` relays, overriding the selection with [`SendEventOptions`]
+    ///
+    /// The included relays must be already added to the pool.
+    ///
+    /// This method will wait for the `OK` message from the relays.
+    ///
+    /// If a [`RetryPolicy`] is set, the event is re-published to the relays that failed transiently.
+    /// Use [`SendEventOptions::ok_timeout`] to bound the wait for the `OK` of every relay.
+    /// If neither is set, the event is sent as with [`Client::send_event_to`].
+    pub async fn send_event_with(
+        &self,
+        event: Event,
+        opts: SendEventOptions,
+    ) -> Result<SendEventOutput, Error> {
+        let relays: HashMap<Url, Relay> = self.pool.relays().await;
+
+        // Check if included relays are in the pool
+        if !opts.include.iter().all(|url| relays.contains_key(url)) {
+            return Err(Error::RelayPool(pool::Error::RelayNotFound));
+        }
+
+        // Select WRITE relays
+        let selected: HashSet<Url> = relays
+            .iter()
+            .filter(|(.., relay)| relay.flags().has_write())
+            .map(|(url, ..)| url.clone())
+            .collect();
+
+        let urls: HashSet<Url> = opts.apply(selected);
+
+        let send_opts: RelaySendOptions = self.opts.get_wait_for_send();
+
+        // Nothing to retry and no OK timeout: send it as usual
+        let policy: RetryPolicy = match (opts.retry, opts.ok_timeout) {
+            (Some(policy), ..) => policy,
+            (None, Some(..)) => RetryPolicy::new().max_attempts(1),
+            (None, None) => {
+                return Ok(self
+                    .pool
+                    .send_event_to(urls, event, send_opts)
+                    .await?
+                    .into())
+            }
+        };
+
+        if urls.is_empty() {
+            return Err(Error::RelayPool(pool::Error::NoRelaysSpecified));
+        }
+
+        // Save event into database
+        self.database()
+            .save_event(&event)
+            .await
+            .map_err(pool::Error::from)?;
+
+        let output: SendEventOutput =
+            retry::send_with_retry(event.id(), urls, policy, opts.ok_timeout, |url| {
+                let relay: Option<Relay> = relays.get(&url).cloned();
+                let event: Event = event.clone();
+                async move {
+                    let relay: Relay = match relay {
+                        Some(relay) => relay,
+                        None => return SendStatus::Refused(pool::Error::RelayNotFound.to_string()),
+                    };
+                    match relay.send_event(event, send_opts).await {
+                        Ok(..) => SendStatus::Accepted,
+                        Err(e) => SendStatus::from(e),
+                    }
+                }
+            })
+            .await;
+
+        if output.success.is_empty() {
+            return Err(Error::RelayPool(pool::Error::EventNotPublished));
+        }
+
+        Ok(output)
+    }
+
     /// Send multiple [`Event`] at once to **all relays**.
     #[inline]
     pub async fn batch_event(
@@ -1006,12 +1668,66 @@ impl Client {
             .kind(Kind::Metadata)
             .limit(1);
         let events: Vec<Event> = self.get_events_of(vec![filter], None).await?; // TODO: add timeout?
-        match events.first() {
+        match newest_replaceable(events) {
             Some(event) => Ok(Metadata::from_json(event.content())?),
             None => Err(Error::MetadataNotFound),
         }
     }
 
+    /// Resolve a NIP05 identifier (i.e. `name@example.com` or `example.com`)
+    ///
+    /// Fetch the `/.well-known/nostr.json` of the domain and return the [`Nip19Profile`] (public key and advertised relays).
+    /// The request is sent through the proxy only if the [`ProxyTarget`] is [`ProxyTarget::All`].
+    ///
+    /// <https://github.com/nostr-protocol/nips/blob/master/05.md>
+    #[cfg(feature = "nip05")]
+    pub async fn resolve_nip05<S>(&self, nip05: S, timeout: Duration) -> Result<Nip19Profile, Error>
+    where
+        S: AsRef<str>,
+    {
+        #[cfg(not(target_arch = "wasm32"))]
+        let proxy: Option<SocketAddr> = match self.opts.proxy.target {
+            ProxyTarget::All => self.opts.proxy.addr,
+            ProxyTarget::Onion => None,
+        };
+
+        #[cfg(target_arch = "wasm32")]
+        let proxy: Option<SocketAddr> = None;
+
+        let profile: Nip19Profile =
+            time::timeout(Some(timeout), nip05::get_profile(nip05.as_ref(), proxy))
+                .await
+                .ok_or(Error::Timeout)??;
+        Ok(profile)
+    }
+
+    /// Get the latest version of a replaceable or parameterized replaceable event
+    ///
+    /// Only the newest event per `(public key, kind, identifier)` is returned:
+    /// on equal `created_at`, the event with the lowest ID wins (NIP-01).
+    /// The `identifier` (`d` tag) is used only for parameterized replaceable kinds.
+    ///
+    /// If timeout is set to `None`, the default from [`Options`] will be used.
+    ///
+    /// <https://github.com/nostr-protocol/nips/blob/master/01.md>
+    pub async fn latest_replaceable<S>(
+        &self,
+        public_key: PublicKey,
+        kind: Kind,
+        identifier: Option<S>,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Event>, Error>
+    where
+        S: Into<String>,
+    {
+        let mut filter: Filter = Filter::new().author(public_key).kind(kind);
+        if kind.is_parameterized_replaceable() {
+            filter = filter.identifier(identifier.map(|i| i.into()).unwrap_or_default());
+        }
+        let events: Vec<Event> = self.get_events_of(vec![filter], timeout).await?;
+        Ok(newest_replaceable(events))
+    }
+
     /// Update metadata
     ///
     /// <https://github.com/nostr-protocol/nips/blob/master/01.md>
@@ -1265,6 +1981,43 @@ impl Client {
         self.send_event_builder(builder).await
     }
 
+    /// Delete events
+    ///
+    /// The deletion is sent to all the `WRITE` relays and to the relays that delivered the events (if known by the database).
+    ///
+    /// This is a **best-effort** operation: relays not aware of the events or not supporting NIP-09 may ignore the deletion.
+    ///
+    /// <https://github.com/nostr-protocol/nips/blob/master/09.md>
+    pub async fn delete_events(
+        &self,
+        ids: Vec<EventId>,
+        reason: Option<String>,
+    ) -> Result<Output<EventId>, Error> {
+        let relays: HashMap<Url, Relay> = self.pool.relays().await;
+
+        // Compose relays set
+        let mut urls: HashSet<Url> = relays
+            .iter()
+            .filter(|(.., relay)| relay.flags().has_write())
+            .map(|(url, ..)| url.clone())
+            .collect();
+
+        // Add relays that delivered the events
+        let database = self.database();
+        for id in ids.iter() {
+            match database.event_seen_on_relays(*id).await {
+                Ok(Some(seen_on)) => {
+                    urls.extend(seen_on.into_iter().filter(|url| relays.contains_key(url)))
+                }
+                Ok(None) => (),
+                Err(e) => tracing::warn!("Impossible to get relays that have seen {id}: {e}"),
+            }
+        }
+
+        let builder = EventBuilder::delete_with_reason(ids, reason.unwrap_or_default());
+        self.send_event_builder_to(urls, builder).await
+    }
+
     /// Like event
     ///
     /// <https://github.com/nostr-protocol/nips/blob/master/25.md>
@@ -1531,7 +2284,7 @@ impl Client {
         &self,
         filter: Filter,
         opts: NegentropyOptions,
-    ) -> Result<Output<()>, Error> {
+    ) -> Result<Output<Reconciliation>, Error> {
         Ok(self.pool.reconcile(filter, opts).await?)
     }
 
@@ -1544,7 +2297,7 @@ impl Client {
         urls: I,
         filter: Filter,
         opts: NegentropyOptions,
-    ) -> Result<Output<()>, Error>
+    ) -> Result<Output<Reconciliation>, Error>
     where
         I: IntoIterator<Item = U>,
         U: TryIntoUrl,
@@ -1560,7 +2313,7 @@ impl Client {
         filter: Filter,
         items: Vec<(EventId, Timestamp)>,
         opts: NegentropyOptions,
-    ) -> Result<Output<()>, Error> {
+    ) -> Result<Output<Reconciliation>, Error> {
         Ok(self.pool.reconcile_with_items(filter, items, opts).await?)
     }
 
@@ -1574,7 +2327,7 @@ impl Client {
         filter: Filter,
         items: Vec<(EventId, Timestamp)>,
         opts: NegentropyOptions,
-    ) -> Result<Output<()>, Error>
+    ) -> Result<Output<Reconciliation>, Error>
     where
         I: IntoIterator<Item = U>,
         U: TryIntoUrl,
@@ -1586,6 +2339,41 @@ impl Client {
             .await?)
     }
 
+    /// Negentropy reconciliation with all relays, calling `on_item` for every [`ReconcileItem`] as soon as it's discovered
+    ///
+    /// The items are emitted during the negentropy exchange, before the events transfer (also in dry run).
+    ///
+    /// <https://github.com/hoytech/negentropy>
+    #[inline]
+    pub async fn reconcile_with_callback<F>(
+        &self,
+        filter: Filter,
+        opts: NegentropyOptions,
+        on_item: F,
+    ) -> Result<Output<Reconciliation>, Error>
+    where
+        F: Fn(&Url, ReconcileItem) + Send + Sync + 'static,
+    {
+        Ok(self
+            .pool
+            .reconcile_with_callback(filter, opts, on_item)
+            .await?)
+    }
+
+    /// Negentropy reconciliation with all relays that can be aborted
+    ///
+    /// The sync runs in background: use [`SyncHandle::abort`] to stop it and [`SyncHandle::output`] to get the partial result.
+    ///
+    /// <https://github.com/hoytech/negentropy>
+    #[inline]
+    pub fn reconcile_cancellable(
+        &self,
+        filter: Filter,
+        opts: NegentropyOptions,
+    ) -> Result<SyncHandle<Output<Reconciliation>, pool::Error>, Error> {
+        Ok(self.pool.reconcile_cancellable(filter, opts)?)
+    }
+
     /// Handle notifications
     #[inline]
     pub async fn handle_notifications<F, Fut>(&self, func: F) -> Result<(), Error>
@@ -1596,3 +2384,332 @@ impl Client {
         Ok(self.pool.handle_notifications(func).await?)
     }
 }
+
+/// Get the newest event: on equal `created_at`, the lowest ID wins (NIP-01)
+fn newest_replaceable(events: Vec<Event>) -> Option<Event> {
+    events.into_iter().max_by(|a, b| {
+        a.created_at()
+            .cmp(&b.created_at())
+            .then_with(|| b.id().cmp(&a.id()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use async_utility::futures_util::StreamExt;
+    use async_utility::thread;
+    use nostr_relay_pool::mock::MockRelay;
+
+    use super::*;
+
+    fn replaceable(keys: &Keys, kind: Kind, identifier: &str, created_at: u64) -> Event {
+        EventBuilder::new(kind, "", [Tag::identifier(identifier)])
+            .custom_created_at(Timestamp::from(created_at))
+            .to_event(keys)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_newest_replaceable() {
+        let keys = Keys::generate();
+
+        // Newest wins
+        let old = replaceable(&keys, Kind::Metadata, "", 10);
+        let new = replaceable(&keys, Kind::Metadata, "", 20);
+        assert_eq!(
+            newest_replaceable(vec![new.clone(), old.clone()]),
+            Some(new.clone())
+        );
+        assert_eq!(newest_replaceable(vec![old, new.clone()]), Some(new));
+        assert_eq!(newest_replaceable(Vec::new()), None);
+
+        // Same timestamp: lowest ID wins
+        let kind = Kind::ParameterizedReplaceable(30000);
+        let a = replaceable(&keys, kind, "a", 10);
+        let b = replaceable(&keys, kind, "b", 10);
+        let lowest: EventId = a.id().min(b.id());
+        assert_eq!(newest_replaceable(vec![a, b]).unwrap().id(), lowest);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_events_ephemeral() {
+        let client = Client::default();
+        let url = Url::parse("ws://127.0.0.1:1").unwrap();
+
+        let events = client
+            .fetch_events_ephemeral([url.clone()], Filter::new(), Duration::from_millis(500))
+            .await
+            .unwrap();
+        assert!(events.is_empty());
+
+        // Not added to the pool
+        assert!(client.relays().await.is_empty());
+        assert!(client.relay(url).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_event_by_id_from_hint() {
+        let client = Client::default();
+        client.add_relay("wss://read.example.com").await.unwrap();
+
+        let keys = Keys::generate();
+        let event = EventBuilder::text_note("hinted", [])
+            .to_event(&keys)
+            .unwrap();
+        let hint = Url::parse("wss://hint.example.com").unwrap();
+
+        // Only the hinted relay has the event
+        let pool = client.pool();
+        let url = hint.clone();
+        let injected = event.clone();
+        thread::spawn(async move {
+            time::sleep(Duration::from_millis(100)).await;
+            pool.inject_events(url, vec![injected]).await.unwrap();
+        })
+        .unwrap();
+
+        let found = client
+            .fetch_event_by_id(event.id(), vec![hint.clone()], Duration::from_secs(2))
+            .await
+            .unwrap();
+        assert_eq!(found, Some(event));
+
+        // Hinted relay removed after the fetch
+        assert!(client.relay(&hint).await.is_err());
+        assert!(client.relay("wss://read.example.com").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_event_by_nevent_from_outbox() {
+        let keys = Keys::generate();
+        let event = EventBuilder::text_note("outbox", [])
+            .to_event(&keys)
+            .unwrap();
+
+        // Only the outbox relay of the author has the event
+        let read = MockRelay::run().await;
+        let outbox = MockRelay::run().await;
+        outbox.add_events([event.clone()]).await;
+
+        let client = Client::default();
+        client.add_relay(read.url()).await.unwrap();
+        client.connect().await;
+
+        let relay_list = EventBuilder::relay_list([(outbox.url(), Some(RelayMetadata::Write))])
+            .to_event(&keys)
+            .unwrap();
+        client.database().save_event(&relay_list).await.unwrap();
+
+        let nevent = Nip19Event::new(event.id(), Vec::<String>::new()).author(keys.public_key());
+        let found = client
+            .fetch_event_by_nevent(&nevent, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(found, Some(event));
+
+        // Found before the fallback on the read relays
+        assert!(outbox.received().await.iter().any(|msg| msg.is_req()));
+        assert!(!read.received().await.iter().any(|msg| msg.is_req()));
+
+        // Outbox relay removed after the fetch
+        assert!(client.relay(outbox.url()).await.is_err());
+        assert!(client.relay(read.url()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_delete_events_routed_to_delivering_relay() {
+        let keys = Keys::generate();
+        let event = EventBuilder::text_note("to delete", [])
+            .to_event(&keys)
+            .unwrap();
+
+        // The event is delivered by a `READ` relay
+        let read = MockRelay::run().await;
+        read.add_events([event.clone()]).await;
+        let write = MockRelay::run().await;
+
+        let client = Client::new(&keys);
+        client
+            .add_relay_with_opts(read.url(), RelayOptions::new().write(false))
+            .await
+            .unwrap();
+        client
+            .add_relay_with_opts(write.url(), RelayOptions::new().read(false))
+            .await
+            .unwrap();
+        client.connect().await;
+
+        let events = client
+            .get_events_of(
+                vec![Filter::new().id(event.id())],
+                Some(Duration::from_secs(5)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(events, vec![event.clone()]);
+
+        let output = client
+            .delete_events(vec![event.id()], Some(String::from("mistake")))
+            .await
+            .unwrap();
+        assert_eq!(output.success, HashSet::from([read.url(), write.url()]));
+
+        // Deletion sent also to the relay that delivered the event
+        for mock in [&read, &write] {
+            let deletion: Event = mock
+                .events()
+                .await
+                .into_iter()
+                .find(|e| e.kind() == Kind::EventDeletion)
+                .expect("Deletion not sent");
+            assert_eq!(
+                deletion.event_ids().copied().collect::<Vec<_>>(),
+                vec![event.id()]
+            );
+            assert_eq!(deletion.content(), "mistake");
+        }
+    }
+
+    /// Number of relay lists published to the relay
+    async fn published(relay: &MockRelay) -> usize {
+        relay
+            .received()
+            .await
+            .into_iter()
+            .filter(|msg| match msg {
+                ClientMessage::Event(event) => event.kind() == Kind::RelayList,
+                _ => false,
+            })
+            .count()
+    }
+
+    #[tokio::test]
+    async fn test_auto_publish_relay_list() {
+        let relay = MockRelay::run().await;
+        let keys = Keys::generate();
+        let opts = Options::new().auto_publish_relay_list(true);
+        let client = Client::with_opts(&keys, opts);
+
+        // Adding a relay triggers exactly one republish
+        client.add_relay(relay.url()).await.unwrap();
+        client.connect().await;
+        thread::sleep(RELAY_LIST_DEBOUNCE + Duration::from_secs(1)).await;
+        assert_eq!(published(&relay).await, 1);
+
+        // Nothing removed: no republish
+        client
+            .remove_relay("wss://unknown.example.com")
+            .await
+            .unwrap();
+        thread::sleep(RELAY_LIST_DEBOUNCE + Duration::from_secs(1)).await;
+        assert_eq!(published(&relay).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_events_ephemeral_from_relay() {
+        let keys = Keys::generate();
+        let event = EventBuilder::text_note("ephemeral", [])
+            .to_event(&keys)
+            .unwrap();
+
+        let relay = MockRelay::run().await;
+        relay.add_events([event.clone()]).await;
+
+        let client = Client::default();
+        let events = client
+            .fetch_events_ephemeral([relay.url()], Filter::new(), Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(events, vec![event]);
+        assert!(relay.received().await.iter().any(|msg| msg.is_req()));
+
+        // Pool untouched
+        assert!(client.relays().await.is_empty());
+        assert!(client.relay(relay.url()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_events_paginated_stream() {
+        let keys = Keys::generate();
+        let mut events: Vec<Event> = [1, 2, 2, 2, 3, 4, 5, 6, 7, 7]
+            .into_iter()
+            .map(|created_at| {
+                EventBuilder::text_note("paginated", [])
+                    .custom_created_at(Timestamp::from(created_at))
+                    .to_event(&keys)
+                    .unwrap()
+            })
+            .collect();
+
+        let relay = MockRelay::run().await;
+        relay.add_events(events.clone()).await;
+
+        let client = Client::default();
+        client.add_relay(relay.url()).await.unwrap();
+        client.connect().await;
+
+        let filter = Filter::new().author(keys.public_key());
+        let output: Vec<Event> = client
+            .fetch_events_paginated(filter, 2, None, Some(Duration::from_secs(5)))
+            .into_stream()
+            .collect()
+            .await;
+
+        // Stitched together in order, without losing the events with the same timestamp
+        events.sort_by(|a, b| b.cmp(a));
+        assert_eq!(output, events);
+        assert!(
+            relay
+                .received()
+                .await
+                .iter()
+                .filter(|msg| msg.is_req())
+                .count()
+                > 1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_events_paginated_include_archive() {
+        let keys = Keys::generate();
+        let event = EventBuilder::text_note("archived", [])
+            .to_event(&keys)
+            .unwrap();
+
+        let relay = MockRelay::run().await;
+        let archive = MockRelay::run().await;
+        archive.add_events([event.clone()]).await;
+
+        let client = Client::default();
+        client.add_relay(relay.url()).await.unwrap();
+        client
+            .add_relay_with_opts(archive.url(), RelayOptions::default().archive(true))
+            .await
+            .unwrap();
+        client.connect().await;
+
+        let reqs = |msgs: Vec<ClientMessage>| msgs.iter().filter(|msg| msg.is_req()).count();
+        let filter = Filter::new().author(keys.public_key());
+        let timeout = Some(Duration::from_secs(5));
+
+        // Archive relay skipped by default
+        let output: Vec<Event> = client
+            .fetch_events_paginated(filter.clone(), 2, None, timeout)
+            .into_stream()
+            .collect()
+            .await;
+        assert!(output.is_empty());
+        assert!(reqs(relay.received().await) > 0);
+        assert_eq!(reqs(archive.received().await), 0);
+
+        // Queried when included
+        let output: Vec<Event> = client
+            .fetch_events_paginated(filter, 2, None, timeout)
+            .include_archive(true)
+            .into_stream()
+            .collect()
+            .await;
+        assert_eq!(output, vec![event]);
+        assert!(reqs(archive.received().await) > 0);
+    }
+}
@@ -4,12 +4,14 @@
 
 //! Client Options
 
+use std::collections::HashSet;
 #[cfg(not(target_arch = "wasm32"))]
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
+use nostr::Url;
 use nostr_relay_pool::relay::options::DEFAULT_SEND_TIMEOUT;
 use nostr_relay_pool::{RelayLimits, RelayPoolOptions, RelaySendOptions};
 
@@ -29,6 +31,7 @@ pub struct Options {
     #[cfg(not(target_arch = "wasm32"))]
     pub(super) proxy: Proxy,
     pub(super) relay_limits: RelayLimits,
+    pub(super) auto_publish_relay_list: bool,
     pub(super) pool: RelayPoolOptions,
 }
 
@@ -48,6 +51,7 @@ impl Default for Options {
             #[cfg(not(target_arch = "wasm32"))]
             proxy: Proxy::default(),
             relay_limits: RelayLimits::default(),
+            auto_publish_relay_list: false,
             pool: RelayPoolOptions::default(),
         }
     }
@@ -202,6 +206,18 @@ impl Options {
         self
     }
 
+    /// Automatically publish the relay list when relays are added or removed (default: false)
+    ///
+    /// The changes are debounced, to publish only once during bulk adds.
+    /// The relay list is published only if a signer is configured.
+    ///
+    /// <https://github.com/nostr-protocol/nips/blob/master/65.md>
+    #[inline]
+    pub fn auto_publish_relay_list(mut self, enable: bool) -> Self {
+        self.auto_publish_relay_list = enable;
+        self
+    }
+
     /// Set pool options
     #[inline]
     pub fn pool(mut self, opts: RelayPoolOptions) -> Self {
@@ -210,6 +226,115 @@ impl Options {
     }
 }
 
+/// Send event options
+///
+/// Override the relays selected for a single send.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SendEventOptions {
+    pub(super) include: HashSet<Url>,
+    pub(super) exclude: HashSet<Url>,
+    pub(super) retry: Option<RetryPolicy>,
+    pub(super) ok_timeout: Option<Duration>,
+}
+
+impl SendEventOptions {
+    /// New default options
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Force-add relays (must be already added to the pool)
+    pub fn include<I>(mut self, urls: I) -> Self
+    where
+        I: IntoIterator<Item = Url>,
+    {
+        self.include.extend(urls);
+        self
+    }
+
+    /// Force-exclude relays, also if selected as `WRITE` relays
+    pub fn exclude<I>(mut self, urls: I) -> Self
+    where
+        I: IntoIterator<Item = Url>,
+    {
+        self.exclude.extend(urls);
+        self
+    }
+
+    /// Re-publish to the relays that failed transiently (default: none)
+    ///
+    /// Permanent rejections (i.e. `invalid:`, `blocked:`) are never retried.
+    #[inline]
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Max time to wait for the `OK` of every relay (default: none)
+    ///
+    /// The relays that not send the `OK` in time are marked as [`SendStatus::TimedOut`](super::SendStatus::TimedOut),
+    /// without delaying the confirmation of the other relays.
+    #[inline]
+    pub fn ok_timeout(mut self, timeout: Duration) -> Self {
+        self.ok_timeout = Some(timeout);
+        self
+    }
+
+    /// Compose the target relays: selected and included relays, minus the excluded ones
+    pub(super) fn apply(&self, selected: HashSet<Url>) -> HashSet<Url> {
+        selected
+            .into_iter()
+            .chain(self.include.iter().cloned())
+            .filter(|url| !self.exclude.contains(url))
+            .collect()
+    }
+}
+
+/// Send retry policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub(super) max_attempts: u8,
+    pub(super) backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// New default retry policy
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Max number of attempts per relay, including the first one (default: 3)
+    #[inline]
+    pub fn max_attempts(mut self, max_attempts: u8) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Delay before the first retry, doubled at every attempt (default: 1 sec)
+    #[inline]
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Delay before the retry following the `attempt`-th attempt
+    pub(super) fn delay(&self, attempt: u8) -> Duration {
+        let factor: u32 = 1 << u32::from(attempt.saturating_sub(1)).min(16);
+        self.backoff.saturating_mul(factor)
+    }
+}
+
 /// Proxy target
 #[cfg(not(target_arch = "wasm32"))]
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
@@ -247,3 +372,43 @@ impl Proxy {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn test_send_event_options() {
+        let damus = url("wss://relay.damus.io");
+        let nos = url("wss://nos.lol");
+        let primal = url("wss://relay.primal.net");
+
+        let selected: HashSet<Url> = [damus.clone(), nos.clone()].into_iter().collect();
+
+        // Include only
+        let opts = SendEventOptions::new().include([primal.clone()]);
+        assert_eq!(
+            opts.apply(selected.clone()),
+            [damus.clone(), nos.clone(), primal.clone()]
+                .into_iter()
+                .collect()
+        );
+
+        // Exclude only
+        let opts = SendEventOptions::new().exclude([nos.clone()]);
+        assert_eq!(
+            opts.apply(selected.clone()),
+            [damus.clone()].into_iter().collect()
+        );
+
+        // Combined: exclude wins over include
+        let opts = SendEventOptions::new()
+            .include([primal.clone()])
+            .exclude([damus, primal]);
+        assert_eq!(opts.apply(selected), [nos].into_iter().collect());
+    }
+}
@@ -0,0 +1,254 @@
+// Copyright (c) 2022-2023 Yuki Kishimoto
+// Copyright (c) 2023-2024 Rust Nostr Developers
+// Distributed under the MIT software license
+
+//! Events pagination
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use async_utility::futures_util::stream::{self, Stream, StreamExt};
+use nostr::prelude::*;
+
+use super::{Client, Error};
+
+/// Pagination state
+#[derive(Debug, Clone)]
+struct Pagination {
+    filter: Filter,
+    page_size: usize,
+    /// Inclusive: the events with the same timestamp of the oldest one of a page may be in the next one
+    until: Option<Timestamp>,
+    /// Remaining events, if the filter has a `limit`
+    remaining: Option<usize>,
+    seen: HashSet<EventId>,
+    /// Already returned events with `created_at == until`, requested again by the next page
+    boundary: usize,
+    done: bool,
+}
+
+impl Pagination {
+    fn new(filter: Filter, page_size: u16, until: Option<Timestamp>) -> Self {
+        Self {
+            until: until.or(filter.until),
+            remaining: filter.limit,
+            filter,
+            page_size: page_size.max(1) as usize,
+            seen: HashSet::new(),
+            boundary: 0,
+            done: false,
+        }
+    }
+
+    /// Filter for the next page (`None` if the pagination is completed)
+    fn next_filter(&self) -> Option<Filter> {
+        if self.done || self.remaining == Some(0) {
+            return None;
+        }
+
+        if let (Some(since), Some(until)) = (self.filter.since, self.until) {
+            if until < since {
+                return None;
+            }
+        }
+
+        // Request also the already returned events at the `until` boundary,
+        // so that a full page always contains new events
+        let limit: usize = match self.remaining {
+            Some(remaining) => remaining.min(self.page_size),
+            None => self.page_size,
+        } + self.boundary;
+
+        let mut filter: Filter = self.filter.clone().limit(limit);
+        filter.until = self.until;
+        Some(filter)
+    }
+
+    /// Process received page and return the events not already returned, newest first
+    fn process(&mut self, mut events: Vec<Event>) -> Vec<Event> {
+        // Stop on empty page
+        let oldest: Timestamp = match events.iter().map(|e| e.created_at()).min() {
+            Some(oldest) => oldest,
+            None => {
+                self.done = true;
+                return Vec::new();
+            }
+        };
+
+        let boundary: usize = events.iter().filter(|e| e.created_at() == oldest).count();
+
+        // Dedup by ID
+        events.retain(|e| self.seen.insert(e.id()));
+        events.sort_by(|a, b| b.cmp(a));
+
+        if let Some(remaining) = self.remaining.as_mut() {
+            events.truncate(*remaining);
+            *remaining -= events.len();
+        }
+
+        if events.is_empty() {
+            // Only already returned events: nothing else to fetch
+            self.done = true;
+        } else {
+            self.until = Some(oldest);
+            self.boundary = boundary;
+        }
+
+        events
+    }
+}
+
+/// Events paginator
+///
+/// Returned by [`Client::fetch_events_paginated`].
+#[derive(Debug, Clone)]
+pub struct EventsPaginator {
+    client: Client,
+    timeout: Option<Duration>,
+    include_archive: bool,
+    pagination: Pagination,
+}
+
+impl EventsPaginator {
+    pub(super) fn new(
+        client: Client,
+        filter: Filter,
+        page_size: u16,
+        until: Option<Timestamp>,
+        timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            client,
+            timeout,
+            include_archive: false,
+            pagination: Pagination::new(filter, page_size, until),
+        }
+    }
+
+    /// Query also the archive relays (default: false)
+    ///
+    /// Archive relays are skipped by routine queries (see [`RelayOptions::archive`](nostr_relay_pool::RelayOptions::archive)).
+    pub fn include_archive(mut self, include: bool) -> Self {
+        self.include_archive = include;
+        self
+    }
+
+    /// Fetch the next page, newest events first
+    ///
+    /// Return `None` when there are no more events.
+    pub async fn next_page(&mut self) -> Result<Option<Vec<Event>>, Error> {
+        while let Some(filter) = self.pagination.next_filter() {
+            let events: Vec<Event> = if self.include_archive {
+                let urls: Vec<Url> = self
+                    .client
+                    .relays()
+                    .await
+                    .into_iter()
+                    .filter(|(.., relay)| relay.flags().has_read())
+                    .map(|(url, ..)| url)
+                    .collect();
+                self.client
+                    .get_events_from(urls, vec![filter], self.timeout)
+                    .await?
+            } else {
+                self.client
+                    .get_events_of(vec![filter], self.timeout)
+                    .await?
+            };
+            let events: Vec<Event> = self.pagination.process(events);
+            if !events.is_empty() {
+                return Ok(Some(events));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Convert into a stream of events, newest first
+    ///
+    /// The stream ends when there are no more events or at the first error.
+    pub fn into_stream(self) -> impl Stream<Item = Event> {
+        stream::unfold(self, |mut paginator| async move {
+            match paginator.next_page().await {
+                Ok(Some(events)) => Some((stream::iter(events), paginator)),
+                Ok(None) => None,
+                Err(e) => {
+                    tracing::error!("Impossible to fetch the next page: {e}");
+                    None
+                }
+            }
+        })
+        .flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Simulate a relay that return the newest `limit` events
+    fn query(events: &[Event], filter: &Filter) -> Vec<Event> {
+        let mut events: Vec<Event> = events
+            .iter()
+            .filter(|e| filter.match_event(e))
+            .cloned()
+            .collect();
+        events.sort_by(|a, b| b.cmp(a));
+        if let Some(limit) = filter.limit {
+            events.truncate(limit);
+        }
+        events
+    }
+
+    fn paginate(events: &[Event], mut pagination: Pagination) -> Vec<Event> {
+        let mut output: Vec<Event> = Vec::new();
+        while let Some(filter) = pagination.next_filter() {
+            output.extend(pagination.process(query(events, &filter)));
+        }
+        output
+    }
+
+    #[test]
+    fn test_pagination() {
+        let keys = Keys::generate();
+        let events: Vec<Event> = [1, 2, 2, 2, 3, 4, 5, 6, 7, 7]
+            .into_iter()
+            .enumerate()
+            .map(|(i, created_at)| {
+                EventBuilder::text_note(format!("Text note {i}"), [])
+                    .custom_created_at(Timestamp::from(created_at))
+                    .to_event(&keys)
+                    .unwrap()
+            })
+            .collect();
+
+        let mut expected: Vec<Event> = events.clone();
+        expected.sort_by(|a, b| b.cmp(a));
+
+        // All events, stitched in order
+        let filter = Filter::new().author(keys.public_key());
+        let output = paginate(&events, Pagination::new(filter.clone(), 3, None));
+        assert_eq!(output, expected);
+
+        // Page smaller than events with the same timestamp
+        let output = paginate(&events, Pagination::new(filter.clone(), 1, None));
+        assert_eq!(output, expected);
+
+        // Until
+        let output = paginate(
+            &events,
+            Pagination::new(filter.clone(), 3, Some(Timestamp::from(4))),
+        );
+        assert_eq!(output, expected[4..]);
+
+        // Limit of the filter is the total number of events
+        let output = paginate(&events, Pagination::new(filter.clone().limit(5), 2, None));
+        assert_eq!(output, expected[..5]);
+
+        // Since
+        let output = paginate(
+            &events,
+            Pagination::new(filter.since(Timestamp::from(5)), 2, None),
+        );
+        assert_eq!(output, expected[..4]);
+    }
+}
@@ -0,0 +1,347 @@
+// Copyright (c) 2022-2023 Yuki Kishimoto
+// Copyright (c) 2023-2024 Rust Nostr Developers
+// Distributed under the MIT software license
+
+//! Send retry
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::ops::Deref;
+use std::time::Duration;
+
+use async_utility::futures_util::future;
+use async_utility::time;
+use nostr::message::MachineReadablePrefix;
+use nostr::{EventId, Url};
+use nostr_relay_pool::{relay, Output};
+
+use super::options::RetryPolicy;
+
+/// Send status of a relay
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SendStatus {
+    /// Event accepted (`OK` with `true` status)
+    Accepted,
+    /// Event rejected (`OK` with `false` status), with the relay message
+    Rejected(String),
+    /// `OK` not received in time
+    TimedOut,
+    /// Impossible to send the event, because of the connection (i.e. relay not connected)
+    Failed(String),
+    /// Event refused before sending it (i.e. `WRITE` disabled): never retried
+    Refused(String),
+}
+
+impl From<relay::Error> for SendStatus {
+    fn from(e: relay::Error) -> Self {
+        match e {
+            // Keep the raw `OK` message if the relay rejected the event
+            relay::Error::EventNotPublished(message) => Self::Rejected(message),
+            relay::Error::Timeout => Self::TimedOut,
+            relay::Error::RecvTimeout
+            | relay::Error::WebSocketTimeout
+            | relay::Error::NotConnected
+            | relay::Error::NotConnectedStatusChanged
+            | relay::Error::MessageNotSent
+            | relay::Error::CantSendChannelMessage { .. }
+            | relay::Error::OneShotRecvError
+            | relay::Error::WebSocket(..) => Self::Failed(e.to_string()),
+            e => Self::Refused(e.to_string()),
+        }
+    }
+}
+
+impl SendStatus {
+    /// Check if a failed publish is worth a retry
+    ///
+    /// Rejections without a machine-readable prefix have an unknown reason, so are retried.
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Accepted | Self::Refused(..) => false,
+            Self::Rejected(message) => matches!(
+                MachineReadablePrefix::parse(message),
+                None | Some(MachineReadablePrefix::RateLimited)
+                    | Some(MachineReadablePrefix::Error)
+            ),
+            Self::TimedOut | Self::Failed(..) => true,
+        }
+    }
+
+    fn message(&self) -> Option<String> {
+        match self {
+            Self::Accepted => None,
+            Self::Rejected(message) | Self::Failed(message) | Self::Refused(message) => {
+                Some(message.clone())
+            }
+            Self::TimedOut => Some(String::from("timeout")),
+        }
+    }
+}
+
+/// Send event output
+///
+/// Same as [`Output`], with the number of attempts made and the final status for every relay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendEventOutput {
+    /// Final output
+    pub output: Output<EventId>,
+    /// Number of attempts per relay
+    pub attempts: HashMap<Url, u8>,
+    /// Final status per relay
+    pub status: HashMap<Url, SendStatus>,
+}
+
+impl Deref for SendEventOutput {
+    type Target = Output<EventId>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.output
+    }
+}
+
+/// Output of a single attempt: the failures are reported as [`SendStatus::Failed`]
+impl From<Output<EventId>> for SendEventOutput {
+    fn from(output: Output<EventId>) -> Self {
+        let mut status: HashMap<Url, SendStatus> = output
+            .success
+            .iter()
+            .map(|url| (url.clone(), SendStatus::Accepted))
+            .collect();
+        status.extend(output.failed.iter().map(|(url, message)| {
+            (
+                url.clone(),
+                SendStatus::Failed(message.clone().unwrap_or_default()),
+            )
+        }));
+        let attempts: HashMap<Url, u8> = status.keys().map(|url| (url.clone(), 1)).collect();
+        Self {
+            output,
+            attempts,
+            status,
+        }
+    }
+}
+
+/// Send to every relay, retrying the transient failures according to the [`RetryPolicy`]
+///
+/// If `ok_timeout` is set, every relay that doesn't complete the attempt in time is marked as [`SendStatus::TimedOut`].
+pub(super) async fn send_with_retry<F, Fut>(
+    id: EventId,
+    urls: HashSet<Url>,
+    policy: RetryPolicy,
+    ok_timeout: Option<Duration>,
+    send: F,
+) -> SendEventOutput
+where
+    F: Fn(Url) -> Fut,
+    Fut: Future<Output = SendStatus>,
+{
+    let mut output: Output<EventId> = Output {
+        val: id,
+        success: HashSet::new(),
+        failed: HashMap::new(),
+    };
+    let mut attempts: HashMap<Url, u8> = HashMap::with_capacity(urls.len());
+    let mut status: HashMap<Url, SendStatus> = HashMap::with_capacity(urls.len());
+    let mut pending: Vec<Url> = urls.into_iter().collect();
+    let mut attempt: u8 = 0;
+
+    while !pending.is_empty() {
+        attempt += 1;
+
+        let results = future::join_all(pending.into_iter().map(|url| {
+            let fut = send(url.clone());
+            async move {
+                let status: SendStatus = match ok_timeout {
+                    Some(ok_timeout) => time::timeout(Some(ok_timeout), fut)
+                        .await
+                        .unwrap_or(SendStatus::TimedOut),
+                    None => fut.await,
+                };
+                (url, status)
+            }
+        }))
+        .await;
+
+        let mut retry: Vec<Url> = Vec::new();
+
+        for (url, s) in results.into_iter() {
+            attempts.insert(url.clone(), attempt);
+
+            match s.message() {
+                None => {
+                    output.failed.remove(&url);
+                    output.success.insert(url.clone());
+                }
+                Some(message) => {
+                    if attempt < policy.max_attempts && s.is_transient() {
+                        tracing::debug!("Retrying to send event {id} to '{url}': {message}");
+                        retry.push(url.clone());
+                    }
+
+                    output.failed.insert(url.clone(), Some(message));
+                }
+            }
+
+            status.insert(url, s);
+        }
+
+        pending = retry;
+
+        if !pending.is_empty() {
+            time::sleep(policy.delay(attempt)).await;
+        }
+    }
+
+    SendEventOutput {
+        output,
+        attempts,
+        status,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::Mutex;
+
+    use super::*;
+
+    fn rejected(message: &str) -> SendStatus {
+        SendStatus::Rejected(String::from(message))
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(rejected("rate-limited: slow down").is_transient());
+        assert!(rejected("error: internal error").is_transient());
+        assert!(SendStatus::TimedOut.is_transient());
+        assert!(!rejected("blocked: you are banned").is_transient());
+        assert!(!rejected("invalid: bad signature").is_transient());
+        assert!(!SendStatus::Accepted.is_transient());
+
+        // Connection errors are retried, local errors are not
+        assert!(SendStatus::from(relay::Error::NotConnectedStatusChanged).is_transient());
+        assert!(SendStatus::from(relay::Error::MessageNotSent).is_transient());
+        assert!(!SendStatus::from(relay::Error::BatchEventEmpty).is_transient());
+        assert_eq!(
+            SendStatus::from(relay::Error::EventNotPublished(String::from(
+                "blocked: banned"
+            ))),
+            rejected("blocked: banned")
+        );
+        assert_eq!(
+            SendStatus::from(relay::Error::Timeout),
+            SendStatus::TimedOut
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_with_retry() {
+        let rate_limited = Url::parse("wss://rate-limited.example.com").unwrap();
+        let blocked = Url::parse("wss://blocked.example.com").unwrap();
+        let urls: HashSet<Url> = [rate_limited.clone(), blocked.clone()]
+            .into_iter()
+            .collect();
+
+        let calls: Arc<Mutex<HashMap<Url, u8>>> = Arc::new(Mutex::new(HashMap::new()));
+        let policy = RetryPolicy::new().backoff(Duration::from_millis(1));
+
+        let out = send_with_retry(EventId::all_zeros(), urls, policy, None, |url| {
+            let calls = calls.clone();
+            let rate_limited = rate_limited.clone();
+            async move {
+                let mut calls = calls.lock().await;
+                let count = calls.entry(url.clone()).or_default();
+                *count += 1;
+
+                if url == rate_limited {
+                    if *count == 1 {
+                        rejected("rate-limited: slow down")
+                    } else {
+                        SendStatus::Accepted
+                    }
+                } else {
+                    rejected("blocked: you are banned")
+                }
+            }
+        })
+        .await;
+
+        assert!(out.success.contains(&rate_limited));
+        assert_eq!(out.attempts.get(&rate_limited), Some(&2));
+
+        assert!(out.failed.contains_key(&blocked));
+        assert_eq!(out.attempts.get(&blocked), Some(&1));
+        assert_eq!(
+            out.status.get(&blocked),
+            Some(&rejected("blocked: you are banned"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_with_retry_local_errors() {
+        let write_disabled = Url::parse("wss://write-disabled.example.com").unwrap();
+        let too_large = Url::parse("wss://too-large.example.com").unwrap();
+        let urls: HashSet<Url> = [write_disabled.clone(), too_large.clone()]
+            .into_iter()
+            .collect();
+
+        let policy = RetryPolicy::new()
+            .max_attempts(3)
+            .backoff(Duration::from_millis(1));
+
+        let out = send_with_retry(EventId::all_zeros(), urls, policy, None, |url| {
+            let write_disabled = write_disabled.clone();
+            async move {
+                if url == write_disabled {
+                    SendStatus::from(relay::Error::WriteDisabled)
+                } else {
+                    SendStatus::from(relay::Error::EventTooLarge {
+                        size: 1_000,
+                        max_size: 100,
+                    })
+                }
+            }
+        })
+        .await;
+
+        // Never retried
+        assert_eq!(out.attempts.get(&write_disabled), Some(&1));
+        assert_eq!(out.attempts.get(&too_large), Some(&1));
+        assert!(matches!(
+            out.status.get(&write_disabled),
+            Some(SendStatus::Refused(..))
+        ));
+        assert!(out.success.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_send_with_ok_timeout() {
+        let fast = Url::parse("wss://fast.example.com").unwrap();
+        let never = Url::parse("wss://never.example.com").unwrap();
+        let urls: HashSet<Url> = [fast.clone(), never.clone()].into_iter().collect();
+
+        let policy = RetryPolicy::new().max_attempts(1);
+        let ok_timeout = Some(Duration::from_millis(100));
+
+        let out = send_with_retry(EventId::all_zeros(), urls, policy, ok_timeout, |url| {
+            let fast = fast.clone();
+            async move {
+                if url == fast {
+                    SendStatus::Accepted
+                } else {
+                    // Never send the `OK`
+                    future::pending().await
+                }
+            }
+        })
+        .await;
+
+        assert_eq!(out.status.get(&fast), Some(&SendStatus::Accepted));
+        assert_eq!(out.status.get(&never), Some(&SendStatus::TimedOut));
+        assert!(out.success.contains(&fast));
+        assert!(out.failed.contains_key(&never));
+    }
+}
@@ -82,6 +82,72 @@ impl<'de> Deserialize<'de> for NegentropyErrorCode {
     }
 }
 
+/// Machine-readable prefix of `OK` and `CLOSED` messages
+///
+/// <https://github.com/nostr-protocol/nips/blob/master/01.md>
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MachineReadablePrefix {
+    /// Duplicate
+    Duplicate,
+    /// POW
+    Pow,
+    /// Blocked
+    Blocked,
+    /// Rate limited
+    RateLimited,
+    /// Invalid
+    Invalid,
+    /// Error
+    Error,
+    /// Auth required
+    AuthRequired,
+    /// Restricted
+    Restricted,
+    /// Other
+    Other(String),
+}
+
+impl fmt::Display for MachineReadablePrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Duplicate => write!(f, "duplicate"),
+            Self::Pow => write!(f, "pow"),
+            Self::Blocked => write!(f, "blocked"),
+            Self::RateLimited => write!(f, "rate-limited"),
+            Self::Invalid => write!(f, "invalid"),
+            Self::Error => write!(f, "error"),
+            Self::AuthRequired => write!(f, "auth-required"),
+            Self::Restricted => write!(f, "restricted"),
+            Self::Other(p) => write!(f, "{p}"),
+        }
+    }
+}
+
+impl MachineReadablePrefix {
+    /// Parse machine-readable prefix of `OK` or `CLOSED` message
+    ///
+    /// Return `None` if the message doesn't start with a `<prefix>:`.
+    pub fn parse(message: &str) -> Option<Self> {
+        let (prefix, _) = message.split_once(':')?;
+
+        if prefix.is_empty() || prefix.contains(char::is_whitespace) {
+            return None;
+        }
+
+        match prefix {
+            "duplicate" => Some(Self::Duplicate),
+            "pow" => Some(Self::Pow),
+            "blocked" => Some(Self::Blocked),
+            "rate-limited" => Some(Self::RateLimited),
+            "invalid" => Some(Self::Invalid),
+            "error" => Some(Self::Error),
+            "auth-required" => Some(Self::AuthRequired),
+            "restricted" => Some(Self::Restricted),
+            p => Some(Self::Other(p.to_string())),
+        }
+    }
+}
+
 /// Messages sent by relays, received by clients
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum RelayMessage {
@@ -367,6 +433,49 @@ mod tests {
     use super::*;
     use crate::{Kind, PublicKey, Timestamp};
 
+    #[test]
+    fn test_machine_readable_prefix() {
+        assert_eq!(
+            MachineReadablePrefix::parse("duplicate: already have this event"),
+            Some(MachineReadablePrefix::Duplicate)
+        );
+        assert_eq!(
+            MachineReadablePrefix::parse("pow: difficulty 25>=24"),
+            Some(MachineReadablePrefix::Pow)
+        );
+        assert_eq!(
+            MachineReadablePrefix::parse("rate-limited: slow down there chief"),
+            Some(MachineReadablePrefix::RateLimited)
+        );
+        assert_eq!(
+            MachineReadablePrefix::parse("invalid: event creation date is too far off"),
+            Some(MachineReadablePrefix::Invalid)
+        );
+        assert_eq!(
+            MachineReadablePrefix::parse("blocked: you are banned from posting here"),
+            Some(MachineReadablePrefix::Blocked)
+        );
+        assert_eq!(
+            MachineReadablePrefix::parse(
+                "auth-required: we only accept events from registered users"
+            ),
+            Some(MachineReadablePrefix::AuthRequired)
+        );
+        assert_eq!(
+            MachineReadablePrefix::parse("custom: reason"),
+            Some(MachineReadablePrefix::Other(String::from("custom")))
+        );
+        assert_eq!(MachineReadablePrefix::parse(""), None);
+        assert_eq!(
+            MachineReadablePrefix::parse("relay not connected (status changed)"),
+            None
+        );
+        assert_eq!(
+            MachineReadablePrefix::parse("no prefix here: message"),
+            None
+        );
+    }
+
     #[test]
     fn test_handle_valid_notice() {
         let valid_notice_msg = r#"["NOTICE","Invalid event format!"]"#;
@@ -27,6 +27,8 @@ pub enum Error {
     InvalidFormat,
     /// Impossible to verify
     ImpossibleToVerify,
+    /// Name not found in `nostr.json`
+    NameNotFound(String),
     /// Reqwest error
     Reqwest(reqwest::Error),
     /// Error deserializing JSON data
@@ -43,6 +45,7 @@ impl fmt::Display for Error {
         match self {
             Self::InvalidFormat => write!(f, "invalid format"),
             Self::ImpossibleToVerify => write!(f, "impossible to verify"),
+            Self::NameNotFound(name) => write!(f, "name '{name}' not found in nostr.json"),
             Self::Reqwest(e) => write!(f, "{e}"),
             Self::Json(e) => write!(f, "impossible to deserialize NIP05 data: {e}"),
             Self::Keys(e) => write!(f, "{e}"),
@@ -68,21 +71,35 @@ impl From<key::Error> for Error {
     }
 }
 
+/// Compose the `.well-known/nostr.json` URL
+///
+/// An identifier without the name (i.e. `example.com`) is the root `_@example.com` identifier.
 fn compose_url(nip05: &str) -> Result<(String, &str), Error> {
-    let mut split = nip05.split('@');
-    if let (Some(name), Some(domain)) = (split.next(), split.next()) {
-        let url = format!("https://{domain}/.well-known/nostr.json?name={name}");
-        return Ok((url, name));
+    let (name, domain) = match nip05.split_once('@') {
+        Some((name, domain)) => (name, domain),
+        None => ("_", nip05),
+    };
+
+    if name.is_empty() || domain.is_empty() || domain.contains('@') {
+        return Err(Error::InvalidFormat);
     }
-    Err(Error::InvalidFormat)
+
+    let url = format!("https://{domain}/.well-known/nostr.json?name={name}");
+    Ok((url, name))
 }
 
-#[inline]
-fn get_key_from_json(json: &Value, name: &str) -> Option<PublicKey> {
-    json.get("names")
+/// Get the public key of the name
+///
+/// Return [`Error::NameNotFound`] if the name is missing and [`Error::Keys`] if the public key is invalid.
+fn get_key_from_json(json: &Value, name: &str) -> Result<PublicKey, Error> {
+    let value: &Value = json
+        .get("names")
         .and_then(|names| names.get(name))
-        .and_then(|value| value.as_str())
-        .and_then(|pubkey| PublicKey::from_hex(pubkey).ok())
+        .ok_or_else(|| Error::NameNotFound(name.to_string()))?;
+    let pubkey: &str = value
+        .as_str()
+        .ok_or(Error::Keys(key::Error::InvalidPublicKey))?;
+    Ok(PublicKey::from_hex(pubkey)?)
 }
 
 #[inline]
@@ -102,7 +119,7 @@ fn get_nip46_relays_from_json(json: Value, pk: PublicKey) -> Vec<Url> {
 }
 
 fn verify_json(public_key: &PublicKey, json: &Value, name: &str) -> bool {
-    if let Some(pubkey) = get_key_from_json(json, name) {
+    if let Ok(pubkey) = get_key_from_json(json, name) {
         if &pubkey == public_key {
             return true;
         }
@@ -156,10 +173,12 @@ where
     S: AsRef<str>,
 {
     let (json, name) = make_req(nip05.as_ref(), _proxy).await?;
+    get_profile_from_json(json, name)
+}
 
-    let public_key: PublicKey = get_key_from_json(&json, name).ok_or(Error::ImpossibleToVerify)?;
+fn get_profile_from_json(json: Value, name: &str) -> Result<Nip19Profile, Error> {
+    let public_key: PublicKey = get_key_from_json(&json, name)?;
     let relays: Vec<Url> = get_relays_from_json(json, public_key);
-
     Ok(Nip19Profile { public_key, relays })
 }
 
@@ -175,7 +194,7 @@ where
 {
     let (json, name) = make_req(nip05.as_ref(), _proxy).await?;
 
-    let public_key: PublicKey = get_key_from_json(&json, name).ok_or(Error::ImpossibleToVerify)?;
+    let public_key: PublicKey = get_key_from_json(&json, name)?;
     let relays: Vec<Url> = get_nip46_relays_from_json(json, public_key);
 
     Ok((public_key, relays))
@@ -214,4 +233,61 @@ mod tests {
                 .unwrap();
         assert!(!verify_json(&public_key, &json, "yuki"));
     }
+
+    #[test]
+    fn test_get_profile_from_json() {
+        // nostr.json
+        let json: &str = r#"{
+            "names": {
+              "_": "68d81165918100b7da43fc28f7d1fc12554466e1115886b9e7bb326f65ec4272"
+            },
+            "relays": {
+              "68d81165918100b7da43fc28f7d1fc12554466e1115886b9e7bb326f65ec4272": ["wss://relay.damus.io", "wss://nos.lol"]
+            }
+          }"#;
+        let json: Value = serde_json::from_str(json).unwrap();
+
+        // Root identifier
+        let (url, name) = compose_url("yukikishimoto.com").unwrap();
+        assert_eq!(
+            url,
+            "https://yukikishimoto.com/.well-known/nostr.json?name=_"
+        );
+
+        let profile = get_profile_from_json(json.clone(), name).unwrap();
+        assert_eq!(
+            profile.public_key,
+            PublicKey::from_hex("68d81165918100b7da43fc28f7d1fc12554466e1115886b9e7bb326f65ec4272")
+                .unwrap()
+        );
+        assert_eq!(
+            profile.relays,
+            vec![
+                Url::parse("wss://relay.damus.io").unwrap(),
+                Url::parse("wss://nos.lol").unwrap()
+            ]
+        );
+
+        // Missing entry
+        assert!(matches!(
+            get_profile_from_json(json, "alice"),
+            Err(Error::NameNotFound(name)) if name == "alice"
+        ));
+
+        // Invalid public key
+        let json: Value = serde_json::json!({ "names": { "bob": "invalid", "carol": 1 } });
+        assert!(matches!(
+            get_profile_from_json(json.clone(), "bob"),
+            Err(Error::Keys(..))
+        ));
+        assert!(matches!(
+            get_profile_from_json(json, "carol"),
+            Err(Error::Keys(key::Error::InvalidPublicKey))
+        ));
+
+        assert!(matches!(
+            compose_url("@example.com"),
+            Err(Error::InvalidFormat)
+        ));
+    }
 }